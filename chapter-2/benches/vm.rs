@@ -0,0 +1,69 @@
+// in vm.rs - providing a benchmark of the bytecode VM against the tree walking evaluator
+// run with: cargo bench, or cargo bench --features jit to include the native code on x86-64 Linux
+
+use chapter_2::parsemaths::ast;
+use chapter_2::parsemaths::bytecode;
+use chapter_2::parsemaths::environment::Environment;
+use chapter_2::parsemaths::parser::Parser;
+use chapter_2::parsemaths::value::Value;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const FORMULA: &str = "x^3 - 2*x^2 + 3*sin(x) * cos(x) - sqrt(x) / (1 + x) + (x > 2 ? x : -x)";
+const INPUTS: usize = 200_000;
+
+/* Runs f for every input, returning the total time and the sum of the results */
+fn time(mut f: impl FnMut(f64) -> f64) -> (Duration, f64) {
+    let start = Instant::now();
+    let mut total = 0.0;
+    for i in 0..INPUTS {
+        total += f(black_box(i as f64 / 1000.0));
+    }
+    (start.elapsed(), total)
+}
+
+fn main() {
+    let ast = Parser::new(FORMULA).unwrap().parse().unwrap();
+    let mut env = Environment::new();
+
+    // the tree walker consumes the tree, so every evaluation needs a fresh clone
+    let (walker, walker_total) = time(|x| {
+        env.set("x", Value::Real(x));
+        ast::eval(ast.clone(), &mut env).unwrap().to_f64()
+    });
+
+    let program = bytecode::compile(&ast, &env);
+    let mut slots = program.bind(&env);
+    let slot = program.slot("x").unwrap();
+    let (vm, vm_total) = time(|x| {
+        slots[slot] = Some(Value::Real(x));
+        program.run(&mut slots, &env).unwrap().to_f64()
+    });
+
+    assert_eq!(walker_total, vm_total, "the VM must give the same results");
+    let per_eval = |elapsed: Duration| elapsed.as_nanos() as f64 / INPUTS as f64;
+    println!("{} for {} values of x", FORMULA, INPUTS);
+    println!("tree walker: {:>8.1} ns/eval", per_eval(walker));
+    println!("bytecode VM: {:>8.1} ns/eval", per_eval(vm));
+    println!(
+        "speedup:     {:>8.2}x",
+        walker.as_secs_f64() / vm.as_secs_f64()
+    );
+
+    // the formula has no errors in the IEEE policy, so the native code gives the same values
+    #[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+    {
+        let f = chapter_2::parsemaths::jit::compile(&ast, &["x"]);
+        assert!(f.is_native());
+        let (jit, jit_total) = time(|x| f.call(&[x]));
+        assert_eq!(
+            walker_total, jit_total,
+            "the JIT must give the same results"
+        );
+        println!("native JIT:  {:>8.1} ns/eval", per_eval(jit));
+        println!(
+            "speedup:     {:>8.2}x",
+            walker.as_secs_f64() / jit.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,631 @@
+// in editor.rs - providing code for the line editor used by the REPL
+
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::PathBuf;
+
+// most lines kept in the history file
+const HISTORY_LIMIT: usize = 1000;
+
+/*
+Reads the lines typed into the REPL
+On a Linux terminal the line is edited in place: the arrow keys move through the line and step
+through the history, Tab completes a name and Ctrl-R searches the history for earlier lines
+The history is kept between sessions in ~/.parsemaths_history
+Input that is not a terminal e.g. a pipe or a file is read a line at a time as it is, and is not
+added to the history
+*/
+pub struct Editor {
+    history: Vec<String>,
+    // where the history is saved - None if there is no home directory
+    path: Option<PathBuf>,
+    terminal: bool,
+}
+
+/* A key press, decoded from the bytes the terminal sends for it */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Key {
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    Cancel,      // Ctrl-C or Ctrl-G
+    EndOfInput,  // Ctrl-D
+    KillToEnd,   // Ctrl-K
+    KillToStart, // Ctrl-U
+    Search,      // Ctrl-R
+    Ignored,     // any other control key or escape sequence
+}
+
+/* The line being edited, held as characters so the cursor moves over whole characters e.g. π */
+#[derive(Debug, Default)]
+struct Line {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /* Replaces the line, leaving the cursor at the end */
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.chars.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+}
+
+impl Editor {
+    /* Creates the editor, reading the history saved by earlier sessions */
+    pub fn new() -> Self {
+        let path = env::var_os("HOME").map(|home| PathBuf::from(home).join(".parsemaths_history"));
+        let mut history: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|text| text.lines().map(String::from).collect())
+            .unwrap_or_default();
+        // the file is only appended to while the REPL runs, so it is cut back to size here
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+            if let Some(path) = &path {
+                let _ = fs::write(path, history.join("\n") + "\n");
+            }
+        }
+        Editor {
+            history,
+            path,
+            terminal: cfg!(target_os = "linux") && io::stdin().is_terminal(),
+        }
+    }
+
+    /*
+    Reads the next line without its line ending, showing the prompt on a terminal
+    words are the names Tab completes from
+    Returns None at the end of the input e.g. Ctrl-D on an empty line
+    */
+    pub fn read_line(&mut self, prompt: &str, words: &[String]) -> io::Result<Option<String>> {
+        if self.terminal {
+            if let Some(raw) = RawMode::enable() {
+                let line = self.edit(&mut io::stdin().lock(), &mut io::stdout(), prompt, words);
+                drop(raw);
+                if let Ok(Some(line)) = &line {
+                    self.add_history(line);
+                }
+                return line;
+            }
+        }
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    /*
+    Adds a line to the history and appends it to the history file
+    Blank lines and repeats of the line before are left out
+    The history is only a convenience, so a file that cannot be written is not an error
+    */
+    fn add_history(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() || self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if let Some(path) = &self.path {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    /* Edits a line read key by key from input, drawing it on output */
+    fn edit(
+        &self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+        prompt: &str,
+        words: &[String],
+    ) -> io::Result<Option<String>> {
+        let mut line = Line::default();
+        // position in the history while stepping through it, the line being typed is at the end
+        let mut entry = self.history.len();
+        // the line being typed, kept while an earlier line is shown
+        let mut draft = String::new();
+        // a key that ended a search, handled as if it had just been pressed
+        let mut pending = None;
+        refresh(output, prompt, &line)?;
+        loop {
+            let key = match pending.take() {
+                Some(key) => key,
+                None => match read_key(input)? {
+                    Some(key) => key,
+                    // the end of the input part way through a line still submits it
+                    None => return Ok((!line.chars.is_empty()).then(|| line.text())),
+                },
+            };
+            match key {
+                Key::Enter => {
+                    write!(output, "\r\n")?;
+                    return Ok(Some(line.text()));
+                }
+                Key::EndOfInput if line.chars.is_empty() => {
+                    write!(output, "\r\n")?;
+                    return Ok(None);
+                }
+                Key::Cancel => {
+                    write!(output, "^C\r\n")?;
+                    line = Line::default();
+                    entry = self.history.len();
+                }
+                Key::Char(c) => line.insert(c.encode_utf8(&mut [0; 4])),
+                Key::Backspace if line.cursor > 0 => {
+                    line.cursor -= 1;
+                    line.chars.remove(line.cursor);
+                }
+                Key::Delete | Key::EndOfInput if line.cursor < line.chars.len() => {
+                    line.chars.remove(line.cursor);
+                }
+                Key::Left => line.cursor = line.cursor.saturating_sub(1),
+                Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                Key::Home => line.cursor = 0,
+                Key::End => line.cursor = line.chars.len(),
+                Key::KillToEnd => line.chars.truncate(line.cursor),
+                Key::KillToStart => {
+                    line.chars.drain(..line.cursor);
+                    line.cursor = 0;
+                }
+                Key::Up | Key::Down => {
+                    if entry == self.history.len() {
+                        draft = line.text();
+                    }
+                    entry = match key {
+                        Key::Up => entry.saturating_sub(1),
+                        _ => (entry + 1).min(self.history.len()),
+                    };
+                    line.set(self.history.get(entry).unwrap_or(&draft));
+                }
+                Key::Tab => {
+                    let names = complete(&mut line, words);
+                    if !names.is_empty() {
+                        write!(output, "\r\n{}\r\n", names.join("  "))?;
+                    }
+                }
+                Key::Search => {
+                    let (found, key) = self.search(input, output)?;
+                    if let Some(found) = found {
+                        line.set(&found);
+                    }
+                    match key {
+                        Some(key) => pending = Some(key),
+                        None => return Ok((!line.chars.is_empty()).then(|| line.text())),
+                    }
+                }
+                _ => {}
+            }
+            refresh(output, prompt, &line)?;
+        }
+    }
+
+    /*
+    Ctrl-R: shows the latest line of the history containing the text typed so far
+    Ctrl-R again moves on to an earlier match, Ctrl-C or Ctrl-G gives up the search and any other
+    key takes the match as the line, and is then handled as usual e.g. Enter submits it
+    Returns the match and the key that ended the search - None if the input ended
+    */
+    fn search(
+        &self,
+        input: &mut impl Read,
+        output: &mut impl Write,
+    ) -> io::Result<(Option<String>, Option<Key>)> {
+        let mut query = String::new();
+        let mut found: Option<usize> = None;
+        loop {
+            let text = found.map_or("", |i| self.history[i].as_str());
+            write!(output, "\r(reverse-i-search)`{}': {}\x1b[0K", query, text)?;
+            output.flush()?;
+            match read_key(input)? {
+                // the current match is kept if it still contains the longer text
+                Some(Key::Char(c)) => {
+                    query.push(c);
+                    found = self.find(&query, found.map_or(self.history.len(), |i| i + 1));
+                }
+                Some(Key::Backspace) => {
+                    query.pop();
+                    found = self.find(&query, self.history.len());
+                }
+                Some(Key::Search) => {
+                    found = self
+                        .find(&query, found.unwrap_or(self.history.len()))
+                        .or(found);
+                }
+                Some(Key::Cancel) => return Ok((None, Some(Key::Ignored))),
+                key => return Ok((found.map(|i| self.history[i].clone()), key)),
+            }
+        }
+    }
+
+    /* Index of the latest line before the entry before that contains query */
+    fn find(&self, query: &str, before: usize) -> Option<usize> {
+        if query.is_empty() {
+            return None;
+        }
+        self.history[..before]
+            .iter()
+            .rposition(|line| line.contains(query))
+    }
+}
+
+/* Redraws the prompt and the line, then writes the part before the cursor again to place it */
+fn refresh(output: &mut impl Write, prompt: &str, line: &Line) -> io::Result<()> {
+    let before: String = line.chars[..line.cursor].iter().collect();
+    write!(
+        output,
+        "\r{}{}\x1b[0K\r{}{}",
+        prompt,
+        line.text(),
+        prompt,
+        before
+    )?;
+    output.flush()
+}
+
+/*
+Completes the name that ends at the cursor from words
+A single match is written out in full and several are completed as far as they agree
+Returns the matches to list when they are ambiguous and nothing could be added, otherwise nothing
+*/
+fn complete(line: &mut Line, words: &[String]) -> Vec<String> {
+    let start = line.chars[..line.cursor]
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |i| i + 1);
+    let prefix: String = line.chars[start..line.cursor].iter().collect();
+    if prefix.is_empty() {
+        return Vec::new();
+    }
+    let mut matches: Vec<String> = words
+        .iter()
+        .filter(|word| word.starts_with(&prefix))
+        .cloned()
+        .collect();
+    matches.sort();
+    matches.dedup();
+    let Some(first) = matches.first() else {
+        return Vec::new();
+    };
+    // the longest prefix every match shares
+    let common = matches.iter().fold(first.as_str(), |common, word| {
+        let len = common
+            .char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(common.len().min(word.len()), |((i, _), _)| i);
+        &common[..len]
+    });
+    if common.len() > prefix.len() {
+        line.insert(&common[prefix.len()..]);
+        return Vec::new();
+    }
+    match matches.len() {
+        1 => Vec::new(),
+        _ => matches,
+    }
+}
+
+/* Reads a byte, None at the end of the input */
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/* Reads the next key press, None at the end of the input */
+fn read_key(input: &mut impl Read) -> io::Result<Option<Key>> {
+    let Some(byte) = read_byte(input)? else {
+        return Ok(None);
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        // the emacs keys readline also accepts: Ctrl-A, E, B, F, P and N
+        0x01 => Key::Home,
+        0x05 => Key::End,
+        0x02 => Key::Left,
+        0x06 => Key::Right,
+        0x10 => Key::Up,
+        0x0e => Key::Down,
+        0x03 | 0x07 => Key::Cancel,
+        0x04 => Key::EndOfInput,
+        0x0b => Key::KillToEnd,
+        0x15 => Key::KillToStart,
+        0x12 => Key::Search,
+        0x1b => read_escape(input)?,
+        byte if byte < 0x20 => Key::Ignored,
+        byte => read_char(byte, input)?,
+    };
+    Ok(Some(key))
+}
+
+/*
+Reads the rest of an escape sequence e.g. ESC [ A for the up arrow
+Terminals send ESC [ or ESC O, then any number parameters, then a final character
+*/
+fn read_escape(input: &mut impl Read) -> io::Result<Key> {
+    let introducer = read_byte(input)?;
+    if !matches!(introducer, Some(b'[' | b'O')) {
+        return Ok(Key::Ignored);
+    }
+    let mut parameter = String::new();
+    loop {
+        let Some(byte) = read_byte(input)? else {
+            return Ok(Key::Ignored);
+        };
+        // Home, End and Delete are also sent as a number and '~' e.g. ESC [ 3 ~
+        return Ok(match byte {
+            b'0'..=b'9' | b';' => {
+                parameter.push(byte as char);
+                continue;
+            }
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            b'~' => match parameter.as_str() {
+                "1" | "7" => Key::Home,
+                "4" | "8" => Key::End,
+                "3" => Key::Delete,
+                _ => Key::Ignored,
+            },
+            _ => Key::Ignored,
+        });
+    }
+}
+
+/* Reads the rest of a character encoded in UTF-8 that starts with first e.g. the 2 bytes of π */
+fn read_char(first: u8, input: &mut impl Read) -> io::Result<Key> {
+    let len = match first {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => break,
+        }
+    }
+    Ok(match std::str::from_utf8(&bytes) {
+        Ok(text) => text.chars().next().map_or(Key::Ignored, Key::Char),
+        Err(_) => Key::Ignored,
+    })
+}
+
+#[cfg(target_os = "linux")]
+use self::linux::RawMode;
+
+/* Raw mode is only supported on Linux, elsewhere lines are read as they are */
+#[cfg(not(target_os = "linux"))]
+struct RawMode;
+
+#[cfg(not(target_os = "linux"))]
+impl RawMode {
+    fn enable() -> Option<RawMode> {
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::ffi::c_int;
+
+    // the layout of struct termios and the flags used, from <termios.h>
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Termios {
+        iflag: u32,
+        oflag: u32,
+        cflag: u32,
+        lflag: u32,
+        line: u8,
+        cc: [u8; 32],
+        ispeed: u32,
+        ospeed: u32,
+    }
+
+    const BRKINT: u32 = 0o2;
+    const INPCK: u32 = 0o20;
+    const ISTRIP: u32 = 0o40;
+    const ICRNL: u32 = 0o400;
+    const IXON: u32 = 0o2000;
+    const OPOST: u32 = 0o1;
+    const CS8: u32 = 0o60;
+    const ISIG: u32 = 0o1;
+    const ICANON: u32 = 0o2;
+    const ECHO: u32 = 0o10;
+    const IEXTEN: u32 = 0o100000;
+    const VTIME: usize = 5;
+    const VMIN: usize = 6;
+    const TCSAFLUSH: c_int = 2;
+    const STDIN: c_int = 0;
+
+    extern "C" {
+        fn tcgetattr(fd: c_int, termios: *mut Termios) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const Termios) -> c_int;
+    }
+
+    /*
+    Puts the terminal in raw mode, where every key press is read as it is made and nothing is
+    echoed, so the editor can draw the line itself
+    Ctrl-C is read as a key too, rather than ending the program
+    The terminal is put back as it was when it is dropped
+    */
+    pub struct RawMode {
+        original: Termios,
+    }
+
+    impl RawMode {
+        /* None if stdin is not a terminal or its settings cannot be changed */
+        pub fn enable() -> Option<RawMode> {
+            // SAFETY: tcgetattr fills in the termios it is given, which is only read if it succeeds
+            let original = unsafe {
+                let mut termios = std::mem::zeroed::<Termios>();
+                if tcgetattr(STDIN, &mut termios) != 0 {
+                    return None;
+                }
+                termios
+            };
+            let mut raw = original;
+            raw.iflag &= !(BRKINT | ICRNL | INPCK | ISTRIP | IXON);
+            raw.oflag &= !OPOST;
+            raw.cflag |= CS8;
+            raw.lflag &= !(ECHO | ICANON | IEXTEN | ISIG);
+            // each read waits for at least one byte, with no time limit
+            raw.cc[VMIN] = 1;
+            raw.cc[VTIME] = 0;
+            // SAFETY: raw is a complete termios, copied from the one the terminal gave
+            (unsafe { tcsetattr(STDIN, TCSAFLUSH, &raw) } == 0).then_some(RawMode { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: original is the termios read from the terminal by RawMode::enable
+            unsafe {
+                tcsetattr(STDIN, TCSAFLUSH, &self.original);
+            }
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor(history: &[&str]) -> Editor {
+        Editor {
+            history: history.iter().map(|line| line.to_string()).collect(),
+            path: None,
+            terminal: false,
+        }
+    }
+
+    /* The line the editor gives for the bytes typed */
+    fn typed(editor: &Editor, keys: &str, words: &[&str]) -> Option<String> {
+        let words: Vec<String> = words.iter().map(|word| word.to_string()).collect();
+        editor
+            .edit(&mut keys.as_bytes(), &mut Vec::new(), "> ", &words)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_read_key() {
+        let keys: Vec<Key> = std::iter::from_fn({
+            let mut input = "a\x1b[D\x1bOH\x1b[3~π\x7f\r".as_bytes();
+            move || read_key(&mut input).unwrap()
+        })
+        .collect();
+        assert_eq!(
+            keys,
+            vec![
+                Key::Char('a'),
+                Key::Left,
+                Key::Home,
+                Key::Delete,
+                Key::Char('π'),
+                Key::Backspace,
+                Key::Enter
+            ]
+        );
+    }
+
+    #[test]
+    fn test_editing() {
+        let editor = editor(&[]);
+        // 2 * 4, then left twice, 3 inserted and the 4 deleted
+        assert_eq!(
+            typed(&editor, "2*4\x1b[D\x1b[D3\x1b[C\x1b[3~\r", &[]),
+            Some("23*".into())
+        );
+        assert_eq!(
+            typed(&editor, "1+2\x01-\x05\x7f3\r", &[]),
+            Some("-1+3".into())
+        );
+        assert_eq!(typed(&editor, "junk\x15quit\r", &[]), Some("quit".into()));
+        assert_eq!(typed(&editor, "x\x03y\r", &[]), Some("y".into()));
+        // Ctrl-D ends the input on an empty line, as does the end of the bytes
+        assert_eq!(typed(&editor, "\x04", &[]), None);
+        assert_eq!(typed(&editor, "", &[]), None);
+    }
+
+    #[test]
+    fn test_history() {
+        let editor = editor(&["1 + 1", "let x = 2", "x * 3"]);
+        assert_eq!(typed(&editor, "\x1b[A\r", &[]), Some("x * 3".into()));
+        assert_eq!(
+            typed(&editor, "\x1b[A\x1b[A\x1b[A\x1b[A\r", &[]),
+            Some("1 + 1".into())
+        );
+        // stepping back down past the history returns to the line being typed
+        assert_eq!(typed(&editor, "7\x1b[A\x1b[B\r", &[]), Some("7".into()));
+        // Ctrl-R finds the latest line containing the text, and again for an earlier one
+        assert_eq!(typed(&editor, "\x12x\r", &[]), Some("x * 3".into()));
+        assert_eq!(typed(&editor, "\x12x\x12\r", &[]), Some("let x = 2".into()));
+        assert_eq!(
+            typed(&editor, "\x12+\x05 + 1\r", &[]),
+            Some("1 + 1 + 1".into())
+        );
+        assert_eq!(typed(&editor, "5\x12x\x03\r", &[]), Some("5".into()));
+    }
+
+    #[test]
+    fn test_completion() {
+        let words = ["sin", "sinh", "sqrt", "speed", "pi"];
+        let editor = editor(&[]);
+        assert_eq!(
+            typed(&editor, "2 * sq\t(2)\r", &words),
+            Some("2 * sqrt(2)".into())
+        );
+        assert_eq!(typed(&editor, "si\t\r", &words), Some("sin".into()));
+        assert_eq!(typed(&editor, "x\t\r", &words), Some("x".into()));
+        // names that share no more than what is typed are listed instead
+        let mut line = Line::default();
+        line.set("s");
+        let listed = complete(&mut line, &words.map(String::from));
+        assert_eq!(listed, ["sin", "sinh", "speed", "sqrt"]);
+        assert_eq!(line.text(), "s");
+        line.set("sin");
+        assert_eq!(
+            complete(&mut line, &words.map(String::from)),
+            ["sin", "sinh"]
+        );
+    }
+}
@@ -0,0 +1,3 @@
+// in lib.rs - providing the expression evaluator as a library, used by the REPL and the benchmarks
+
+pub mod parsemaths;
@@ -1,45 +1,841 @@
 extern crate core;
 
-use std::io;
+mod editor;
 
-mod parsemaths;
-use parsemaths::ast;
-use parsemaths::parser::{ParseError, Parser};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal};
+use std::process::ExitCode;
 
-fn evaluate(expr: String) -> Result<f64, ParseError> {
-    // remove whitespace characters and collect into a collection of type String
-    let expr = expr.split_whitespace().collect::<String>();
-    let mut maths_parser = Parser::new(&expr)?;
-    let ast = maths_parser.parse()?;
-    println!("The generated AST is {:?}", ast);
+use chapter_2::parsemaths::ast;
+use chapter_2::parsemaths::derivative;
+use chapter_2::parsemaths::environment::{Environment, EvalPolicy, IntWidth, NumberMode};
+use chapter_2::parsemaths::explain;
+use chapter_2::parsemaths::functions;
+use chapter_2::parsemaths::json::Json;
+use chapter_2::parsemaths::parser::{Negation, ParseError, Parser, Products, Syntax};
+use chapter_2::parsemaths::render;
+use chapter_2::parsemaths::simplify;
+use chapter_2::parsemaths::value::{ComplexFormat, DisplayOptions, ExactFormat, Value};
+use chapter_2::parsemaths::CalcError;
+use editor::Editor;
 
-    Ok(ast::eval(ast)?)
+const USAGE: &str = "\
+Usage: chapter-2 [options] [expression ...]
+Evaluates each expression given, or each line of a file or of piped input, printing one result per
+line. Later lines can use the variables of earlier ones e.g. chapter-2 'let r = 2' 'pi * r^2'
+With no expressions or file and input from a terminal, the interactive calculator is started.
+
+Options:
+  -f, --file <path>   evaluate each line of the file, or of stdin if the path is -
+  -k, --keep-going    carry on after an error rather than stopping at the first
+  --json              write one JSON object per line instead of text, errors included
+  --ast=tree          draw the tree each expression is parsed to instead of evaluating it
+  --ast=dot           the same as a Graphviz graph e.g. chapter-2 --ast=dot '1+2*3' | dot -Tsvg
+  --explain           show each step of working out each expression, ending with its value
+  -h, --help          show this help
+  --                  treat every argument after it as an expression
+
+Blank lines and lines starting with '#' are skipped, and ':' commands work as in the calculator.
+Exit status: 0 if every line succeeded, 1 if any failed, 2 for a bad option or unreadable input.
+
+Each JSON object has the line number, the input and whether it succeeded (ok). An expression adds
+the expression in its canonical form, as given by :simplify (null if it could not be parsed), and
+either the value as text, its type and the number (null if it is not a plain number), or an error
+with its kind, message and span (the byte offsets of the mistake, null for errors in evaluating).
+A ':' command adds the message it gives, or an error of kind command.";
+
+/*
+What evaluating a line gave: the tree it was parsed to, if it could be, and the result
+When it was explained, steps holds the expression after each step of working it out
+*/
+struct Evaluation {
+    ast: Option<ast::Node>,
+    steps: Vec<String>,
+    result: Result<Value, CalcError>,
+}
+
+/* Parses and evaluates expr, explaining each step with the values shown as in explain */
+fn evaluate(
+    expr: &str,
+    env: &mut Environment,
+    syntax: Syntax,
+    explain: Option<&DisplayOptions>,
+) -> Evaluation {
+    // whitespace is skipped by the tokenizer, it is still needed to separate `let` from the name
+    let ast = match parse(expr, syntax) {
+        Ok(ast) => ast,
+        Err(e) => {
+            return Evaluation {
+                ast: None,
+                steps: Vec::new(),
+                result: Err(e.into()),
+            }
+        }
+    };
+    let (steps, result) = match explain {
+        Some(options) => {
+            let explanation = explain::explain(ast.clone(), env, options);
+            (explanation.steps, explanation.result)
+        }
+        None => (Vec::new(), ast::eval(ast.clone(), env)),
+    };
+    let result = result.map_err(CalcError::from);
+    // the last successfully computed value is always available as `ans`
+    if let Ok(val) = &result {
+        env.set("ans", val.clone());
+    }
+    Evaluation {
+        ast: Some(ast),
+        steps,
+        result,
+    }
+}
+
+/*
+The steps of an explanation one per line, each after the first starting with '=' e.g.
+  2 * 3 + 4
+= 6 + 4
+= 10
+*/
+fn steps_text(steps: &[String]) -> String {
+    let lines: Vec<String> = steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{} {}", if i == 0 { ' ' } else { '=' }, step))
+        .collect();
+    lines.join("\n")
+}
+
+/* The evaluation of the input on the given line as a JSON object, see USAGE */
+fn evaluation_json(
+    line: usize,
+    input: &str,
+    evaluation: &Evaluation,
+    options: &DisplayOptions,
+) -> Json {
+    // the canonical form, so equivalent inputs e.g. b + a and a + b give the same expression
+    let expression = evaluation
+        .ast
+        .as_ref()
+        .map(|ast| simplify::simplify(ast.clone()).to_string());
+    match &evaluation.result {
+        Ok(val) => {
+            let (kind, number) = match val {
+                Value::Real(x) => ("real", Some(*x)),
+                Value::Rational(_) => ("exact", Some(val.to_f64())),
+                Value::Integer(n) => ("integer", Some(*n as f64)),
+                Value::Complex(_) => ("complex", None),
+                Value::Bool(_) => ("boolean", None),
+                Value::Quantity(_) => ("quantity", None),
+            };
+            Json::object([
+                ("line", line.into()),
+                ("input", input.into()),
+                ("ok", true.into()),
+                ("expression", expression.into()),
+                ("value", val.format(options).into()),
+                ("type", kind.into()),
+                ("number", number.into()),
+            ])
+        }
+        Err(e) => {
+            let span = e
+                .span()
+                .map(|span| Json::object([("start", span.start.into()), ("end", span.end.into())]));
+            Json::object([
+                ("line", line.into()),
+                ("input", input.into()),
+                ("ok", false.into()),
+                ("expression", expression.into()),
+                (
+                    "error",
+                    Json::object([
+                        ("kind", e.kind().into()),
+                        ("message", e.to_string().into()),
+                        ("span", span.into()),
+                    ]),
+                ),
+            ])
+        }
+    }
+}
+
+/* The result of the ':' command on the given line as a JSON object, see USAGE */
+fn command_json(line: usize, input: &str, result: &Result<String, String>) -> Json {
+    let (line, input) = (("line", line.into()), ("input", input.into()));
+    match result {
+        Ok(message) => Json::object([
+            line,
+            input,
+            ("ok", true.into()),
+            ("message", message.as_str().into()),
+        ]),
+        Err(e) => Json::object([
+            line,
+            input,
+            ("ok", false.into()),
+            (
+                "error",
+                Json::object([
+                    ("kind", "command".into()),
+                    ("message", e.as_str().into()),
+                    ("span", Json::Null),
+                ]),
+            ),
+        ]),
+    }
+}
+
+/* Parses an expression for a REPL command */
+fn parse(expr: &str, syntax: Syntax) -> Result<ast::Node, ParseError> {
+    Parser::with_syntax(expr, syntax)?.parse()
+}
+
+/* How --ast and :ast draw a tree, see render */
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AstFormat {
+    Tree,
+    Dot,
+}
+
+impl AstFormat {
+    fn from_name(name: &str) -> Option<AstFormat> {
+        match name {
+            "tree" => Some(AstFormat::Tree),
+            "dot" => Some(AstFormat::Dot),
+            _ => None,
+        }
+    }
+}
+
+/* Parses expr and draws its tree, with where in expr each node was read from */
+fn draw(expr: &str, syntax: Syntax, format: AstFormat) -> Result<String, ParseError> {
+    let mut parser = Parser::with_syntax(expr, syntax)?;
+    let ast = parser.parse()?;
+    let spans = parser.span_tree(&ast);
+    Ok(match format {
+        AstFormat::Tree => render::tree(&ast, spans.as_ref(), expr),
+        AstFormat::Dot => render::dot(&ast, spans.as_ref(), expr),
+    })
+}
+
+/* Parses expr and differentiates it with respect to var */
+fn derivative_of(expr: &str, var: &str, syntax: Syntax) -> Result<ast::Node, Box<dyn Error>> {
+    let ast = parse(expr, syntax)?;
+    Ok(derivative::differentiate(&ast, var)?)
+}
+
+/*
+Describes an error in evaluating input for the user
+Syntax errors point at the offending characters in the input, and the input is parsed again to
+find every error rather than only the first
+*/
+fn report(input: &str, error: &CalcError, syntax: Syntax) -> String {
+    match error {
+        CalcError::Parse(_) => {
+            let mut lines = vec![input.to_string()];
+            for e in Parser::parse_recovering(input, syntax).errors {
+                lines.push(e.marker(input));
+                lines.push(e.to_string());
+            }
+            lines.join("\n")
+        }
+        CalcError::Eval(e) => format!("Error in evaluating expression: {}", e),
+    }
+}
+
+/* Names Tab completes in the REPL: the built-in functions and constants and the variables */
+fn completions(env: &Environment) -> Vec<String> {
+    let functions = functions::BUILTINS.iter().map(|builtin| builtin.name);
+    let constants = functions::CONSTANTS.iter().map(|(name, _)| *name);
+    functions
+        .chain(constants)
+        .chain(env.names())
+        .map(String::from)
+        .collect()
+}
+
+/*
+Handles a REPL command, i.e. a line starting with ':'
+Returns the message to show the user, or the error if the command is unknown or failed
+*/
+fn run_command(
+    command: &str,
+    env: &mut Environment,
+    options: &mut DisplayOptions,
+    syntax: &mut Syntax,
+    explain: &mut bool,
+) -> Result<String, String> {
+    // :d/dx <expr> differentiates <expr> with respect to x, any variable name can follow d/d
+    if let Some(rest) = command.strip_prefix(":d/d") {
+        let (var, expr) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        return match derivative_of(expr, var, *syntax) {
+            Ok(derivative) => Ok(format!("d/d{} = {}", var, derivative)),
+            Err(e) => Err(format!("Error in differentiating expression: {}", e)),
+        };
+    }
+    if let Some(expr) = command.strip_prefix(":simplify ") {
+        return match parse(expr, *syntax) {
+            Ok(ast) => Ok(format!("Simplified: {}", simplify::simplify(ast))),
+            Err(e) => Err(format!("Error in simplifying expression: {}", e)),
+        };
+    }
+    // :ast tree|dot <expr> draws the tree the expression is parsed to
+    if let Some(rest) = command.strip_prefix(":ast ") {
+        let (name, expr) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        return match AstFormat::from_name(name) {
+            Some(format) => match draw(expr, *syntax, format) {
+                Ok(drawing) => Ok(drawing),
+                Err(e) => Err(format!("Error in drawing expression: {}", e)),
+            },
+            None => Err(format!("'{}' is not a drawing, use tree or dot", name)),
+        };
+    }
+    // :equiv <expr> ; <expr> checks whether the two formulas have the same canonical form
+    if let Some(formulas) = command.strip_prefix(":equiv ") {
+        let (first, second) = formulas.split_once(';').unwrap_or((formulas, ""));
+        return match (parse(first, *syntax), parse(second, *syntax)) {
+            (Ok(a), Ok(b)) if simplify::equivalent(&a, &b) => {
+                Ok("The formulas are equivalent".into())
+            }
+            (Ok(a), Ok(b)) => Ok(format!(
+                "Could not show the formulas are equivalent: {} and {}",
+                simplify::simplify(a),
+                simplify::simplify(b)
+            )),
+            (Err(e), _) | (_, Err(e)) => Err(format!("Error in comparing expressions: {}", e)),
+        };
+    }
+    let words: Vec<&str> = command.split_whitespace().collect();
+    let message = match words.as_slice() {
+        [":policy", "strict"] => {
+            env.set_policy(EvalPolicy::Strict);
+            "1/0, sqrt(-1) and overflows are now reported as errors".into()
+        }
+        [":policy", "ieee"] => {
+            env.set_policy(EvalPolicy::Ieee);
+            "1/0, sqrt(-1) and overflows now give inf or NaN".into()
+        }
+        [":policy"] => format!("Current policy is {:?}", env.policy()),
+        [":mode", "exact"] => {
+            env.set_mode(NumberMode::Exact);
+            "Numbers are now exact fractions, so 0.1+0.2 is exactly 3/10".into()
+        }
+        [":mode", "complex"] => {
+            env.set_mode(NumberMode::Complex);
+            "Operations with no real result now give complex numbers e.g. sqrt(-1) = i".into()
+        }
+        [":mode", "real"] => {
+            env.set_mode(NumberMode::Real);
+            "Numbers are now floating point".into()
+        }
+        [":mode", "int" | "i64"] => {
+            env.set_mode(NumberMode::Integer(IntWidth::I64));
+            "Numbers are now 64-bit signed integers, results that do not fit are errors".into()
+        }
+        [":mode", "u64"] => {
+            env.set_mode(NumberMode::Integer(IntWidth::U64));
+            "Numbers are now 64-bit unsigned integers, results that do not fit are errors".into()
+        }
+        [":mode"] => format!("Current number mode is {:?}", env.mode()),
+        [":syntax", "strict"] => {
+            syntax.products = Products::Strict;
+            "Every product now needs an explicit '*', so 2x is an error".into()
+        }
+        [":syntax", "implicit"] => {
+            syntax.products = Products::Implicit;
+            "Products can now be written without '*' e.g. 2x, 2(3) or 2sin(x)".into()
+        }
+        [":syntax", "power-first"] => {
+            syntax.negation = Negation::PowerFirst;
+            "Powers now bind tighter than a leading minus, so -2^2 = -4".into()
+        }
+        [":syntax", "minus-first"] => {
+            syntax.negation = Negation::MinusFirst;
+            "A leading minus now binds tighter than powers, so -2^2 = 4".into()
+        }
+        [":syntax"] => format!(
+            "Current syntax is {:?} products with {:?} negation",
+            syntax.products, syntax.negation
+        ),
+        [":format", "fraction"] => {
+            options.exact = ExactFormat::Fraction;
+            "Exact results are now shown as fractions".into()
+        }
+        [":format", "polar"] => {
+            options.complex = ComplexFormat::Polar;
+            "Complex results are now shown as modulus∠argument (radians)".into()
+        }
+        [":format", "rect"] => {
+            options.complex = ComplexFormat::Rectangular;
+            "Complex results are now shown as a + bi".into()
+        }
+        [":format", "decimal", digits] => match digits.parse::<usize>() {
+            Ok(digits) => {
+                options.exact = ExactFormat::Decimal(digits);
+                format!("Exact results are now shown with {} decimal places", digits)
+            }
+            Err(_) => return Err(format!("'{}' is not a number of decimal places", digits)),
+        },
+        [":explain", "on"] => {
+            *explain = true;
+            "Each step of working out an expression is now shown e.g. 2*3+4 = 6+4 = 10".into()
+        }
+        [":explain", "off"] => {
+            *explain = false;
+            "Only the result of an expression is now shown".into()
+        }
+        [":explain"] => format!("Explaining is {}", if *explain { "on" } else { "off" }),
+        [":base", radix] => match radix.parse::<u32>() {
+            Ok(radix) if (2..=36).contains(&radix) => {
+                options.radix = radix;
+                format!("Integers are now shown in base {}", radix)
+            }
+            _ => return Err(format!("'{}' is not a base from 2 to 36", radix)),
+        },
+        _ => {
+            return Err(format!(
+                "Unknown command '{}'. Available commands: :policy [strict|ieee], \
+                 :mode [real|exact|complex|int|u64], :syntax [implicit|strict|power-first|minus-first], :format [fraction|decimal <digits>|polar|rect], \
+                 :base <2-36>, :explain [on|off], :d/dx <expression>, :simplify <expression>, \
+                 :equiv <expression> ; <expression>, :ast [tree|dot] <expression>",
+                command
+            ))
+        }
+    };
+    Ok(message)
 }
 
-fn main() {
+/* What the command line asks for, see USAGE */
+#[derive(Debug, Default, PartialEq)]
+struct Cli {
+    file: Option<String>,
+    keep_going: bool,
+    json: bool,
+    ast: Option<AstFormat>,
+    explain: bool,
+    help: bool,
+    expressions: Vec<String>,
+}
+
+/*
+Reads the command-line arguments that follow the program name
+Only the options in USAGE are options, any other argument is an expression, so -2*3 needs no --
+*/
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Cli, String> {
+    let mut cli = Cli::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--file" => match args.next() {
+                Some(path) => cli.file = Some(path),
+                None => return Err(format!("{} needs the path of a file", arg)),
+            },
+            "-k" | "--keep-going" => cli.keep_going = true,
+            "--json" => cli.json = true,
+            "--explain" => cli.explain = true,
+            "-h" | "--help" => cli.help = true,
+            "--" => cli.expressions.extend(args.by_ref()),
+            _ if arg.starts_with("--ast=") => match AstFormat::from_name(&arg[6..]) {
+                Some(format) => cli.ast = Some(format),
+                None => return Err(format!("{} needs tree or dot", arg)),
+            },
+            _ if arg.starts_with("--") => return Err(format!("Unknown option '{}'", arg)),
+            _ => cli.expressions.push(arg),
+        }
+    }
+    if cli.file.is_some() && !cli.expressions.is_empty() {
+        return Err("Give either expressions or a file, not both".into());
+    }
+    if [cli.json, cli.ast.is_some(), cli.explain]
+        .iter()
+        .filter(|&&on| on)
+        .count()
+        > 1
+    {
+        return Err("Give only one of --json, --ast and --explain".into());
+    }
+    Ok(cli)
+}
+
+/*
+Evaluates lines one after another without the banner or the generated AST, printing each result
+Blank lines and comments starting with '#' are skipped, ':' commands work as in the REPL and
+'quit' ends the run early
+An error is written to stderr after where it is, given by place for the line number e.g.
+formulas.txt:3, and ends the run unless keep_going is set
+With json set every line, error or not, is written to stdout as a JSON object instead
+With ast set each expression is drawn as its tree rather than evaluated
+With explain set, or after ':explain on', each step of working out an expression is printed
+*/
+fn run_batch(
+    lines: impl Iterator<Item = io::Result<String>>,
+    place: impl Fn(usize) -> String,
+    cli: &Cli,
+) -> ExitCode {
+    let (keep_going, json, ast) = (cli.keep_going, cli.json, cli.ast);
+    let mut env = Environment::new();
+    let mut options = DisplayOptions::default();
+    let mut syntax = Syntax::default();
+    let mut explain = cli.explain;
+    let mut failed = false;
+    for (index, line) in lines.enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("{}: {}", place(index + 1), e);
+                return ExitCode::from(2);
+            }
+        };
+        let input = line.trim();
+        if input.is_empty() || input.starts_with('#') {
+            continue;
+        }
+        if input.eq_ignore_ascii_case("quit") {
+            break;
+        }
+        if input.starts_with(':') {
+            let result = run_command(input, &mut env, &mut options, &mut syntax, &mut explain);
+            if json {
+                println!("{}", command_json(index + 1, input, &result));
+            } else {
+                match &result {
+                    Ok(message) => println!("{}", message),
+                    Err(e) => eprintln!("{}: {}", place(index + 1), e),
+                }
+            }
+            // a failed command counts as an error, as a failed expression does
+            if result.is_err() {
+                failed = true;
+                if !keep_going {
+                    break;
+                }
+            }
+            continue;
+        }
+        // what is left to print once the line is done, if anything
+        let result = match ast {
+            Some(format) => draw(input, syntax, format)
+                .map(Some)
+                .map_err(CalcError::from),
+            None => {
+                let explained = explain && !json;
+                let evaluation = evaluate(input, &mut env, syntax, explained.then_some(&options));
+                if json {
+                    println!(
+                        "{}",
+                        evaluation_json(index + 1, input, &evaluation, &options)
+                    );
+                }
+                // the steps end with the value, or with the expression a step failed on
+                if !evaluation.steps.is_empty() {
+                    println!("{}", steps_text(&evaluation.steps));
+                }
+                evaluation
+                    .result
+                    .map(|val| (!json && !explained).then(|| val.format(&options)))
+            }
+        };
+        match result {
+            Ok(Some(text)) => println!("{}", text),
+            Ok(None) => {}
+            Err(e) => {
+                // a syntax error starts with the input, so the markers under it line up
+                let separator = match e {
+                    CalcError::Parse(_) => '\n',
+                    CalcError::Eval(_) => ' ',
+                };
+                if !json {
+                    eprintln!(
+                        "{}:{}{}",
+                        place(index + 1),
+                        separator,
+                        report(input, &e, syntax)
+                    );
+                }
+                failed = true;
+                if !keep_going {
+                    break;
+                }
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/* Evaluates every line piped into stdin, see run_batch */
+fn run_stdin(cli: &Cli) -> ExitCode {
+    run_batch(
+        io::stdin().lock().lines(),
+        |line| format!("<stdin>:{}", line),
+        cli,
+    )
+}
+
+/* Evaluates every line of the file at path, see run_batch - a file that cannot be read exits with 2 */
+fn run_file(path: &str, cli: &Cli) -> ExitCode {
+    match fs::read_to_string(path) {
+        Ok(text) => run_batch(
+            text.lines().map(|line| Ok(line.to_string())),
+            |line| format!("{}:{}", path, line),
+            cli,
+        ),
+        Err(e) => {
+            eprintln!("Cannot read {}: {}", path, e);
+            ExitCode::from(2)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = match parse_args(std::env::args().skip(1)) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if cli.help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    match cli.file.as_deref() {
+        Some("-") => return run_stdin(&cli),
+        Some(path) => return run_file(path, &cli),
+        None => {}
+    }
+    if !cli.expressions.is_empty() {
+        return run_batch(
+            cli.expressions.iter().cloned().map(Ok),
+            |n| format!("argument {}", n),
+            &cli,
+        );
+    }
+    // piped input runs silently, like a file
+    if !io::stdin().is_terminal() {
+        return run_stdin(&cli);
+    }
+    run_repl();
+    ExitCode::SUCCESS
+}
+
+/*
+The ':' command on a line of the calculator, which may be indented e.g. '  :mode int'
+An expression is not trimmed in the same way, so its errors line up with what was typed
+*/
+fn command_of(input: &str) -> Option<&str> {
+    let command = input.trim_start();
+    command.starts_with(':').then_some(command)
+}
+
+/* The interactive calculator, with its banner and messages */
+fn run_repl() {
     println!("Hello! Welcome to Arithmetic Expression Evaluator!");
     println!("You can calculate the value of expressions such as: 2*3+4(4-5)+2^3/4. ");
-    println!("Allowed numbers: positive, negative and decimals. ");
+    println!("Allowed numbers: positive, negative, decimals and exponents e.g. 6.02e23 or .5. ");
+    println!("Unicode operators are accepted too: 2 × π ÷ √2 − 1. ");
     println!("Supported operations: Add, Subtract, Multiply, Divide, PowerOf(^). ");
-    println!("Type 'quit' to exit. ");
+    println!(
+        "Functions such as sqrt(x), sin(x), ln(x), min(a, b, ...) and the constants pi, e, tau. "
+    );
+    println!("Postfix 5! is a factorial (0.5! too), 'price * 15%' a percentage and 7 mod 3 the remainder. ");
+    println!("Store results with 'let x = 2*3' and use 'ans' for the last computed value. ");
+    println!("Use ':policy ieee' to get inf/NaN instead of errors for 1/0 and similar. ");
+    println!(
+        "Use ':mode exact' for exact fractions and ':format decimal 10' to show them as decimals. "
+    );
+    println!("Complex numbers are written 3 + 2.5i, use ':mode complex' to allow sqrt(-1). ");
+    println!(
+        "Use ':mode int' or ':mode u64' for integers with 0x/0o/0b literals, % // & | xor << >> ~ \
+         and ':base 16' to show them in hex. "
+    );
+    println!(
+        "Conditions such as 'load > 0.8 && mem < 0.5' give true or false, 'c ? a : b' picks a value. "
+    );
+    println!("Differentiate with ':d/dx x^2 * sin(x)' (any variable name can follow d/d). ");
+    println!(
+        "Use ':simplify 2*x + x' for a canonical form and ':equiv a + b ; b + a' to compare. "
+    );
+    println!("Use ':explain on' to see each step of the working e.g. 2*3+4 = 6+4 = 10. ");
+    println!("See how an expression is grouped with ':ast tree 1 + 2 * 3' (or ':ast dot' for Graphviz). ");
+    println!(
+        "Numbers can carry units e.g. '70 kg * 9.81 m/s^2', '60 mph to m/s', '20 dBm to mW'. "
+    );
+    println!(
+        "Products can be written without '*' e.g. 2x or 3(x+1), ':syntax strict' turns this off. "
+    );
+    println!(
+        "'^' groups to the right and binds tighter than a leading minus: 2^3^2 = 512, -2^2 = -4, \
+         ':syntax minus-first' makes -2^2 = 4. "
+    );
+    println!(
+        "Edit the line with the arrow keys, Up/Down for history, Ctrl-R to search it, Tab to complete. "
+    );
+    println!(
+        "Type 'quit' or press Ctrl-D to exit, see 'chapter-2 --help' for running it from scripts. "
+    );
     println!("Enter your arithmetic expression below:");
+    let mut env = Environment::new();
+    let mut options = DisplayOptions::default();
+    let mut syntax = Syntax::default();
+    let mut explain = false;
+    let mut editor = Editor::new();
     loop {
-        let mut input = String::new();
-        match io::stdin().read_line(&mut input) {
+        let input = match editor.read_line("> ", &completions(&env)) {
+            Ok(Some(input)) if !input.trim().eq_ignore_ascii_case("quit") => input,
+            // 'quit' or the end of the input e.g. Ctrl-D or the end of a piped file
             Ok(_) => {
-                if input.to_lowercase().contains("quit") {
-                    println!("Thanks for using the Arithmetic Expression Evaluator! ");
-                    println!("Goodbye!");
-                    break;
-                }
-                match evaluate(input) {
-                    Ok(val) => println!("The computed number is {}\n", val),
-                    Err(_) => {
-                        println!("Error in evaluating expression. Please enter valid expression\n");
-                    }
-                };
+                println!("Thanks for using the Arithmetic Expression Evaluator! ");
+                println!("Goodbye!");
+                break;
+            }
+            Err(error) => {
+                println!("ERROR: {}", error);
+                break;
             }
-            Err(error) => println!("ERROR: {}", error),
+        };
+        // trailing whitespace is dropped so an error at the end of input lines up
+        let input = input.trim_end();
+        if let Some(command) = command_of(input) {
+            let (Ok(message) | Err(message)) =
+                run_command(command, &mut env, &mut options, &mut syntax, &mut explain);
+            println!("{}\n", message);
+            continue;
         }
+        let evaluation = evaluate(input, &mut env, syntax, explain.then_some(&options));
+        if explain {
+            // the steps end with the value, or with the expression a step failed on
+            if !evaluation.steps.is_empty() {
+                println!("{}", steps_text(&evaluation.steps));
+            }
+        } else if let Some(ast) = &evaluation.ast {
+            println!("The generated AST is {}", ast);
+        }
+        match evaluation.result {
+            Ok(val) => println!("The computed number is {}\n", val.format(&options)),
+            Err(e) => println!("{}\n", report(input, &e, syntax)),
+        };
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Cli, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli = args(&["-k", "2*3", "-2^2", "--", "--help"]).unwrap();
+        assert!(cli.keep_going && !cli.help && !cli.json);
+        assert_eq!(cli.expressions, ["2*3", "-2^2", "--help"]);
+        let cli = args(&["--file", "formulas.txt", "--json"]).unwrap();
+        assert_eq!(cli.file.as_deref(), Some("formulas.txt"));
+        assert!(cli.json);
+        assert_eq!(args(&[]), Ok(Cli::default()));
+        assert!(args(&["-f"]).is_err());
+        assert!(args(&["--verbose"]).is_err());
+        assert!(args(&["-f", "formulas.txt", "1 + 1"]).is_err());
+        assert_eq!(args(&["--ast=dot", "1"]).unwrap().ast, Some(AstFormat::Dot));
+        assert!(args(&["--ast=svg"]).is_err());
+        assert!(args(&["--ast=tree", "--json"]).is_err());
+        assert!(args(&["--explain"]).unwrap().explain);
+        assert!(args(&["--explain", "--ast=dot"]).is_err());
+    }
+
+    #[test]
+    fn test_evaluation_json() {
+        let mut env = Environment::new();
+        let options = DisplayOptions::default();
+        let json = |input: &str, env: &mut Environment| {
+            let evaluation = evaluate(input, env, Syntax::default(), None);
+            evaluation_json(1, input, &evaluation, &options).to_string()
+        };
+        assert_eq!(
+            json("let x = 3!", &mut env),
+            r#"{"line":1,"input":"let x = 3!","ok":true,"expression":"let x = factorial(3)","value":"6","type":"real","number":6}"#
+        );
+        assert_eq!(
+            json("x / 0", &mut env),
+            r#"{"line":1,"input":"x / 0","ok":false,"expression":"x / 0","error":{"kind":"division_by_zero","message":"Division by zero","span":null}}"#
+        );
+        assert!(json("(1 +", &mut env).ends_with(r#""span":{"start":4,"end":4}}}"#));
+        // the expression is the canonical form of the input
+        assert!(json("y*2 + 0", &mut env).contains(r#""expression":"2 * y""#));
+        assert_eq!(
+            command_json(2, ":base 1", &Err("'1' is not a base from 2 to 36".into())).to_string(),
+            r#"{"line":2,"input":":base 1","ok":false,"error":{"kind":"command","message":"'1' is not a base from 2 to 36","span":null}}"#
+        );
+    }
+
+    #[test]
+    fn test_batch_exit_code() {
+        let run = |lines: &[&str], keep_going: bool| {
+            let cli = Cli {
+                keep_going,
+                ..Cli::default()
+            };
+            let lines = lines.iter().map(|line| Ok(line.to_string()));
+            run_batch(lines, |n| format!("argument {}", n), &cli)
+        };
+        assert_eq!(run(&[":mode int", "2 + 3"], false), ExitCode::SUCCESS);
+        // a failed or unknown command fails the run like a failed expression
+        assert_eq!(run(&[":mode bogus"], false), ExitCode::FAILURE);
+        assert_eq!(run(&[":d/dx x <"], false), ExitCode::FAILURE);
+        assert_eq!(run(&[":simplify (1", "2 + 3"], true), ExitCode::FAILURE);
+        assert_eq!(run(&["1 / 0"], false), ExitCode::FAILURE);
+        assert_eq!(run(&["1 / 0", "2 + 3"], true), ExitCode::FAILURE);
+        assert_eq!(
+            run(&["# comment", "", "let x = 2", "x"], false),
+            ExitCode::SUCCESS
+        );
+
+        // input that cannot be read exits with 2, even after a line has failed
+        let unreadable = [
+            Ok("1 / 0".to_string()),
+            Err(io::Error::new(io::ErrorKind::InvalidData, "not UTF-8")),
+            Ok("2 + 3".to_string()),
+        ];
+        let cli = Cli {
+            keep_going: true,
+            ..Cli::default()
+        };
+        assert_eq!(
+            run_batch(unreadable.into_iter(), |n| format!("<stdin>:{}", n), &cli),
+            ExitCode::from(2)
+        );
+
+        let path = std::env::temp_dir().join(format!("chapter-2-exit-{}.txt", std::process::id()));
+        let path_text = path.to_str().unwrap();
+        fs::write(&path, "1 + 1\n2 * 3\n").unwrap();
+        assert_eq!(run_file(path_text, &cli), ExitCode::SUCCESS);
+        fs::write(&path, "1 + 1\nsqrt(-1)\n").unwrap();
+        assert_eq!(run_file(path_text, &cli), ExitCode::FAILURE);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(run_file(path_text, &cli), ExitCode::from(2));
+    }
+
+    #[test]
+    fn test_repl_command() {
+        let mut env = Environment::new();
+        let (mut options, mut syntax, mut explain) =
+            (DisplayOptions::default(), Syntax::default(), false);
+        // an indented command runs as it would without the spaces in front
+        let command = command_of("   :mode int").unwrap();
+        assert_eq!(command, ":mode int");
+        assert!(run_command(command, &mut env, &mut options, &mut syntax, &mut explain).is_ok());
+        assert!(env.int_width().is_some());
+        assert_eq!(command_of("\t:explain on"), Some(":explain on"));
+        // an expression is left as it is, spaces and all
+        assert_eq!(command_of("  2 + 3"), None);
+        assert_eq!(command_of("x ? 1 : 2"), None);
     }
 }
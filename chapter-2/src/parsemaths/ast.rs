@@ -1,10 +1,29 @@
 // in ast.rs - providing code for the AST
 
-use std::error;
+use crate::parsemaths::bigint::BigInt;
+use crate::parsemaths::complex::Complex;
+use crate::parsemaths::environment::{Environment, EvalPolicy, NumberMode};
+use crate::parsemaths::functions::{self, Arity, Builtin};
+use crate::parsemaths::rational::Rational;
+use crate::parsemaths::token::{OperPrec, Span};
+use crate::parsemaths::tokenizer::MAX_EXACT_FLOAT_INTEGER;
+use crate::parsemaths::units::{self, Unit};
+use crate::parsemaths::value::{check_complex, check_real, real_text, whole_result, Value};
+use std::cmp::Ordering;
+use std::{error, fmt};
 
 /*
 List of permitted AST node types that can be evaluated
-Can be arithmetic operators or numbers
+Can be arithmetic, bitwise, comparison or logical operators, the conditional cond ? a : b,
+numbers (real or imaginary e.g. 2.5i), true and false, variables, an assignment to a variable or a
+function call
+Integer holds literals that must keep every digit e.g. 0xffff_ffff_ffff_ffff, and Decimal those
+written with more digits than an f64 keeps e.g. 0.1234567890123456789, as their text
+Percent is a percentage e.g. 15% = 15 / 100, which has no meaning for whole numbers
+Quantity is a number with a unit e.g. 9.81 m/s^2, and Convert shows a value in another unit of the
+same dimension e.g. 60 mph to m/s
+Error stands in for an operand the parser could not read when recovering from errors, with the
+location of the problem in the input, see Parser::parse_recovering
 */
 #[derive(Debug, Clone, PartialEq)]
 pub enum Node {
@@ -12,31 +31,628 @@ pub enum Node {
     Subtract(Box<Node>, Box<Node>),
     Multiply(Box<Node>, Box<Node>),
     Divide(Box<Node>, Box<Node>),
+    FloorDivide(Box<Node>, Box<Node>),
+    Modulo(Box<Node>, Box<Node>),
     Caret(Box<Node>, Box<Node>),
+    BitAnd(Box<Node>, Box<Node>),
+    BitOr(Box<Node>, Box<Node>),
+    BitXor(Box<Node>, Box<Node>),
+    ShiftLeft(Box<Node>, Box<Node>),
+    ShiftRight(Box<Node>, Box<Node>),
+    BitNot(Box<Node>),
+    Equal(Box<Node>, Box<Node>),
+    NotEqual(Box<Node>, Box<Node>),
+    Less(Box<Node>, Box<Node>),
+    LessEqual(Box<Node>, Box<Node>),
+    Greater(Box<Node>, Box<Node>),
+    GreaterEqual(Box<Node>, Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Percent(Box<Node>),
+    Conditional(Box<Node>, Box<Node>, Box<Node>),
     Negative(Box<Node>),
     Number(f64),
+    Integer(u64),
+    Decimal(String),
+    Imaginary(f64),
+    Quantity(f64, Unit),
+    Bool(bool),
+    Variable(String),
+    Assign(String, Box<Node>),
+    Call(String, Vec<Node>),
+    Convert(Box<Node>, Unit),
+    Error(Span),
+}
+
+impl Node {
+    /* Symbol and operands of a binary operator - None for every other kind of node */
+    pub fn binary(&self) -> Option<(&'static str, &Node, &Node)> {
+        use self::Node::*;
+        let (symbol, left, right) = match self {
+            Add(a, b) => ("+", a, b),
+            Subtract(a, b) => ("-", a, b),
+            Multiply(a, b) => ("*", a, b),
+            Divide(a, b) => ("/", a, b),
+            FloorDivide(a, b) => ("//", a, b),
+            Modulo(a, b) => ("%", a, b),
+            Caret(a, b) => ("^", a, b),
+            BitAnd(a, b) => ("&", a, b),
+            BitOr(a, b) => ("|", a, b),
+            BitXor(a, b) => ("xor", a, b),
+            ShiftLeft(a, b) => ("<<", a, b),
+            ShiftRight(a, b) => (">>", a, b),
+            Equal(a, b) => ("==", a, b),
+            NotEqual(a, b) => ("!=", a, b),
+            Less(a, b) => ("<", a, b),
+            LessEqual(a, b) => ("<=", a, b),
+            Greater(a, b) => (">", a, b),
+            GreaterEqual(a, b) => (">=", a, b),
+            And(a, b) => ("&&", a, b),
+            Or(a, b) => ("||", a, b),
+            _ => return None,
+        };
+        Some((symbol, left, right))
+    }
+
+    /* Rebuilds the node with f applied to each of the nodes directly below it */
+    pub fn map_children(self, mut f: impl FnMut(Node) -> Node) -> Node {
+        use self::Node::*;
+        let mut g = |node: Box<Node>| Box::new(f(*node));
+        match self {
+            Add(a, b) => Add(g(a), g(b)),
+            Subtract(a, b) => Subtract(g(a), g(b)),
+            Multiply(a, b) => Multiply(g(a), g(b)),
+            Divide(a, b) => Divide(g(a), g(b)),
+            FloorDivide(a, b) => FloorDivide(g(a), g(b)),
+            Modulo(a, b) => Modulo(g(a), g(b)),
+            Caret(a, b) => Caret(g(a), g(b)),
+            BitAnd(a, b) => BitAnd(g(a), g(b)),
+            BitOr(a, b) => BitOr(g(a), g(b)),
+            BitXor(a, b) => BitXor(g(a), g(b)),
+            ShiftLeft(a, b) => ShiftLeft(g(a), g(b)),
+            ShiftRight(a, b) => ShiftRight(g(a), g(b)),
+            Equal(a, b) => Equal(g(a), g(b)),
+            NotEqual(a, b) => NotEqual(g(a), g(b)),
+            Less(a, b) => Less(g(a), g(b)),
+            LessEqual(a, b) => LessEqual(g(a), g(b)),
+            Greater(a, b) => Greater(g(a), g(b)),
+            GreaterEqual(a, b) => GreaterEqual(g(a), g(b)),
+            And(a, b) => And(g(a), g(b)),
+            Or(a, b) => Or(g(a), g(b)),
+            Negative(a) => Negative(g(a)),
+            BitNot(a) => BitNot(g(a)),
+            Not(a) => Not(g(a)),
+            Percent(a) => Percent(g(a)),
+            Assign(name, a) => Assign(name, g(a)),
+            Convert(a, unit) => Convert(g(a), unit),
+            Conditional(cond, a, b) => Conditional(g(cond), g(a), g(b)),
+            Call(name, args) => Call(name, args.into_iter().map(f).collect()),
+            leaf => leaf,
+        }
+    }
+
+    /*
+    Precedence of the operator at the top of the node, as used by the parser
+    None for numbers, names and calls, which never need parentheses around them
+    */
+    pub fn precedence(&self) -> Option<OperPrec> {
+        use self::Node::*;
+        Some(match self {
+            Assign(..) => OperPrec::DefaultZero,
+            Convert(..) => OperPrec::Convert,
+            Conditional(..) => OperPrec::Conditional,
+            Or(..) => OperPrec::Or,
+            And(..) => OperPrec::And,
+            Equal(..) | NotEqual(..) | Less(..) | LessEqual(..) | Greater(..)
+            | GreaterEqual(..) => OperPrec::Comparison,
+            BitOr(..) => OperPrec::BitOr,
+            BitXor(..) => OperPrec::BitXor,
+            BitAnd(..) => OperPrec::BitAnd,
+            ShiftLeft(..) | ShiftRight(..) => OperPrec::Shift,
+            Add(..) | Subtract(..) => OperPrec::AddSub,
+            Multiply(..) | Divide(..) | FloorDivide(..) | Modulo(..) => OperPrec::MulDiv,
+            Caret(..) => OperPrec::Power,
+            Negative(_) | BitNot(_) | Not(_) => OperPrec::Negative,
+            Percent(_) => OperPrec::Postfix,
+            Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) | Bool(_)
+            | Variable(_) | Call(..) | Error(_) => return None,
+        })
+    }
+
+    /* The nodes directly below this one, in the order they are written */
+    pub fn children(&self) -> Vec<&Node> {
+        use self::Node::*;
+        match self {
+            Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) | Bool(_)
+            | Variable(_) | Error(_) => Vec::new(),
+            Negative(a) | BitNot(a) | Not(a) | Percent(a) | Assign(_, a) | Convert(a, _) => {
+                vec![a]
+            }
+            Conditional(cond, a, b) => vec![cond, a, b],
+            Call(_, args) => args.iter().collect(),
+            _ => match self.binary() {
+                Some((_, a, b)) => vec![a, b],
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /* The nodes directly below this one, in the order they are written, for changing in place */
+    pub fn children_mut(&mut self) -> Vec<&mut Node> {
+        use self::Node::*;
+        match self {
+            Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) | Bool(_)
+            | Variable(_) | Error(_) => Vec::new(),
+            Negative(a) | BitNot(a) | Not(a) | Percent(a) | Assign(_, a) | Convert(a, _) => {
+                vec![a]
+            }
+            Conditional(cond, a, b) => vec![cond, a, b],
+            Call(_, args) => args.iter_mut().collect(),
+            Add(a, b)
+            | Subtract(a, b)
+            | Multiply(a, b)
+            | Divide(a, b)
+            | FloorDivide(a, b)
+            | Modulo(a, b)
+            | Caret(a, b)
+            | BitAnd(a, b)
+            | BitOr(a, b)
+            | BitXor(a, b)
+            | ShiftLeft(a, b)
+            | ShiftRight(a, b)
+            | Equal(a, b)
+            | NotEqual(a, b)
+            | Less(a, b)
+            | LessEqual(a, b)
+            | Greater(a, b)
+            | GreaterEqual(a, b)
+            | And(a, b)
+            | Or(a, b) => {
+                vec![a, b]
+            }
+        }
+    }
+}
+
+/*
+Writes the node back out as an expression the parser reads back to the same tree e.g. 2 * x + 1
+Only the parentheses the precedence and associativity of the operators need are written
+e.g. a - (b - c), (a + b) * c, (a ^ b) ^ c and (-x) ^ 2
+Prefix operators are written for the default Negation, where -x ^ 2 is -(x ^ 2)
+This holds for every tree the parser can produce, which never has a NaN in it, see literal_text
+*/
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Node::*;
+        // wraps an operand in parentheses when it binds more loosely than its position needs
+        // or, if tight, when it is at the same level
+        let operand = |node: &Node, position: OperPrec, tight: bool| match node.precedence() {
+            Some(prec) if prec < position || (tight && prec == position) => format!("({})", node),
+            _ => node.to_string(),
+        };
+        match self {
+            Number(n) => write!(f, "{}", literal_text(*n)),
+            // smaller integers are only kept as integers when written with a prefix e.g. 0xff
+            Integer(n) if *n <= MAX_EXACT_FLOAT_INTEGER => write!(f, "{:#x}", n),
+            Integer(n) => write!(f, "{}", n),
+            Decimal(text) => write!(f, "{}", text),
+            Imaginary(n) => write!(f, "{}i", literal_text(*n)),
+            Quantity(n, unit) => write!(f, "{} {}", Number(*n), unit),
+            Bool(b) => write!(f, "{}", b),
+            Variable(name) => write!(f, "{}", name),
+            Error(_) => write!(f, "<error>"),
+            Negative(a) => write!(f, "-{}", operand(a, OperPrec::Negative, false)),
+            BitNot(a) => write!(f, "~{}", operand(a, OperPrec::Negative, false)),
+            Not(a) => write!(f, "!{}", operand(a, OperPrec::Negative, false)),
+            Percent(a) => write!(f, "{}%", operand(a, OperPrec::Postfix, false)),
+            Assign(name, a) => write!(f, "let {} = {}", name, a),
+            // the first branch is read up to the ':' so it never needs parentheses, the second only
+            // around a conversion, which would otherwise apply to the whole conditional
+            Conditional(cond, a, b) => write!(
+                f,
+                "{} ? {} : {}",
+                operand(cond, OperPrec::Conditional, true),
+                a,
+                operand(b, OperPrec::Conditional, false)
+            ),
+            Convert(a, unit) => write!(f, "{} to {}", operand(a, OperPrec::Convert, false), unit),
+            Call(name, args) => {
+                let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            _ => {
+                let (symbol, a, b) = self
+                    .binary()
+                    .expect("every other node is a binary operator");
+                let prec = self
+                    .precedence()
+                    .expect("binary operators have a precedence");
+                // the operand on the side an operator does not group towards is tight, which is
+                // the right one except for '^'
+                let right_assoc = matches!(self, Caret(..));
+                // a prefix operator is read wherever an operand starts, so it never needs
+                // parentheses on the right e.g. 2 ^ -x
+                let right = match b.precedence() {
+                    Some(OperPrec::Negative) => b.to_string(),
+                    _ => operand(b, prec, !right_assoc),
+                };
+                // except after '%', where a '-' is read as taking away from a percentage e.g. 5% - 1
+                let right = match self {
+                    Modulo(..) if right.starts_with('-') => format!("({})", right),
+                    _ => right,
+                };
+                write!(f, "{} {} {}", operand(a, prec, right_assoc), symbol, right)
+            }
+        }
+    }
+}
+
+/*
+Text of a number literal that reads back as n
+Very large and very small numbers are written with an exponent e.g. 6.02e23, and infinity as 1e400,
+which is too large for an f64 and so is read back as infinity
+NaN is the one value no literal is read as, and is written NaN
+*/
+fn literal_text(n: f64) -> String {
+    if n.is_nan() {
+        "NaN".into()
+    } else if n.is_infinite() {
+        format!("{}1e400", if n < 0.0 { "-" } else { "" })
+    } else if n.fract() == 0.0 && n.abs() > MAX_EXACT_FLOAT_INTEGER as f64 && n.abs() < 1e16 {
+        // whole numbers past 2^53 are read back as integers, so they keep a decimal point
+        format!("{}.0", n)
+    } else {
+        real_text(n)
+    }
 }
 
 /*
 Code to evaluate the information in the node, introducing the basic rules of maths logic
-Function returns Box<dyn error:Error> to tell the compiler we don't know what will return
-Whatever returns must implement the Error trait - dyn error Error = trait object
-The use of dyn indicates a trait object (Dynamic Dispatch)
+Returns an EvalError for problems with the maths itself - syntax problems are ParseErrors
+The environment supplies the values of variables and records any assignments made
+It also holds the NumberMode, which chooses whether literals are floating point, exact or whole
+numbers and whether sqrt(-1) is i or an error, and the EvalPolicy, which decides whether results
+such as 1/0 are errors or IEEE infinities/NaN
+Numbers and booleans do not mix: 1 + true and !2 are a TypeError, while && and || only evaluate
+their right side when the left side does not decide the result
 */
-pub fn eval(expr: Node) -> Result<f64, Box<dyn error::Error>> {
+pub fn eval(expr: Node, env: &mut Environment) -> Result<Value, EvalError> {
     use self::Node::*;
     match expr {
-        Number(i) => Ok(i),
-        Add(expr1, expr2) => Ok(eval(*expr1)? + eval(*expr2)?),
-        Subtract(expr1, expr2) => Ok(eval(*expr1)? - eval(*expr2)?),
-        Multiply(expr1, expr2) => Ok(eval(*expr1)? * eval(*expr2)?),
-        Divide(expr1, expr2) => Ok(eval(*expr1)? / eval(*expr2)?),
-        Negative(expr1) => Ok(-(eval(*expr1)?)),
-        Caret(expr1, expr2) => Ok(eval(*expr1)?.powf(eval(*expr2)?)),
+        Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) => {
+            eval_literal(&expr, env)
+        }
+        Negative(ref a) if matches!(**a, Number(_) | Integer(_)) => eval_literal(&expr, env),
+        Add(expr1, expr2) => eval_number(*expr1, env)?.add(eval_number(*expr2, env)?, env),
+        Subtract(expr1, expr2) => eval_number(*expr1, env)?.sub(eval_number(*expr2, env)?, env),
+        Multiply(expr1, expr2) => eval_number(*expr1, env)?.mul(eval_number(*expr2, env)?, env),
+        Divide(expr1, expr2) => eval_number(*expr1, env)?.div(eval_number(*expr2, env)?, env),
+        FloorDivide(expr1, expr2) => {
+            eval_number(*expr1, env)?.floor_div(eval_number(*expr2, env)?, env)
+        }
+        Modulo(expr1, expr2) => eval_number(*expr1, env)?.modulo(eval_number(*expr2, env)?, env),
+        Negative(expr1) => eval_number(*expr1, env)?.neg(env),
+        Percent(expr1) => eval_number(*expr1, env)?.percent(env),
+        Caret(expr1, expr2) => eval_number(*expr1, env)?.pow(eval_number(*expr2, env)?, env),
+        BitAnd(expr1, expr2) => eval_number(*expr1, env)?.bit_and(eval_number(*expr2, env)?, env),
+        BitOr(expr1, expr2) => eval_number(*expr1, env)?.bit_or(eval_number(*expr2, env)?, env),
+        BitXor(expr1, expr2) => eval_number(*expr1, env)?.bit_xor(eval_number(*expr2, env)?, env),
+        ShiftLeft(expr1, expr2) => eval_number(*expr1, env)?.shl(eval_number(*expr2, env)?, env),
+        ShiftRight(expr1, expr2) => eval_number(*expr1, env)?.shr(eval_number(*expr2, env)?, env),
+        BitNot(expr1) => eval_number(*expr1, env)?.bit_not(env),
+        Bool(b) => Ok(Value::Bool(b)),
+        Equal(expr1, expr2) => Ok(Value::Bool(eval(*expr1, env)?.equals(&eval(*expr2, env)?)?)),
+        NotEqual(expr1, expr2) => Ok(Value::Bool(
+            !eval(*expr1, env)?.equals(&eval(*expr2, env)?)?,
+        )),
+        Less(expr1, expr2) => compare(*expr1, *expr2, env, Ordering::is_lt),
+        LessEqual(expr1, expr2) => compare(*expr1, *expr2, env, Ordering::is_le),
+        Greater(expr1, expr2) => compare(*expr1, *expr2, env, Ordering::is_gt),
+        GreaterEqual(expr1, expr2) => compare(*expr1, *expr2, env, Ordering::is_ge),
+        And(expr1, expr2) => Ok(Value::Bool(
+            eval_bool(*expr1, env)? && eval_bool(*expr2, env)?,
+        )),
+        Or(expr1, expr2) => Ok(Value::Bool(
+            eval_bool(*expr1, env)? || eval_bool(*expr2, env)?,
+        )),
+        Not(expr1) => Ok(Value::Bool(!eval_bool(*expr1, env)?)),
+        Convert(expr1, unit) => units::convert(eval_number(*expr1, env)?, &unit, env),
+        Error(_) => Err(EvalError::SyntaxError),
+        // only the chosen branch is evaluated, so cond ? 1/x : 0 is safe when x is zero
+        Conditional(cond, expr1, expr2) => {
+            if eval_bool(*cond, env)? {
+                eval(*expr1, env)
+            } else {
+                eval(*expr2, env)
+            }
+        }
+        // assigned variables are checked first, then the built-in constants such as pi
+        Variable(name) => match env.get(&name) {
+            Some(val) => Ok(val),
+            None => match functions::constant(&name) {
+                Some(val) => Ok(Value::Real(val)),
+                None => Err(EvalError::UnknownVariable(name)),
+            },
+        },
+        // an assignment evaluates to the value it stores
+        Assign(name, expr1) => {
+            if functions::constant(&name).is_some() {
+                return Err(EvalError::AssignToConstant(name));
+            }
+            let val = eval(*expr1, env)?;
+            env.set(&name, val.clone());
+            Ok(val)
+        }
+        Call(name, args) => {
+            let builtin = match functions::lookup(&name) {
+                Some(builtin) => builtin,
+                None => return Err(EvalError::UnknownFunction(name)),
+            };
+            if !builtin.arity.accepts(args.len()) {
+                return Err(EvalError::WrongArity {
+                    name,
+                    expected: builtin.arity,
+                    found: args.len(),
+                });
+            }
+            let mut values = Vec::with_capacity(args.len());
+            for arg in args {
+                values.push(eval_number(arg, env)?);
+            }
+            call_builtin(builtin, &values, env)
+        }
     }
 }
 
+/*
+Value of a number literal in the number mode of env e.g. 0.1 is exactly 1/10 in the exact mode
+A negated number e.g. -5 counts as a literal too
+Kept apart from eval so the bytecode compiler converts literals the same way
+*/
+pub fn eval_literal(expr: &Node, env: &Environment) -> Result<Value, EvalError> {
+    use self::Node::*;
+    let policy = env.policy();
+    match *expr {
+        Number(i) => match env.mode() {
+            // an infinite literal has no exact value, so it stays a Real for the policy to judge
+            NumberMode::Exact if i.is_finite() => Ok(Value::Rational(
+                Rational::from_f64(i).expect("literal is finite"),
+            )),
+            NumberMode::Integer(_) => {
+                let n = Value::Real(i).to_whole()?;
+                whole_result(n, env, || real_text(i))
+            }
+            _ => check_real(policy, &[], i, || "number literal".into()).map(Value::Real),
+        },
+        Integer(i) => match env.mode() {
+            NumberMode::Integer(_) => whole_result(i as i128, env, || i.to_string()),
+            NumberMode::Exact => Ok(Value::Rational(Rational::from_integer(BigInt::from_i128(
+                i as i128,
+            )))),
+            _ => Ok(Value::Real(i as f64)),
+        },
+        // the exact mode keeps every digit, the others take the nearest f64 as for a Number
+        Decimal(ref text) => match env.mode() {
+            NumberMode::Exact => Ok(Value::Rational(
+                Rational::parse_decimal(text).expect("the tokenizer read it as a number"),
+            )),
+            _ => eval_literal(&Number(text.parse().unwrap_or(f64::NAN)), env),
+        },
+        Imaginary(i) => {
+            if env.int_width().is_some() {
+                return Err(EvalError::TypeError(format!(
+                    "{}i is not a whole number",
+                    i
+                )));
+            }
+            check_real(policy, &[], i, || "number literal".into())
+                .map(|i| Value::from_complex(Complex::new(0.0, i)))
+        }
+        Quantity(i, ref unit) => units::literal(i, unit, env),
+        // whole numbers are negated before their range is checked, so -9223372036854775808 is the
+        // smallest i64 even though 9223372036854775808 on its own is too large for one
+        Negative(ref a) => match (env.int_width(), &**a) {
+            (Some(_), &Number(i)) => {
+                let n = Value::Real(i).to_whole()?;
+                whole_result(-n, env, || format!("-{}", real_text(i)))
+            }
+            (Some(_), &Integer(i)) => whole_result(-(i as i128), env, || format!("-{}", i)),
+            _ => eval_literal(a, env)?.neg(env),
+        },
+        _ => unreachable!("only called on number literals"),
+    }
+}
+
+/* Evaluates an operand of an arithmetic operator or a function - a TypeError if it is a boolean */
+fn eval_number(expr: Node, env: &mut Environment) -> Result<Value, EvalError> {
+    expect_number(eval(expr, env)?)
+}
+
+/* The value itself, or a TypeError if it is a boolean */
+pub fn expect_number(val: Value) -> Result<Value, EvalError> {
+    match val {
+        Value::Bool(b) => Err(EvalError::TypeError(format!(
+            "expected a number, found {}",
+            b
+        ))),
+        val => Ok(val),
+    }
+}
+
+/* Evaluates an operand of !, && or || or a condition - a TypeError if it is a number */
+fn eval_bool(expr: Node, env: &mut Environment) -> Result<bool, EvalError> {
+    expect_bool(eval(expr, env)?)
+}
+
+/* The value of a boolean, or a TypeError if it is a number */
+pub fn expect_bool(val: Value) -> Result<bool, EvalError> {
+    match val {
+        Value::Bool(b) => Ok(b),
+        val => Err(EvalError::TypeError(format!(
+            "expected true or false, found {}",
+            val
+        ))),
+    }
+}
+
+/*
+Evaluates an ordering comparison such as a < b
+Comparisons with NaN are false as in IEEE 754, and complex numbers have no order at all
+*/
+fn compare(
+    left: Node,
+    right: Node,
+    env: &mut Environment,
+    test: fn(Ordering) -> bool,
+) -> Result<Value, EvalError> {
+    compare_values(eval_number(left, env)?, eval_number(right, env)?, test)
+}
+
+/* Compares two evaluated numbers, giving true when test accepts their ordering */
+pub fn compare_values(a: Value, b: Value, test: fn(Ordering) -> bool) -> Result<Value, EvalError> {
+    if a.is_complex() || b.is_complex() {
+        return Err(EvalError::TypeError(format!(
+            "complex numbers cannot be ordered, found {} and {}",
+            a, b
+        )));
+    }
+    if a.is_quantity() || b.is_quantity() {
+        return Ok(Value::Bool(units::compare(&a, &b)?.is_some_and(test)));
+    }
+    Ok(Value::Bool(a.compare(&b).is_some_and(test)))
+}
+
+/*
+Calls a built-in function on evaluated arguments - functions are computed in floating point,
+except in the integer number mode where only the functions with an integer version can be called
+Real arguments use the real version of the function, unless the result is undefined (NaN) and
+the complex number mode is on, in which case the complex version is tried e.g. sqrt(-1) = i
+Complex arguments always use the complex version, if the function has one
+*/
+pub fn call_builtin(
+    builtin: &Builtin,
+    values: &[Value],
+    env: &Environment,
+) -> Result<Value, EvalError> {
+    let policy = env.policy();
+    let describe = || {
+        let args: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        format!("{}({})", builtin.name, args.join(", "))
+    };
+    if env.int_width().is_some() {
+        let integer = builtin.integer.ok_or_else(|| {
+            EvalError::TypeError(format!("{}() is not available for integers", builtin.name))
+        })?;
+        let mut args = Vec::with_capacity(values.len());
+        for value in values {
+            args.push(value.to_whole()?);
+        }
+        let result = integer(&args).ok_or_else(|| EvalError::DomainError(describe()))?;
+        return whole_result(result, env, describe);
+    }
+    if values.iter().any(Value::is_quantity) {
+        return units::call(builtin, values, env);
+    }
+    let call_complex = |complex: fn(&[Complex]) -> Complex| {
+        let args: Vec<Complex> = values.iter().map(|v| v.to_complex()).collect();
+        check_complex(policy, &args, complex(&args), describe).map(Value::from_complex)
+    };
+    if values.iter().any(|v| v.is_complex()) {
+        return match builtin.complex {
+            Some(complex) => call_complex(complex),
+            None => Err(EvalError::DomainError(describe())),
+        };
+    }
+    let reals: Vec<f64> = values.iter().map(|v| v.to_f64()).collect();
+    let result = (builtin.func)(&reals);
+    if let (true, NumberMode::Complex, Some(complex)) = (
+        result.is_nan() && reals.iter().all(|x| x.is_finite()),
+        env.mode(),
+        builtin.complex,
+    ) {
+        return call_complex(complex);
+    }
+    // an infinite result at zero is a pole of the function e.g. ln(0), not an overflow
+    if result.is_infinite() && reals.contains(&0.0) && policy == EvalPolicy::Strict {
+        return Err(EvalError::DomainError(describe()));
+    }
+    check_real(policy, &reals, result, describe).map(Value::Real)
+}
+
+/*
+Errors raised while evaluating a syntactically valid AST
+Kept separate from ParseError so callers can tell a maths problem from a syntax problem
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    DivisionByZero,
+    DomainError(String),
+    Overflow(String),
+    Underflow(String),
+    NonFinite,
+    UnknownVariable(String),
+    UnknownFunction(String),
+    AssignToConstant(String),
+    TypeError(String),
+    UnitError(String),
+    SyntaxError,
+    WrongArity {
+        name: String,
+        expected: Arity,
+        found: usize,
+    },
+}
+
+impl EvalError {
+    /* Short name for the kind of error, for programs reading the output */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EvalError::DivisionByZero => "division_by_zero",
+            EvalError::DomainError(_) => "domain",
+            EvalError::Overflow(_) => "overflow",
+            EvalError::Underflow(_) => "underflow",
+            EvalError::NonFinite => "non_finite",
+            EvalError::UnknownVariable(_) => "unknown_variable",
+            EvalError::UnknownFunction(_) => "unknown_function",
+            EvalError::AssignToConstant(_) => "assign_to_constant",
+            EvalError::TypeError(_) => "type",
+            EvalError::UnitError(_) => "units",
+            EvalError::SyntaxError => "syntax",
+            EvalError::WrongArity { .. } => "wrong_arity",
+        }
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::DivisionByZero => write!(f, "Division by zero"),
+            EvalError::DomainError(e) => write!(f, "Domain error: {} is undefined", e),
+            EvalError::Overflow(e) => write!(f, "Overflow: {} is too large to represent", e),
+            EvalError::Underflow(e) => write!(f, "Underflow: {} is too small to represent", e),
+            EvalError::NonFinite => write!(f, "Operand is infinite or not a number"),
+            EvalError::UnknownVariable(name) => write!(f, "Unknown variable '{}'", name),
+            EvalError::UnknownFunction(name) => write!(f, "Unknown function '{}'", name),
+            EvalError::AssignToConstant(name) => {
+                write!(f, "Cannot assign to the constant '{}'", name)
+            }
+            EvalError::TypeError(e) => write!(f, "Type error: {}", e),
+            EvalError::UnitError(e) => write!(f, "Units error: {}", e),
+            EvalError::SyntaxError => write!(f, "Cannot evaluate an expression with syntax errors"),
+            EvalError::WrongArity {
+                name,
+                expected,
+                found,
+            } => write!(f, "{}() takes {} but {} were given", name, expected, found),
+        }
+    }
+}
+
+impl error::Error for EvalError {}
+
 // Unit tests
+#[cfg(test)]
 mod tests {
     use super::*;
     #[test]
@@ -44,7 +660,7 @@ mod tests {
         use crate::parsemaths::parser::Parser;
 
         let ast = Parser::new("1+2-3").unwrap().parse().unwrap();
-        let value = eval(ast).unwrap();
+        let value = eval(ast, &mut Environment::new()).unwrap();
         assert_eq!(value, 0.0);
     }
     #[test]
@@ -52,7 +668,494 @@ mod tests {
         use crate::parsemaths::parser::Parser;
 
         let ast = Parser::new("3+2-1*5/4").unwrap().parse().unwrap();
-        let val = eval(ast).unwrap();
+        let val = eval(ast, &mut Environment::new()).unwrap();
         assert_eq!(val, 3.75);
     }
+    #[test]
+    fn test_variables() {
+        use crate::parsemaths::parser::Parser;
+
+        let mut env = Environment::new();
+        let ast = Parser::new("let x = 2*3").unwrap().parse().unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap(), 6.0);
+        let ast = Parser::new("x + 1").unwrap().parse().unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap(), 7.0);
+        let ast = Parser::new("y").unwrap().parse().unwrap();
+        assert!(eval(ast, &mut env).is_err());
+    }
+    #[test]
+    fn test_function_calls() {
+        use crate::parsemaths::parser::Parser;
+
+        let mut env = Environment::new();
+        let ast = Parser::new("sqrt(16) + max(1, 2*pi, 3)")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            eval(ast, &mut env).unwrap(),
+            4.0 + 2.0 * std::f64::consts::PI
+        );
+        // wrong number of arguments and unknown functions are errors, not panics
+        let ast = Parser::new("sqrt(1, 2)").unwrap().parse().unwrap();
+        assert!(eval(ast, &mut env).is_err());
+        let ast = Parser::new("foo(1)").unwrap().parse().unwrap();
+        assert!(eval(ast, &mut env).is_err());
+    }
+    #[test]
+    fn test_eval_errors() {
+        use crate::parsemaths::parser::Parser;
+
+        let eval_str = |expr: &str, env: &mut Environment| {
+            eval(Parser::new(expr).unwrap().parse().unwrap(), env)
+        };
+        let mut env = Environment::new();
+        assert_eq!(eval_str("1/0", &mut env), Err(EvalError::DivisionByZero));
+        assert_eq!(eval_str("0^-1", &mut env), Err(EvalError::DivisionByZero));
+        assert!(matches!(
+            eval_str("(0-8)^0.5", &mut env),
+            Err(EvalError::DomainError(_))
+        ));
+        assert!(matches!(
+            eval_str("sqrt(0-1)", &mut env),
+            Err(EvalError::DomainError(_))
+        ));
+        assert!(matches!(
+            eval_str("ln(0)", &mut env),
+            Err(EvalError::DomainError(_))
+        ));
+        assert!(matches!(
+            eval_str("10^400", &mut env),
+            Err(EvalError::Overflow(_))
+        ));
+        assert!(matches!(
+            eval_str("exp(1000)", &mut env),
+            Err(EvalError::Overflow(_))
+        ));
+        // the numbers in the message are written as literals are, not with all their digits
+        assert_eq!(
+            eval_str("1e308*10", &mut env).unwrap_err().to_string(),
+            "Overflow: 1e308 * 10 is too large to represent"
+        );
+        assert_eq!(
+            eval_str("1e300 m * 1e20", &mut env)
+                .unwrap_err()
+                .to_string(),
+            "Overflow: 1e300 m * 1e20 a plain number is too large to represent"
+        );
+        for (expr, message) in [
+            ("1e306 Ym", "Overflow: 1e306 Ym is too large to represent"),
+            (
+                "1e20 m * 1e300 m",
+                "Overflow: 1e20 m * 1e300 m is too large to represent",
+            ),
+        ] {
+            assert_eq!(eval_str(expr, &mut env).unwrap_err().to_string(), message);
+        }
+
+        // the IEEE policy gives back infinities and NaN instead
+        env.set_policy(EvalPolicy::Ieee);
+        assert_eq!(eval_str("1/0", &mut env), Ok(Value::Real(f64::INFINITY)));
+        assert!(eval_str("(0-8)^0.5", &mut env).unwrap().to_f64().is_nan());
+        eval_str("let big = 10^400", &mut env).unwrap();
+        env.set_policy(EvalPolicy::Strict);
+        assert_eq!(eval_str("big - big", &mut env), Err(EvalError::NonFinite));
+    }
+    #[test]
+    fn test_exact_mode() {
+        use crate::parsemaths::parser::Parser;
+
+        let mut env = Environment::new();
+        let ast = Parser::new("0.1 + 0.2").unwrap().parse().unwrap();
+        assert_ne!(eval(ast.clone(), &mut env).unwrap(), 0.3);
+        env.set_mode(NumberMode::Exact);
+        let exact = eval(ast, &mut env).unwrap();
+        assert_eq!(exact.to_string(), "3/10");
+        let ast = Parser::new("(1/3)^3 * 27").unwrap().parse().unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap().to_string(), "1");
+        // literals keep digits past the 17 an f64 has
+        for (expr, expected) in [
+            ("10000000000000000.01 - 10000000000000000", "1/100"),
+            ("0.1234567890123456789 * 10^19", "1234567890123456789"),
+            (
+                "-1.000000000000000000001e-3 + 1/1000",
+                "-1/1000000000000000000000000",
+            ),
+        ] {
+            let ast = Parser::new(expr).unwrap().parse().unwrap();
+            assert_eq!(
+                eval(ast, &mut env).unwrap().to_string(),
+                expected,
+                "{}",
+                expr
+            );
+        }
+        // and the other modes take the nearest f64
+        env.set_mode(NumberMode::Real);
+        let ast = Parser::new("0.1234567890123456789")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(
+            eval(ast, &mut env),
+            Ok(Value::Real(0.123_456_789_012_345_68))
+        );
+        env.set_mode(NumberMode::Exact);
+        // functions and constants are floating point, which makes the whole result floating point
+        let ast = Parser::new("sqrt(4) / 4").unwrap().parse().unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap(), Value::Real(0.5));
+    }
+    #[test]
+    fn test_complex_mode() {
+        use crate::parsemaths::parser::Parser;
+
+        let mut env = Environment::new();
+        let ast = Parser::new("(1 + 2i) * (3 - i)").unwrap().parse().unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap().to_string(), "5 + 5i");
+        // without the complex mode sqrt(-1) has no value
+        let ast = Parser::new("sqrt(0-1)").unwrap().parse().unwrap();
+        assert!(matches!(
+            eval(ast.clone(), &mut env),
+            Err(EvalError::DomainError(_))
+        ));
+        env.set_mode(NumberMode::Complex);
+        assert_eq!(eval(ast, &mut env).unwrap().to_string(), "i");
+        let ast = Parser::new("abs(3 + 4i) + re(2.5i) + im(2.5i)")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(eval(ast, &mut env).unwrap(), Value::Real(7.5));
+        let ast = Parser::new("floor(i)").unwrap().parse().unwrap();
+        assert!(eval(ast, &mut env).is_err());
+    }
+    #[test]
+    fn test_integer_mode() {
+        use crate::parsemaths::environment::IntWidth;
+        use crate::parsemaths::parser::Parser;
+
+        let eval_str = |expr: &str, env: &mut Environment| {
+            eval(Parser::new(expr).unwrap().parse().unwrap(), env)
+        };
+        let mut env = Environment::new();
+        env.set_mode(NumberMode::Integer(IntWidth::U64));
+        // every digit is kept above 2^53, where an f64 starts rounding
+        assert_eq!(
+            eval_str("0xffff_ffff_ffff_fff0 + 0b1111", &mut env),
+            Ok(Value::Integer(u64::MAX as i128))
+        );
+        assert_eq!(
+            eval_str("1 | 2 xor 7 & 12 << 1", &mut env),
+            // 1 | (2 xor (7 & (12 << 1)))
+            Ok(Value::Integer(3))
+        );
+        assert_eq!(
+            eval_str("max(3, 17 % 5) + 7 // 2", &mut env),
+            Ok(Value::Integer(6))
+        );
+        assert!(matches!(
+            eval_str("0xffff_ffff_ffff_ffff + 1", &mut env),
+            Err(EvalError::Overflow(_))
+        ));
+        assert_eq!(
+            eval_str("0 - 1", &mut env),
+            Err(EvalError::Underflow("0 - 1".into()))
+        );
+        assert_eq!(
+            eval_str("-3", &mut env),
+            Err(EvalError::Underflow("-3".into()))
+        );
+        assert_eq!(eval_str("-0", &mut env), Ok(Value::Integer(0)));
+        assert_eq!(
+            eval_str("-18446744073709551615", &mut env),
+            Err(EvalError::Underflow("-18446744073709551615".into()))
+        );
+        env.set_mode(NumberMode::Integer(IntWidth::I64));
+        assert!(matches!(
+            eval_str("0xffff_ffff_ffff_ffff", &mut env),
+            Err(EvalError::Overflow(_))
+        ));
+        assert_eq!(eval_str("-~5", &mut env), Ok(Value::Integer(6)));
+        // the smallest i64 can be written, although it is only negative once negated
+        assert_eq!(
+            eval_str("-9223372036854775808", &mut env),
+            Ok(Value::Integer(i64::MIN as i128))
+        );
+        assert_eq!(
+            eval_str("-0x8000_0000_0000_0000", &mut env),
+            Ok(Value::Integer(i64::MIN as i128))
+        );
+        assert_eq!(
+            eval_str("-9223372036854775808 - 1", &mut env),
+            Err(EvalError::Underflow("-9223372036854775808 - 1".into()))
+        );
+        // only the literal itself is negated before the check, a second minus is an operation
+        assert_eq!(
+            eval_str("-9223372036854775809", &mut env),
+            Err(EvalError::Underflow("-9223372036854775809".into()))
+        );
+        assert_eq!(
+            eval_str("--9223372036854775808", &mut env),
+            Err(EvalError::Overflow("--9223372036854775808".into()))
+        );
+        assert_eq!(
+            eval_str("2 * -9223372036854775808", &mut env),
+            Err(EvalError::Underflow("2 * -9223372036854775808".into()))
+        );
+        assert!(matches!(
+            eval_str("9223372036854775808", &mut env),
+            Err(EvalError::Overflow(_))
+        ));
+        // a literal with an exponent is written in the message as it was typed
+        assert_eq!(
+            eval_str("1e30", &mut env),
+            Err(EvalError::Overflow("1e30".into()))
+        );
+        assert_eq!(
+            eval_str("-1e30", &mut env),
+            Err(EvalError::Underflow("-1e30".into()))
+        );
+        assert_eq!(eval_str("7 / 0", &mut env), Err(EvalError::DivisionByZero));
+        assert!(matches!(
+            eval_str("2.5 * 2", &mut env),
+            Err(EvalError::TypeError(_))
+        ));
+        assert!(matches!(
+            eval_str("sqrt(4)", &mut env),
+            Err(EvalError::TypeError(_))
+        ));
+    }
+    #[test]
+    fn test_conditions() {
+        use crate::parsemaths::parser::Parser;
+
+        let eval_str = |expr: &str, env: &mut Environment| {
+            eval(Parser::new(expr).unwrap().parse().unwrap(), env)
+        };
+        let mut env = Environment::new();
+        eval_str("let load = 0.9", &mut env).unwrap();
+        eval_str("let mem = 0.25", &mut env).unwrap();
+        assert_eq!(
+            eval_str("load > 0.8 && mem < 0.5", &mut env),
+            Ok(Value::Bool(true))
+        );
+        assert_eq!(
+            eval_str("!(load <= 0.8) ? 2 : 1/0", &mut env),
+            Ok(Value::Real(2.0))
+        );
+        assert_eq!(
+            eval_str("1 + 1 == 2 != false", &mut env),
+            Ok(Value::Bool(true))
+        );
+        // the right side is not evaluated once the left side decides the result
+        assert_eq!(
+            eval_str("false && unknown > 1", &mut env),
+            Ok(Value::Bool(false))
+        );
+        // numbers and booleans do not mix
+        for expr in [
+            "1 + true",
+            "!2",
+            "load && true",
+            "sqrt(true)",
+            "true < false",
+            "2 == true",
+        ] {
+            assert!(
+                matches!(eval_str(expr, &mut env), Err(EvalError::TypeError(_))),
+                "{}",
+                expr
+            );
+        }
+        // an exact value compares equal to the same value in floating point
+        env.set_mode(NumberMode::Exact);
+        assert_eq!(eval_str("1/4 == 0.25", &mut env), Ok(Value::Bool(true)));
+        assert!(eval_str("i < 1", &mut env).is_err());
+    }
+
+    fn display(expr: &str) -> String {
+        use crate::parsemaths::parser::Parser;
+
+        Parser::new(expr).unwrap().parse().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(display("((2*x)+1)"), "2 * x + 1");
+        assert_eq!(display("a - (b - c) - d"), "a - (b - c) - d");
+        assert_eq!(display("(a + b) * -(c ^ 2)"), "(a + b) * -c ^ 2");
+        assert_eq!(display("(a ^ b) ^ c ^ (-d) ^ 2"), "(a ^ b) ^ c ^ (-d) ^ 2");
+        assert_eq!(
+            display("(a ? b : c) ? d : (e ? f : g)"),
+            "(a ? b : c) ? d : e ? f : g"
+        );
+        assert_eq!(display("!(x < 1) || y & 3 == 1"), "!(x < 1) || y & 3 == 1");
+        assert_eq!(display("let y = max((1), 2 + 3)"), "let y = max(1, 2 + 3)");
+        assert_eq!(
+            display("0xff + 18446744073709551615"),
+            "0xff + 18446744073709551615"
+        );
+        assert_eq!(display("10000000000000000.0 * 2.5i"), "1e16 * 2.5i");
+        assert_eq!(
+            display("9007199254740994.0 + 0.000000001"),
+            "9007199254740994.0 + 1e-9"
+        );
+        assert_eq!(
+            display("(a ? 2 m : (3 ft to m)) to cm"),
+            "a ? 2 m : (3 ft to m) to cm"
+        );
+        // infinity is written as a literal too large for an f64, which reads back as infinity
+        assert_eq!(display("1e400 + 1e300i"), "1e400 + 1e300i");
+        assert_eq!(Node::Imaginary(f64::INFINITY).to_string(), "1e400i");
+        assert_eq!(Node::Number(f64::NEG_INFINITY).to_string(), "-1e400");
+        assert_eq!(Node::Number(f64::NAN).to_string(), "NaN");
+    }
+
+    /*
+    Reference values for precedence and associativity, as written in maths texts and
+    given by most calculators, with x = 3 for the implicit products
+    */
+    #[test]
+    fn test_conformance() {
+        use crate::parsemaths::parser::{Negation, Parser, Products, Syntax};
+
+        let reference = [
+            ("-2^2", -4.0),
+            ("2^3^2", 512.0),
+            ("(-2)^2", 4.0),
+            ("2^-1", 0.5),
+            ("-2^-2", -0.25),
+            ("2^-2^2", 0.0625),
+            ("2*3^2", 18.0),
+            ("-3*2^2", -12.0),
+            ("--2", 2.0),
+            ("10-4-3", 3.0),
+            ("100/10/5", 2.0),
+            ("2^2*3", 12.0),
+            ("-x^2", -9.0),
+            ("2x^2", 18.0),
+            ("1/2x", 1.5),
+        ];
+        let mut env = Environment::new();
+        env.set("x", Value::Real(3.0));
+        for (expr, expected) in reference {
+            let ast = Parser::new(expr).unwrap().parse().unwrap();
+            // printing the tree and reading it back must not change the grouping
+            let reparsed = Parser::new(&ast.to_string()).unwrap().parse().unwrap();
+            assert_eq!(reparsed, ast, "{}", expr);
+            assert_eq!(eval(ast, &mut env), Ok(Value::Real(expected)), "{}", expr);
+        }
+
+        // the opposite convention, where the minus belongs to the number
+        let syntax = Syntax {
+            products: Products::Implicit,
+            negation: Negation::MinusFirst,
+        };
+        for (expr, expected) in [
+            ("-2^2", 4.0),
+            ("-x^2", 9.0),
+            ("2^-1^2", 2.0),
+            ("-2*3", -6.0),
+            ("2^-2^2", 16.0),
+            ("-2^-2", 0.25),
+            ("-2^2!", 4.0),
+            ("--2^2", 4.0),
+        ] {
+            let ast = Parser::with_syntax(expr, syntax).unwrap().parse().unwrap();
+            // the tree is printed in the default convention, so it reads back the same
+            let reparsed = Parser::new(&ast.to_string()).unwrap().parse().unwrap();
+            assert_eq!(reparsed, ast, "{}", expr);
+            assert_eq!(eval(ast, &mut env), Ok(Value::Real(expected)), "{}", expr);
+        }
+
+        // '√' groups with '^' as '-' does, which the values of √4^2 cannot tell apart
+        for (negation, expected) in [
+            (Negation::PowerFirst, "sqrt(4 ^ 2)"),
+            (Negation::MinusFirst, "sqrt(4) ^ 2"),
+        ] {
+            let syntax = Syntax { negation, ..syntax };
+            let ast = Parser::with_syntax("√4^2", syntax)
+                .unwrap()
+                .parse()
+                .unwrap();
+            assert_eq!(ast.to_string(), expected);
+        }
+    }
+
+    /* Next number below n from a xorshift generator, so the same trees are tested on every run */
+    fn next(seed: &mut u64, n: u64) -> u64 {
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        *seed % n
+    }
+
+    /* Random tree using every kind of node the parser can produce, up to the given depth */
+    fn random_tree(seed: &mut u64, depth: u32) -> Node {
+        use self::Node::*;
+        let unit = |text: &str| units::read_unit(text).unwrap().0;
+        let kind = if depth == 0 || next(seed, 4) == 0 {
+            next(seed, 7)
+        } else {
+            7 + next(seed, 30)
+        };
+        let pick = next(seed, 4) as usize;
+        let number = [0.5, 2.0, 1e16, 6.02e-23, 1e300, f64::INFINITY][next(seed, 6) as usize];
+        let mut sub = || Box::new(random_tree(seed, depth - 1));
+        match kind {
+            0 => Number(number),
+            1 if pick < 2 => Integer([255, u64::MAX][pick]),
+            1 => Decimal(["0.1234567890123456789", "10000000000000000.01"][pick % 2].to_string()),
+            2 => Imaginary(number),
+            3 => Bool(true),
+            4 | 5 => Variable(["x", "y_2"][pick % 2].to_string()),
+            6 => Quantity(2.5, unit(["km/h", "m/s^2", "dBm", "ft"][pick])),
+            7 => Add(sub(), sub()),
+            8 => Subtract(sub(), sub()),
+            9 => Multiply(sub(), sub()),
+            10 => Divide(sub(), sub()),
+            11 => FloorDivide(sub(), sub()),
+            12 => Modulo(sub(), sub()),
+            13 => Caret(sub(), sub()),
+            14 => BitAnd(sub(), sub()),
+            15 => BitOr(sub(), sub()),
+            16 => BitXor(sub(), sub()),
+            17 => ShiftLeft(sub(), sub()),
+            18 => ShiftRight(sub(), sub()),
+            19 => Equal(sub(), sub()),
+            20 => NotEqual(sub(), sub()),
+            21 => Less(sub(), sub()),
+            22 => LessEqual(sub(), sub()),
+            23 => Greater(sub(), sub()),
+            24 => GreaterEqual(sub(), sub()),
+            25 => And(sub(), sub()),
+            26 => Or(sub(), sub()),
+            27 => Negative(sub()),
+            28 => BitNot(sub()),
+            29 => Not(sub()),
+            30 | 31 => Conditional(sub(), sub(), sub()),
+            32 => Call("max".to_string(), vec![*sub(), *sub()]),
+            33 => Call("sin".to_string(), vec![*sub()]),
+            34 => Convert(sub(), unit("mi")),
+            35 => Percent(sub()),
+            _ => Call("pi".to_string(), Vec::new()),
+        }
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        use crate::parsemaths::parser::Parser;
+
+        let mut seed = 0x2545_f491_4f6c_dd1d;
+        for _ in 0..2000 {
+            let tree = random_tree(&mut seed, 5);
+            let text = tree.to_string();
+            let mut parser = Parser::new(&text).unwrap();
+            let parsed = parser.parse().unwrap();
+            assert_eq!(parsed, tree, "{}", text);
+            assert!(parser.span_tree(&parsed).is_some(), "{}", text);
+        }
+        let tree = Node::Assign("z".to_string(), Box::new(random_tree(&mut seed, 5)));
+        let text = tree.to_string();
+        assert_eq!(Parser::new(&text).unwrap().parse().unwrap(), tree);
+    }
 }
@@ -0,0 +1,433 @@
+// in bigint.rs - providing code for arbitrary precision integers used by exact arithmetic
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/*
+Arbitrary precision signed integer
+The magnitude is stored as base 2^32 digits ("limbs"), least significant first, with no leading
+zero limbs - so zero is an empty Vec and is never negative
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+impl BigInt {
+    pub fn zero() -> Self {
+        BigInt {
+            negative: false,
+            magnitude: Vec::new(),
+        }
+    }
+
+    pub fn from_u64(n: u64) -> Self {
+        let mut value = BigInt {
+            negative: false,
+            magnitude: vec![n as u32, (n >> 32) as u32],
+        };
+        value.normalise();
+        value
+    }
+
+    pub fn from_i128(n: i128) -> Self {
+        let magnitude = n.unsigned_abs();
+        let value = BigInt::from_u64((magnitude >> 64) as u64)
+            .shl(64)
+            .add(&BigInt::from_u64(magnitude as u64));
+        if n < 0 {
+            value.neg()
+        } else {
+            value
+        }
+    }
+
+    /*
+    Parses a string of decimal digits with an optional leading '-'
+    Returns None if any other character is present
+    */
+    pub fn parse(digits: &str) -> Option<Self> {
+        let (negative, digits) = match digits.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, digits),
+        };
+        if digits.is_empty() {
+            return None;
+        }
+        let mut value = BigInt::zero();
+        for c in digits.chars() {
+            let digit = c.to_digit(10)?;
+            value = value.mul_small(10).add(&BigInt::from_u64(digit as u64));
+        }
+        if negative {
+            value = value.neg();
+        }
+        Some(value)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.magnitude.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> Self {
+        BigInt {
+            negative: false,
+            magnitude: self.magnitude.clone(),
+        }
+    }
+
+    pub fn neg(&self) -> Self {
+        let mut value = self.clone();
+        value.negative = !value.negative;
+        value.normalise();
+        value
+    }
+
+    /* Number of significant bits in the magnitude */
+    pub fn bits(&self) -> usize {
+        match self.magnitude.last() {
+            Some(top) => self.magnitude.len() * 32 - top.leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    pub fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            let mut value = BigInt {
+                negative: self.negative,
+                magnitude: add_magnitude(&self.magnitude, &other.magnitude),
+            };
+            value.normalise();
+            return value;
+        }
+        // signs differ - subtract the smaller magnitude from the larger one
+        match compare_magnitude(&self.magnitude, &other.magnitude) {
+            Ordering::Equal => BigInt::zero(),
+            Ordering::Greater => {
+                let mut value = BigInt {
+                    negative: self.negative,
+                    magnitude: sub_magnitude(&self.magnitude, &other.magnitude),
+                };
+                value.normalise();
+                value
+            }
+            Ordering::Less => {
+                let mut value = BigInt {
+                    negative: other.negative,
+                    magnitude: sub_magnitude(&other.magnitude, &self.magnitude),
+                };
+                value.normalise();
+                value
+            }
+        }
+    }
+
+    pub fn sub(&self, other: &BigInt) -> BigInt {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &BigInt) -> BigInt {
+        let mut magnitude = vec![0u32; self.magnitude.len() + other.magnitude.len()];
+        for (i, &a) in self.magnitude.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.magnitude.iter().enumerate() {
+                let current = magnitude[i + j] as u64 + a as u64 * b as u64 + carry;
+                magnitude[i + j] = current as u32;
+                carry = current >> 32;
+            }
+            magnitude[i + other.magnitude.len()] = carry as u32;
+        }
+        let mut value = BigInt {
+            negative: self.negative != other.negative,
+            magnitude,
+        };
+        value.normalise();
+        value
+    }
+
+    fn mul_small(&self, factor: u32) -> BigInt {
+        self.mul(&BigInt::from_u64(factor as u64))
+    }
+
+    /*
+    Truncating division, returning (quotient, remainder) like the / and % operators on i64
+    Uses binary long division, which is simple and fast enough for calculator sized numbers
+    Panics on division by zero, callers check for it first
+    */
+    pub fn div_rem(&self, divisor: &BigInt) -> (BigInt, BigInt) {
+        assert!(!divisor.is_zero(), "BigInt division by zero");
+        if divisor.magnitude.len() == 1 {
+            return self.div_rem_small(divisor);
+        }
+        let mut quotient = vec![0u32; self.magnitude.len()];
+        let mut remainder = BigInt::zero();
+        for bit in (0..self.bits()).rev() {
+            remainder = remainder.shl(1);
+            if self.magnitude[bit / 32] >> (bit % 32) & 1 == 1 {
+                remainder = remainder.add(&BigInt::from_u64(1));
+            }
+            if compare_magnitude(&remainder.magnitude, &divisor.magnitude) != Ordering::Less {
+                remainder.magnitude = sub_magnitude(&remainder.magnitude, &divisor.magnitude);
+                remainder.normalise();
+                quotient[bit / 32] |= 1 << (bit % 32);
+            }
+        }
+        let mut quotient = BigInt {
+            negative: self.negative != divisor.negative,
+            magnitude: quotient,
+        };
+        quotient.normalise();
+        remainder.negative = self.negative;
+        remainder.normalise();
+        (quotient, remainder)
+    }
+
+    /* Short division by a single limb divisor, one limb of the dividend at a time */
+    fn div_rem_small(&self, divisor: &BigInt) -> (BigInt, BigInt) {
+        let small = divisor.magnitude[0] as u64;
+        let mut quotient = vec![0u32; self.magnitude.len()];
+        let mut remainder = 0u64;
+        for (i, &limb) in self.magnitude.iter().enumerate().rev() {
+            let current = remainder << 32 | limb as u64;
+            quotient[i] = (current / small) as u32;
+            remainder = current % small;
+        }
+        let mut quotient = BigInt {
+            negative: self.negative != divisor.negative,
+            magnitude: quotient,
+        };
+        quotient.normalise();
+        let mut remainder = BigInt::from_u64(remainder);
+        remainder.negative = self.negative;
+        remainder.normalise();
+        (quotient, remainder)
+    }
+
+    /* Greatest common divisor, always non-negative */
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let (_, remainder) = a.div_rem(&b);
+            a = b;
+            b = remainder;
+        }
+        a
+    }
+
+    pub fn pow(&self, exponent: u32) -> BigInt {
+        let mut result = BigInt::from_u64(1);
+        let mut base = self.clone();
+        let mut exponent = exponent;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            base = base.mul(&base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /* Multiplies by 2^shift */
+    pub fn shl(&self, shift: usize) -> BigInt {
+        let (limbs, bits) = (shift / 32, shift % 32);
+        let mut magnitude = vec![0u32; limbs];
+        let mut carry = 0u32;
+        for &limb in &self.magnitude {
+            if bits == 0 {
+                magnitude.push(limb);
+            } else {
+                magnitude.push(limb << bits | carry);
+                carry = limb >> (32 - bits);
+            }
+        }
+        magnitude.push(carry);
+        let mut value = BigInt {
+            negative: self.negative,
+            magnitude,
+        };
+        value.normalise();
+        value
+    }
+
+    /*
+    Converts to i64, or None if the value is outside its range
+    Used for values that are expected to be small e.g. exponents
+    */
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.bits() > 63 {
+            return None;
+        }
+        let mut value = 0i64;
+        for &limb in self.magnitude.iter().rev() {
+            value = value << 32 | limb as i64;
+        }
+        Some(if self.negative { -value } else { value })
+    }
+
+    /* Nearest f64 - the top 64 bits are rounded once and then scaled by the remaining bits */
+    pub fn to_f64(&self) -> f64 {
+        let bits = self.bits();
+        let shift = bits.saturating_sub(64);
+        let top = if shift == 0 {
+            self.abs()
+        } else {
+            self.abs().div_rem(&BigInt::from_u64(1).shl(shift)).0
+        };
+        let mut value = 0f64;
+        for &limb in top.magnitude.iter().rev() {
+            value = value * 4294967296.0 + limb as f64;
+        }
+        value *= 2f64.powi(shift.min(i32::MAX as usize) as i32);
+        if self.negative {
+            -value
+        } else {
+            value
+        }
+    }
+
+    /* Removes leading zero limbs and makes sure zero is never negative */
+    fn normalise(&mut self) {
+        while self.magnitude.last() == Some(&0) {
+            self.magnitude.pop();
+        }
+        if self.magnitude.is_empty() {
+            self.negative = false;
+        }
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitude(&self.magnitude, &other.magnitude),
+            (true, true) => compare_magnitude(&other.magnitude, &self.magnitude),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/*
+Prints the value in decimal
+The magnitude is repeatedly divided by 10^9 so each remainder gives 9 decimal digits at once
+*/
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        let billion = BigInt::from_u64(1_000_000_000);
+        let mut chunks = Vec::new();
+        let mut value = self.abs();
+        while !value.is_zero() {
+            let (quotient, remainder) = value.div_rem(&billion);
+            chunks.push(remainder.to_i64().unwrap_or(0));
+            value = quotient;
+        }
+        let mut text = String::new();
+        if self.negative {
+            text.push('-');
+        }
+        text.push_str(&chunks.pop().unwrap_or(0).to_string());
+        for chunk in chunks.iter().rev() {
+            text.push_str(&format!("{:09}", chunk));
+        }
+        f.pad(&text)
+    }
+}
+
+fn compare_magnitude(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push(sum as u32);
+        carry = sum >> 32;
+    }
+    result.push(carry as u32);
+    result
+}
+
+/* a - b where the magnitude of a is at least that of b */
+fn sub_magnitude(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &limb) in a.iter().enumerate() {
+        let mut diff = limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if diff < 0 {
+            diff += 1 << 32;
+            borrow = 1;
+        }
+        result.push(diff as u32);
+    }
+    result
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(digits: &str) -> BigInt {
+        BigInt::parse(digits).unwrap()
+    }
+
+    #[test]
+    fn test_parse_and_display() {
+        let text = "-123456789012345678901234567890";
+        assert_eq!(big(text).to_string(), text);
+        assert_eq!(big("000").to_string(), "0");
+        assert_eq!(BigInt::parse("12a"), None);
+        assert_eq!(
+            BigInt::from_i128(i128::MIN).to_string(),
+            i128::MIN.to_string()
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(a.add(&b).to_string(), "-864197532086419753208641975320");
+        assert_eq!(a.sub(&b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(
+            a.mul(&b).to_string(),
+            "-121932631137021795226185032733622923332237463801111263526900"
+        );
+        let (quotient, remainder) = b.div_rem(&a);
+        assert_eq!(
+            (quotient.to_string(), remainder.to_string()),
+            ("-8".into(), "-9000000000900000000090".into())
+        );
+        assert_eq!(big("84").gcd(&big("-36")).to_string(), "12");
+        assert_eq!(
+            big("2").pow(100).to_string(),
+            "1267650600228229401496703205376"
+        );
+    }
+
+    #[test]
+    fn test_to_f64() {
+        assert_eq!(big("-12345").to_f64(), -12345.0);
+        assert_eq!(big("2").pow(200).to_f64(), 2f64.powi(200));
+    }
+}
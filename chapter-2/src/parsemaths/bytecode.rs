@@ -0,0 +1,503 @@
+// in bytecode.rs - providing code for compiling an AST to bytecode and running it on a stack machine
+
+use crate::parsemaths::ast::{
+    call_builtin, compare_values, eval_literal, expect_bool, expect_number, EvalError, Node,
+};
+use crate::parsemaths::environment::Environment;
+use crate::parsemaths::functions::{self, Builtin};
+use crate::parsemaths::units::{self, Unit};
+use crate::parsemaths::value::Value;
+use std::cmp::Ordering;
+
+/* An operator taking two numbers e.g. Value::add */
+pub type BinaryOp = fn(Value, Value, &Environment) -> Result<Value, EvalError>;
+
+/*
+One step of a compiled program, working on a stack of values
+Jumps hold the index of the instruction to go to
+*/
+#[derive(Debug, Clone)]
+pub enum Instruction {
+    Push(Value),
+    // value of a variable, from its slot
+    Load(usize),
+    // value of a variable used as a number - a TypeError if it holds a boolean
+    LoadNumber(usize),
+    // copies the top of the stack into a slot, leaving it on the stack
+    Store(usize),
+    CheckNumber,
+    CheckBool,
+    Binary(BinaryOp),
+    Negative,
+    Percent,
+    BitNot,
+    Not,
+    Equal,
+    NotEqual,
+    Compare(fn(Ordering) -> bool),
+    // calls a function on the given number of values from the top of the stack
+    Call(&'static Builtin, usize),
+    // shows the top of the stack in a unit e.g. 60 mph to m/s
+    Convert(Unit),
+    Jump(usize),
+    // pops the top of the stack and jumps if it is false
+    JumpIfFalse(usize),
+    // jumps if the top of the stack decides && or ||, otherwise pops it
+    JumpIfFalseOrPop(usize),
+    JumpIfTrueOrPop(usize),
+    // an error found while compiling, raised only if this point is reached e.g. false && foo(1)
+    Fail(EvalError),
+}
+
+/*
+A compiled expression, which can be run many times without cloning or walking the AST
+Variables are numbered slots rather than names, so running the program for new inputs only
+needs new values in the slots
+Literals are converted for the number mode and policy of the environment it is compiled with,
+so it must be compiled again after either is changed
+*/
+#[derive(Debug, Clone)]
+pub struct Program {
+    code: Vec<Instruction>,
+    names: Vec<String>,
+}
+
+/*
+Compiles an AST to a program that gives the same value, or the same error, as ast::eval
+Problems such as an unknown function become a Fail instruction, so they are only errors if
+they are reached
+*/
+pub fn compile(expr: &Node, env: &Environment) -> Program {
+    let mut program = Program {
+        code: Vec::new(),
+        names: Vec::new(),
+    };
+    program.compile(expr, env);
+    program
+}
+
+impl Program {
+    /* Slot of the variable name, if the program uses it */
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|other| other == name)
+    }
+
+    /*
+    Values for the slots from env, with the built-in constants such as pi for names it does not
+    hold - None for unknown variables, which are errors only if they are used
+    */
+    pub fn bind(&self, env: &Environment) -> Vec<Option<Value>> {
+        self.names
+            .iter()
+            .map(|name| {
+                env.get(name)
+                    .or_else(|| functions::constant(name).map(Value::Real))
+            })
+            .collect()
+    }
+
+    /* Runs the program with the variables in env, saving any assignment back to env */
+    pub fn eval(&self, env: &mut Environment) -> Result<Value, EvalError> {
+        let bound = self.bind(env);
+        let mut slots = bound.clone();
+        let val = self.run(&mut slots, env)?;
+        for (name, (before, after)) in self.names.iter().zip(bound.iter().zip(slots)) {
+            match after {
+                Some(after) if *before != Some(after.clone()) => env.set(name, after),
+                _ => (),
+            }
+        }
+        Ok(val)
+    }
+
+    /*
+    Runs the program with the variables in slots (see Program::bind)
+    env only supplies the number mode and policy used by the operators
+    */
+    pub fn run(&self, slots: &mut [Option<Value>], env: &Environment) -> Result<Value, EvalError> {
+        use self::Instruction::*;
+        let mut stack: Vec<Value> = Vec::with_capacity(self.code.len());
+        let mut pc = 0;
+        while let Some(instruction) = self.code.get(pc) {
+            pc += 1;
+            match instruction {
+                Push(val) => stack.push(val.clone()),
+                Load(slot) => stack.push(self.load(slots, *slot)?),
+                LoadNumber(slot) => stack.push(expect_number(self.load(slots, *slot)?)?),
+                Store(slot) => slots[*slot] = stack.last().cloned(),
+                CheckNumber => {
+                    let val = pop(&mut stack);
+                    stack.push(expect_number(val)?);
+                }
+                CheckBool => {
+                    let val = pop(&mut stack);
+                    stack.push(Value::Bool(expect_bool(val)?));
+                }
+                Binary(op) => {
+                    let (a, b) = pop_pair(&mut stack);
+                    stack.push(op(a, b, env)?);
+                }
+                Negative => {
+                    let val = pop(&mut stack);
+                    stack.push(val.neg(env)?);
+                }
+                Percent => {
+                    let val = pop(&mut stack);
+                    stack.push(val.percent(env)?);
+                }
+                BitNot => {
+                    let val = pop(&mut stack);
+                    stack.push(val.bit_not(env)?);
+                }
+                Not => {
+                    let val = pop(&mut stack);
+                    stack.push(Value::Bool(!expect_bool(val)?));
+                }
+                Equal => {
+                    let (a, b) = pop_pair(&mut stack);
+                    stack.push(Value::Bool(a.equals(&b)?));
+                }
+                NotEqual => {
+                    let (a, b) = pop_pair(&mut stack);
+                    stack.push(Value::Bool(!a.equals(&b)?));
+                }
+                Compare(test) => {
+                    let (a, b) = pop_pair(&mut stack);
+                    stack.push(compare_values(a, b, *test)?);
+                }
+                Convert(unit) => {
+                    let val = pop(&mut stack);
+                    stack.push(units::convert(val, unit, env)?);
+                }
+                Call(builtin, count) => {
+                    let start = stack.len() - count;
+                    let val = call_builtin(builtin, &stack[start..], env)?;
+                    stack.truncate(start);
+                    stack.push(val);
+                }
+                Jump(target) => pc = *target,
+                JumpIfFalse(target) => {
+                    if pop(&mut stack) == Value::Bool(false) {
+                        pc = *target;
+                    }
+                }
+                JumpIfFalseOrPop(target) | JumpIfTrueOrPop(target) => {
+                    let decides = matches!(instruction, JumpIfTrueOrPop(_));
+                    if stack.last() == Some(&Value::Bool(decides)) {
+                        pc = *target;
+                    } else {
+                        stack.pop();
+                    }
+                }
+                Fail(e) => return Err(e.clone()),
+            }
+        }
+        Ok(pop(&mut stack))
+    }
+
+    fn load(&self, slots: &[Option<Value>], slot: usize) -> Result<Value, EvalError> {
+        slots[slot]
+            .clone()
+            .ok_or_else(|| EvalError::UnknownVariable(self.names[slot].clone()))
+    }
+
+    /* Slot of the variable name, giving it a new slot the first time it is seen */
+    fn slot_for(&mut self, name: &str) -> usize {
+        match self.slot(name) {
+            Some(slot) => slot,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    /* Points the jump at index at to the next instruction to be emitted */
+    fn patch(&mut self, at: usize) {
+        let next = self.code.len();
+        match &mut self.code[at] {
+            Instruction::Jump(target)
+            | Instruction::JumpIfFalse(target)
+            | Instruction::JumpIfFalseOrPop(target)
+            | Instruction::JumpIfTrueOrPop(target) => *target = next,
+            other => unreachable!("{:?} is not a jump", other),
+        }
+    }
+
+    /* Emits the code for expr, which leaves its value on the stack */
+    fn compile(&mut self, expr: &Node, env: &Environment) {
+        use self::Node::*;
+        match expr {
+            Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) => {
+                self.literal(expr, env)
+            }
+            // a negated number is a literal, so -9223372036854775808 fits in an i64
+            Negative(a) if matches!(**a, Number(_) | Integer(_)) => self.literal(expr, env),
+            Bool(b) => {
+                self.emit(Instruction::Push(Value::Bool(*b)));
+            }
+            Variable(name) => {
+                let slot = self.slot_for(name);
+                self.emit(Instruction::Load(slot));
+            }
+            Add(a, b) => self.binary(a, b, Value::add, env),
+            Subtract(a, b) => self.binary(a, b, Value::sub, env),
+            Multiply(a, b) => self.binary(a, b, Value::mul, env),
+            Divide(a, b) => self.binary(a, b, Value::div, env),
+            FloorDivide(a, b) => self.binary(a, b, Value::floor_div, env),
+            Modulo(a, b) => self.binary(a, b, Value::modulo, env),
+            Caret(a, b) => self.binary(a, b, Value::pow, env),
+            BitAnd(a, b) => self.binary(a, b, Value::bit_and, env),
+            BitOr(a, b) => self.binary(a, b, Value::bit_or, env),
+            BitXor(a, b) => self.binary(a, b, Value::bit_xor, env),
+            ShiftLeft(a, b) => self.binary(a, b, Value::shl, env),
+            ShiftRight(a, b) => self.binary(a, b, Value::shr, env),
+            Negative(a) => {
+                self.compile_number(a, env);
+                self.emit(Instruction::Negative);
+            }
+            Percent(a) => {
+                self.compile_number(a, env);
+                self.emit(Instruction::Percent);
+            }
+            BitNot(a) => {
+                self.compile_number(a, env);
+                self.emit(Instruction::BitNot);
+            }
+            Not(a) => {
+                self.compile(a, env);
+                self.emit(Instruction::Not);
+            }
+            Convert(a, unit) => {
+                self.compile_number(a, env);
+                self.emit(Instruction::Convert(unit.clone()));
+            }
+            Error(_) => {
+                self.emit(Instruction::Fail(EvalError::SyntaxError));
+            }
+            Equal(a, b) | NotEqual(a, b) => {
+                self.compile(a, env);
+                self.compile(b, env);
+                self.emit(match expr {
+                    Equal(..) => Instruction::Equal,
+                    _ => Instruction::NotEqual,
+                });
+            }
+            Less(a, b) => self.comparison(a, b, Ordering::is_lt, env),
+            LessEqual(a, b) => self.comparison(a, b, Ordering::is_le, env),
+            Greater(a, b) => self.comparison(a, b, Ordering::is_gt, env),
+            GreaterEqual(a, b) => self.comparison(a, b, Ordering::is_ge, env),
+            // the right side is skipped when the left side decides the result
+            And(a, b) | Or(a, b) => {
+                self.compile_bool(a, env);
+                let jump = self.emit(match expr {
+                    And(..) => Instruction::JumpIfFalseOrPop(0),
+                    _ => Instruction::JumpIfTrueOrPop(0),
+                });
+                self.compile_bool(b, env);
+                self.patch(jump);
+            }
+            Conditional(cond, a, b) => {
+                self.compile_bool(cond, env);
+                let to_else = self.emit(Instruction::JumpIfFalse(0));
+                self.compile(a, env);
+                let to_end = self.emit(Instruction::Jump(0));
+                self.patch(to_else);
+                self.compile(b, env);
+                self.patch(to_end);
+            }
+            Assign(name, a) => {
+                if functions::constant(name).is_some() {
+                    self.emit(Instruction::Fail(EvalError::AssignToConstant(name.clone())));
+                    return;
+                }
+                self.compile(a, env);
+                let slot = self.slot_for(name);
+                self.emit(Instruction::Store(slot));
+            }
+            Call(name, args) => {
+                let builtin = match functions::lookup(name) {
+                    Some(builtin) => builtin,
+                    None => {
+                        self.emit(Instruction::Fail(EvalError::UnknownFunction(name.clone())));
+                        return;
+                    }
+                };
+                if !builtin.arity.accepts(args.len()) {
+                    self.emit(Instruction::Fail(EvalError::WrongArity {
+                        name: name.clone(),
+                        expected: builtin.arity,
+                        found: args.len(),
+                    }));
+                    return;
+                }
+                for arg in args {
+                    self.compile_number(arg, env);
+                }
+                self.emit(Instruction::Call(builtin, args.len()));
+            }
+        }
+    }
+
+    /* Emits the value of a literal, or the error converting it gives when the code is run */
+    fn literal(&mut self, expr: &Node, env: &Environment) {
+        self.emit(match eval_literal(expr, env) {
+            Ok(val) => Instruction::Push(val),
+            Err(e) => Instruction::Fail(e),
+        });
+    }
+
+    fn binary(&mut self, a: &Node, b: &Node, op: BinaryOp, env: &Environment) {
+        self.compile_number(a, env);
+        self.compile_number(b, env);
+        self.emit(Instruction::Binary(op));
+    }
+
+    fn comparison(&mut self, a: &Node, b: &Node, test: fn(Ordering) -> bool, env: &Environment) {
+        self.compile_number(a, env);
+        self.compile_number(b, env);
+        self.emit(Instruction::Compare(test));
+    }
+
+    /*
+    Emits the code for an operand that must be a number, checking its value only when it could
+    be a boolean, so the checks cost nothing in plain arithmetic
+    */
+    fn compile_number(&mut self, expr: &Node, env: &Environment) {
+        use self::Node::*;
+        match expr {
+            Variable(name) => {
+                let slot = self.slot_for(name);
+                self.emit(Instruction::LoadNumber(slot));
+            }
+            Bool(_) | Equal(..) | NotEqual(..) | Less(..) | LessEqual(..) | Greater(..)
+            | GreaterEqual(..) | And(..) | Or(..) | Not(_) | Conditional(..) | Assign(..) => {
+                self.compile(expr, env);
+                self.emit(Instruction::CheckNumber);
+            }
+            _ => self.compile(expr, env),
+        }
+    }
+
+    /* Emits the code for an operand that must be true or false, as for compile_number */
+    fn compile_bool(&mut self, expr: &Node, env: &Environment) {
+        use self::Node::*;
+        self.compile(expr, env);
+        if !matches!(
+            expr,
+            Bool(_)
+                | Equal(..)
+                | NotEqual(..)
+                | Less(..)
+                | LessEqual(..)
+                | Greater(..)
+                | GreaterEqual(..)
+                | And(..)
+                | Or(..)
+                | Not(_)
+        ) {
+            self.emit(Instruction::CheckBool);
+        }
+    }
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("compiled code keeps the stack balanced")
+}
+
+/* The two operands of a binary operator, left then right */
+fn pop_pair(stack: &mut Vec<Value>) -> (Value, Value) {
+    let b = pop(stack);
+    (pop(stack), b)
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::ast::eval;
+    use crate::parsemaths::environment::{IntWidth, NumberMode};
+    use crate::parsemaths::parser::Parser;
+
+    fn parse(expr: &str) -> Node {
+        Parser::new(expr).unwrap().parse().unwrap()
+    }
+
+    fn environment(mode: NumberMode) -> Environment {
+        let mut env = Environment::new();
+        env.set_mode(mode);
+        env.set("x", Value::Real(3.0));
+        env.set("y", Value::Real(-1.0));
+        env.set("b", Value::Bool(true));
+        env
+    }
+
+    #[test]
+    fn test_matches_tree_walker() {
+        let exprs = [
+            "1 + 2 * 3 - 4 / 8",
+            "2 ^ 0.5 * sin(pi / 6)",
+            "x * x - 3 * x + y",
+            "max(x, y, 10) // 3 % 4",
+            "x > 1 && y < 2 ? x : -y",
+            "!(x == y) || unknown",
+            "false && foo(1)",
+            "true + 1",
+            "b ? 1 : 2",
+            "1 + b",
+            "sqrt(-x)",
+            "1 / (x - x)",
+            "0xff & ~x << 2",
+            "-9223372036854775808 - x",
+            "0.5 + 2i",
+            "sqrt(1, 2)",
+            "let pi = 3",
+            "let z = x + 1",
+            "x * 1 km + 500 m to mi",
+            "3 m + 2 s",
+        ];
+        for mode in [
+            NumberMode::Real,
+            NumberMode::Exact,
+            NumberMode::Complex,
+            NumberMode::Integer(IntWidth::I64),
+        ] {
+            for expr in exprs {
+                let (mut env, mut vm_env) = (environment(mode), environment(mode));
+                let program = compile(&parse(expr), &vm_env);
+                assert_eq!(
+                    program.eval(&mut vm_env),
+                    eval(parse(expr), &mut env),
+                    "{} in {:?}",
+                    expr,
+                    mode
+                );
+                assert_eq!(vm_env.get("z"), env.get("z"), "{}", expr);
+            }
+        }
+    }
+
+    #[test]
+    fn test_slots() {
+        let env = Environment::new();
+        let program = compile(&parse("a * x ^ 2 + pi"), &env);
+        let mut slots = program.bind(&env);
+        let (a, x) = (program.slot("a").unwrap(), program.slot("x").unwrap());
+        slots[a] = Some(Value::Real(2.0));
+        for i in 0..5 {
+            slots[x] = Some(Value::Real(i as f64));
+            let expected = 2.0 * (i * i) as f64 + std::f64::consts::PI;
+            assert_eq!(program.run(&mut slots, &env), Ok(Value::Real(expected)));
+        }
+        slots[a] = None;
+        assert_eq!(
+            program.run(&mut slots, &env),
+            Err(EvalError::UnknownVariable("a".into()))
+        );
+    }
+}
@@ -0,0 +1,233 @@
+// in complex.rs - providing code for complex numbers used by the complex number mode
+
+use std::fmt;
+
+/*
+Complex number re + im*i with f64 parts
+Functions return the principal value e.g. sqrt(-4) = 2i and ln(-1) = pi*i
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+// integer powers up to this size are computed by repeated multiplication, which keeps i^2 = -1 exact
+const MAX_EXACT_POWER: f64 = 64.0;
+
+// the arithmetic is named like the methods of Value, rather than written with operators
+#[allow(clippy::should_implement_trait)]
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn from_polar(modulus: f64, argument: f64) -> Self {
+        Complex::new(modulus * argument.cos(), modulus * argument.sin())
+    }
+
+    /* Modulus |z| */
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /* Argument (angle) in radians, in the range (-pi, pi] */
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    pub fn is_finite(self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+
+    pub fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    pub fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    pub fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+
+    pub fn div(self, other: Complex) -> Complex {
+        let denominator = other.re * other.re + other.im * other.im;
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / denominator,
+            (self.im * other.re - self.re * other.im) / denominator,
+        )
+    }
+
+    pub fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+
+    /* Principal power z^w = exp(w * ln z), with small integer powers multiplied out exactly */
+    pub fn pow(self, exponent: Complex) -> Complex {
+        if exponent.im == 0.0 && exponent.re.fract() == 0.0 && exponent.re.abs() <= MAX_EXACT_POWER
+        {
+            let mut result = Complex::new(1.0, 0.0);
+            for _ in 0..exponent.re.abs() as u32 {
+                result = result.mul(self);
+            }
+            return if exponent.re < 0.0 {
+                Complex::new(1.0, 0.0).div(result)
+            } else {
+                result
+            };
+        }
+        if self.re == 0.0 && self.im == 0.0 {
+            return Complex::new(0.0, 0.0);
+        }
+        exponent.mul(self.ln()).exp()
+    }
+
+    pub fn sqrt(self) -> Complex {
+        let modulus = self.abs();
+        let re = ((modulus + self.re) / 2.0).sqrt();
+        let im = ((modulus - self.re) / 2.0).sqrt();
+        Complex::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    pub fn exp(self) -> Complex {
+        Complex::from_polar(self.re.exp(), self.im)
+    }
+
+    pub fn ln(self) -> Complex {
+        Complex::new(self.abs().ln(), self.arg())
+    }
+
+    pub fn sin(self) -> Complex {
+        Complex::new(
+            self.re.sin() * self.im.cosh(),
+            self.re.cos() * self.im.sinh(),
+        )
+    }
+
+    pub fn cos(self) -> Complex {
+        Complex::new(
+            self.re.cos() * self.im.cosh(),
+            -self.re.sin() * self.im.sinh(),
+        )
+    }
+
+    pub fn tan(self) -> Complex {
+        self.sin().div(self.cos())
+    }
+
+    pub fn sinh(self) -> Complex {
+        Complex::new(
+            self.re.sinh() * self.im.cos(),
+            self.re.cosh() * self.im.sin(),
+        )
+    }
+
+    pub fn cosh(self) -> Complex {
+        Complex::new(
+            self.re.cosh() * self.im.cos(),
+            self.re.sinh() * self.im.sin(),
+        )
+    }
+
+    pub fn tanh(self) -> Complex {
+        self.sinh().div(self.cosh())
+    }
+
+    /* asin(z) = -i * ln(iz + sqrt(1 - z^2)) */
+    pub fn asin(self) -> Complex {
+        let i = Complex::new(0.0, 1.0);
+        let one = Complex::new(1.0, 0.0);
+        let root = one.sub(self.mul(self)).sqrt();
+        i.neg().mul(i.mul(self).add(root).ln())
+    }
+
+    /* acos(z) = pi/2 - asin(z) */
+    pub fn acos(self) -> Complex {
+        Complex::new(std::f64::consts::FRAC_PI_2, 0.0).sub(self.asin())
+    }
+
+    /* Text in polar form modulus∠argument, with the argument in radians */
+    pub fn to_polar_string(self) -> String {
+        format!("{}∠{}", self.abs(), self.arg())
+    }
+}
+
+/*
+Prints in rectangular form a + bi, in a form the parser reads back e.g. 3 - 4i, 2i, -i
+*/
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let imaginary = |im: f64| {
+            if im == 1.0 {
+                "i".to_string()
+            } else {
+                format!("{}i", im)
+            }
+        };
+        if self.re == 0.0 && self.im != 0.0 {
+            if self.im < 0.0 {
+                write!(f, "-{}", imaginary(-self.im))
+            } else {
+                write!(f, "{}", imaginary(self.im))
+            }
+        } else if self.im < 0.0 {
+            write!(f, "{} - {}", self.re, imaginary(-self.im))
+        } else {
+            write!(f, "{} + {}", self.re, imaginary(self.im))
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Complex, b: Complex) -> bool {
+        a.sub(b).abs() < 1e-12
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let i = Complex::new(0.0, 1.0);
+        assert_eq!(i.mul(i), Complex::new(-1.0, 0.0));
+        assert_eq!(i.pow(Complex::new(2.0, 0.0)), Complex::new(-1.0, 0.0));
+        assert_eq!(
+            Complex::new(3.0, 4.0).div(Complex::new(0.0, 2.0)),
+            Complex::new(2.0, -1.5)
+        );
+        assert_eq!(Complex::new(3.0, 4.0).abs(), 5.0);
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(Complex::new(-4.0, 0.0).sqrt(), Complex::new(0.0, 2.0));
+        let pi = std::f64::consts::PI;
+        assert!(close(Complex::new(-1.0, 0.0).ln(), Complex::new(0.0, pi)));
+        // Euler's identity
+        assert!(close(Complex::new(0.0, pi).exp(), Complex::new(-1.0, 0.0)));
+        let z = Complex::new(0.5, -2.0);
+        assert!(close(z.asin().sin(), z));
+        assert!(close(z.acos().cos(), z));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Complex::new(3.0, -4.0).to_string(), "3 - 4i");
+        assert_eq!(Complex::new(0.0, 2.5).to_string(), "2.5i");
+        assert_eq!(Complex::new(0.0, -1.0).to_string(), "-i");
+        assert_eq!(
+            Complex::new(1.0, 1.0).to_polar_string(),
+            format!("{}∠{}", 2f64.sqrt(), std::f64::consts::FRAC_PI_4)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// in derivative.rs - providing code for the symbolic differentiation of an AST
+
+use crate::parsemaths::ast::Node;
+use crate::parsemaths::functions;
+use crate::parsemaths::simplify::simplify;
+use std::{error, fmt};
+
+/*
+Derivative of expr with respect to the variable var, as a simplified AST
+Every other variable is a constant e.g. d/dx (a * x^2) = a * (2 * x), and so is any part of expr
+that does not mention var, whatever it is made of e.g. d/dx 5! = 0
+The sum, product, quotient and power rules are applied recursively, and the chain rule for calls
+to the built-in functions
+*/
+pub fn differentiate(expr: &Node, var: &str) -> Result<Node, DiffError> {
+    derive(expr, var).map(simplify)
+}
+
+fn derive(expr: &Node, var: &str) -> Result<Node, DiffError> {
+    use self::Node::*;
+    let d = |node: &Node| derive(node, var);
+    if !mentions(expr, var) {
+        return Ok(number(0.0));
+    }
+    Ok(match expr {
+        Variable(_) => number(1.0),
+        Add(a, b) => add(d(a)?, d(b)?),
+        Subtract(a, b) => sub(d(a)?, d(b)?),
+        Negative(a) => neg(d(a)?),
+        // a constant factor is kept as it is: (cu)' = cu' and (u/c)' = u'/c
+        Multiply(a, b) if !mentions(a, var) => mul(copy(a), d(b)?),
+        Multiply(a, b) if !mentions(b, var) => mul(d(a)?, copy(b)),
+        Divide(a, b) if !mentions(b, var) => div(d(a)?, copy(b)),
+        // (uv)' = u'v + uv'
+        Multiply(a, b) => add(mul(d(a)?, copy(b)), mul(copy(a), d(b)?)),
+        // (u/v)' = (u'v - uv') / v^2
+        Divide(a, b) => div(
+            sub(mul(d(a)?, copy(b)), mul(copy(a), d(b)?)),
+            pow(copy(b), number(2.0)),
+        ),
+        Percent(a) => div(d(a)?, number(100.0)),
+        Caret(a, b) => power_rule(a, b, var)?,
+        Call(name, args) => chain_rule(name, args, var)?,
+        // u // v is constant between the points where it jumps
+        FloorDivide(..) => number(0.0),
+        // u % v = u - v * floor(u / v)
+        Modulo(a, b) if !mentions(b, var) => d(a)?,
+        Modulo(a, b) => sub(
+            d(a)?,
+            mul(d(b)?, call("floor", vec![div(copy(a), copy(b))])),
+        ),
+        // the condition picks which derivative applies
+        Conditional(cond, a, b) => Conditional(cond.clone(), Box::new(d(a)?), Box::new(d(b)?)),
+        _ => return Err(DiffError::NotDifferentiable(expr.to_string())),
+    })
+}
+
+/*
+(u^v)' in its three forms:
+- constant power: (u^n)' = n * u^(n - 1) * u'
+- constant base: (c^v)' = c^v * ln(c) * v', where e^v is simply e^v * v'
+- both varying: (u^v)' = u^v * (v' * ln(u) + v * u' / u)
+*/
+fn power_rule(base: &Node, exponent: &Node, var: &str) -> Result<Node, DiffError> {
+    let (du, dv) = (derive(base, var)?, derive(exponent, var)?);
+    let power = || pow(base.clone(), exponent.clone());
+    if !mentions(exponent, var) {
+        let lowered = pow(base.clone(), sub(exponent.clone(), number(1.0)));
+        Ok(mul(mul(exponent.clone(), lowered), du))
+    } else if !mentions(base, var) {
+        match base {
+            Node::Variable(name) if name == "e" => Ok(mul(power(), dv)),
+            _ => Ok(mul(mul(power(), call("ln", vec![base.clone()])), dv)),
+        }
+    } else {
+        let ln_term = mul(dv, call("ln", vec![base.clone()]));
+        let base_term = div(mul(exponent.clone(), du), base.clone());
+        Ok(mul(power(), add(ln_term, base_term)))
+    }
+}
+
+/*
+(f(u))' = f'(u) * u' for the built-in functions of one argument, plus the rules for the functions
+of several arguments e.g. atan2(y, x)
+Functions that only take whole number values e.g. floor have a zero derivative, and min and max
+become a choice between the derivatives of their arguments
+*/
+fn chain_rule(name: &str, args: &[Node], var: &str) -> Result<Node, DiffError> {
+    let d = |node: &Node| derive(node, var);
+    let outer = match (name, args) {
+        ("atan2", [y, x]) => {
+            // (x*y' - y*x') / (x^2 + y^2)
+            let top = sub(mul(x.clone(), d(y)?), mul(y.clone(), d(x)?));
+            let bottom = add(pow(x.clone(), number(2.0)), pow(y.clone(), number(2.0)));
+            return Ok(div(top, bottom));
+        }
+        ("hypot", [a, b]) => {
+            // (a*a' + b*b') / hypot(a, b)
+            let top = add(mul(a.clone(), d(a)?), mul(b.clone(), d(b)?));
+            return Ok(div(top, call("hypot", args.to_vec())));
+        }
+        ("log", [u, base]) => {
+            // log(u, b) = ln(u) / ln(b)
+            let quotient = div(call("ln", vec![u.clone()]), call("ln", vec![base.clone()]));
+            return derive(&quotient, var);
+        }
+        ("min" | "max", [a, rest @ ..]) if !rest.is_empty() => {
+            // max(a, ...) is a when a >= max(...), otherwise max(...)
+            let others = call(name, rest.to_vec());
+            let cond = if name == "max" {
+                Node::GreaterEqual(Box::new(a.clone()), Box::new(others.clone()))
+            } else {
+                Node::LessEqual(Box::new(a.clone()), Box::new(others.clone()))
+            };
+            return Ok(Node::Conditional(
+                Box::new(cond),
+                Box::new(d(a)?),
+                Box::new(d(&others)?),
+            ));
+        }
+        (_, [u]) => match outer_derivative(name, u) {
+            Some(outer) => outer,
+            None => return Err(not_differentiable(name, args)),
+        },
+        _ => return Err(not_differentiable(name, args)),
+    };
+    Ok(mul(outer, d(&args[0])?))
+}
+
+/* f'(u) for the built-in functions of one argument - None if f is not one of them */
+fn outer_derivative(name: &str, u: &Node) -> Option<Node> {
+    let f = |name: &str| call(name, vec![u.clone()]);
+    let square = |node: Node| pow(node, number(2.0));
+    Some(match name {
+        "sin" => f("cos"),
+        "cos" => neg(f("sin")),
+        "tan" => div(number(1.0), square(f("cos"))),
+        "asin" => div(number(1.0), sqrt(sub(number(1.0), square(u.clone())))),
+        "acos" => neg(div(number(1.0), sqrt(sub(number(1.0), square(u.clone()))))),
+        "atan" => div(number(1.0), add(number(1.0), square(u.clone()))),
+        "sinh" => f("cosh"),
+        "cosh" => f("sinh"),
+        "tanh" => div(number(1.0), square(f("cosh"))),
+        "sqrt" => div(number(1.0), mul(number(2.0), f("sqrt"))),
+        "cbrt" => div(number(1.0), mul(number(3.0), square(f("cbrt")))),
+        "exp" => f("exp"),
+        "ln" => div(number(1.0), u.clone()),
+        "log10" | "log" => div(number(1.0), mul(u.clone(), call("ln", vec![number(10.0)]))),
+        "log2" => div(number(1.0), mul(u.clone(), call("ln", vec![number(2.0)]))),
+        // the sign of u
+        "abs" => div(u.clone(), f("abs")),
+        "min" | "max" | "re" | "conj" => number(1.0),
+        "floor" | "ceil" | "round" | "im" | "arg" => number(0.0),
+        _ => return None,
+    })
+}
+
+fn not_differentiable(name: &str, args: &[Node]) -> DiffError {
+    match functions::lookup(name) {
+        Some(_) => DiffError::NotDifferentiable(call(name, args.to_vec()).to_string()),
+        None => DiffError::UnknownFunction(name.to_string()),
+    }
+}
+
+/* Checks whether the variable var appears anywhere in expr */
+fn mentions(expr: &Node, var: &str) -> bool {
+    match expr {
+        Node::Variable(name) => name == var,
+        _ => expr
+            .children()
+            .into_iter()
+            .any(|child| mentions(child, var)),
+    }
+}
+
+// Helpers to build the nodes of a derivative
+
+fn number(n: f64) -> Node {
+    Node::Number(n)
+}
+
+fn copy(node: &Node) -> Node {
+    node.clone()
+}
+
+fn add(a: Node, b: Node) -> Node {
+    Node::Add(Box::new(a), Box::new(b))
+}
+
+fn sub(a: Node, b: Node) -> Node {
+    Node::Subtract(Box::new(a), Box::new(b))
+}
+
+fn mul(a: Node, b: Node) -> Node {
+    Node::Multiply(Box::new(a), Box::new(b))
+}
+
+fn div(a: Node, b: Node) -> Node {
+    Node::Divide(Box::new(a), Box::new(b))
+}
+
+fn pow(a: Node, b: Node) -> Node {
+    Node::Caret(Box::new(a), Box::new(b))
+}
+
+fn neg(a: Node) -> Node {
+    Node::Negative(Box::new(a))
+}
+
+fn call(name: &str, args: Vec<Node>) -> Node {
+    Node::Call(name.to_string(), args)
+}
+
+fn sqrt(a: Node) -> Node {
+    call("sqrt", vec![a])
+}
+
+/*
+Errors raised when an expression has no derivative rule
+e.g. comparisons, bitwise operators or an assignment
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiffError {
+    NotDifferentiable(String),
+    UnknownFunction(String),
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::NotDifferentiable(e) => write!(f, "Cannot differentiate {}", e),
+            DiffError::UnknownFunction(name) => write!(f, "Unknown function '{}'", name),
+        }
+    }
+}
+
+impl error::Error for DiffError {}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::ast::eval;
+    use crate::parsemaths::environment::Environment;
+    use crate::parsemaths::parser::Parser;
+    use crate::parsemaths::value::Value;
+
+    fn derivative(expr: &str, var: &str) -> Result<String, DiffError> {
+        let ast = Parser::new(expr).unwrap().parse().unwrap();
+        differentiate(&ast, var).map(|node| node.to_string())
+    }
+
+    /* Value of d/dx expr at x, worked out by evaluating the derivative tree */
+    fn slope(expr: &str, x: f64) -> f64 {
+        let ast = Parser::new(expr).unwrap().parse().unwrap();
+        let mut env = Environment::new();
+        env.set("x", Value::Real(x));
+        eval(differentiate(&ast, "x").unwrap(), &mut env)
+            .unwrap()
+            .to_f64()
+    }
+
+    #[test]
+    fn test_rules() {
+        assert_eq!(derivative("x^3 + 2*x + 7", "x").unwrap(), "3 * x ^ 2 + 2");
+        assert_eq!(derivative("a * x", "x").unwrap(), "a");
+        assert_eq!(derivative("a * x", "a").unwrap(), "x");
+        assert_eq!(derivative("sin(x^2)", "x").unwrap(), "2 * (cos(x ^ 2) * x)");
+        assert_eq!(derivative("e^x", "x").unwrap(), "e ^ x");
+        assert_eq!(derivative("y^2", "x").unwrap(), "0");
+    }
+
+    #[test]
+    fn test_values() {
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+        // quotient rule: (x/(1+x^2))' at 2 = (1 - x^2)/(1 + x^2)^2 = -3/25
+        assert!(close(slope("x / (1 + x^2)", 2.0), -3.0 / 25.0));
+        // x^x: x^x * (ln(x) + 1)
+        assert!(close(slope("x^x", 2.0), 4.0 * (2f64.ln() + 1.0)));
+        assert!(close(slope("atan2(x, 1) - atan(x)", 0.7), 0.0));
+        assert!(close(slope("ln(sqrt(x))", 4.0), 1.0 / 8.0));
+        assert!(close(slope("max(x, 2*x, 1)", 3.0), 2.0));
+        assert!(close(slope("log(x, 2)", 8.0), 1.0 / (8.0 * 2f64.ln())));
+    }
+
+    #[test]
+    fn test_constants() {
+        // a part without x is a constant, whatever it is
+        assert_eq!(derivative("5!", "x").unwrap(), "0");
+        assert_eq!(
+            derivative("x * 3! + sqrt(2) + y", "x").unwrap(),
+            "factorial(3)"
+        );
+        assert_eq!(derivative("(x & 1) + y", "y").unwrap(), "1");
+        assert_eq!(derivative("ln(x) * a", "x").unwrap(), "1 / x * a");
+        assert_eq!(derivative("log(x, 2)", "x").unwrap(), "1 / x / ln(2)");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            derivative("x & 1", "x"),
+            Err(DiffError::NotDifferentiable("x & 1".into()))
+        );
+        assert_eq!(
+            derivative("foo(x)", "x"),
+            Err(DiffError::UnknownFunction("foo".into()))
+        );
+        assert!(derivative("sqrt(x, 2)", "x").is_err());
+    }
+}
@@ -0,0 +1,136 @@
+// in environment.rs - providing code for the variables shared between evaluations
+
+use crate::parsemaths::value::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/*
+How ast::eval treats results that are not finite real numbers
+Strict: 1/0, (-8)^0.5, 10^400 etc. are reported as an EvalError (the default)
+Ieee: the IEEE 754 result (inf, -inf or NaN) is returned like any other value
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum EvalPolicy {
+    #[default]
+    Strict,
+    Ieee,
+}
+
+/*
+How number literals are evaluated
+Real: as f64 floating point numbers (the default)
+Exact: as arbitrary precision fractions, so 0.1 + 0.2 is exactly 3/10
+Complex: as f64 floating point numbers, but operations with no real result such as sqrt(-1) or
+(-8)^(1/3) give the principal complex value instead of a domain error
+Integer: as whole numbers of the given width, with every result checked to fit in that width
+(programmer mode) - '/' truncates towards zero while '//' and '%' round towards negative infinity
+Imaginary literals such as 2i are complex in every mode except Integer, where they are an error
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum NumberMode {
+    #[default]
+    Real,
+    Exact,
+    Complex,
+    Integer(IntWidth),
+}
+
+/*
+Width of the whole numbers used by the integer number mode
+A result outside the range of the width is an Overflow or Underflow error rather than wrapping around
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IntWidth {
+    I64,
+    U64,
+}
+
+impl IntWidth {
+    pub fn min(self) -> i128 {
+        match self {
+            IntWidth::I64 => i64::MIN as i128,
+            IntWidth::U64 => 0,
+        }
+    }
+
+    pub fn max(self) -> i128 {
+        match self {
+            IntWidth::I64 => i64::MAX as i128,
+            IntWidth::U64 => u64::MAX as i128,
+        }
+    }
+}
+
+impl fmt::Display for IntWidth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntWidth::I64 => write!(f, "i64"),
+            IntWidth::U64 => write!(f, "u64"),
+        }
+    }
+}
+
+/*
+Stores the values bound by `let` statements so later expressions can refer to them
+The REPL keeps one Environment alive for the whole session and passes it to ast::eval
+*/
+#[derive(Debug, Default)]
+pub struct Environment {
+    vars: HashMap<String, Value>,
+    policy: EvalPolicy,
+    mode: NumberMode,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            vars: HashMap::new(),
+            policy: EvalPolicy::Strict,
+            mode: NumberMode::Real,
+        }
+    }
+
+    pub fn policy(&self) -> EvalPolicy {
+        self.policy
+    }
+
+    pub fn set_policy(&mut self, policy: EvalPolicy) {
+        self.policy = policy;
+    }
+
+    pub fn mode(&self) -> NumberMode {
+        self.mode
+    }
+
+    pub fn set_mode(&mut self, mode: NumberMode) {
+        self.mode = mode;
+    }
+
+    /* Width of the integers in the integer number mode - None in the other modes */
+    pub fn int_width(&self) -> Option<IntWidth> {
+        match self.mode {
+            NumberMode::Integer(width) => Some(width),
+            _ => None,
+        }
+    }
+
+    /* Looks up a variable - None if it has never been assigned */
+    pub fn get(&self, name: &str) -> Option<Value> {
+        self.vars.get(name).cloned()
+    }
+
+    /* Names of the assigned variables, in no particular order */
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.vars.keys().map(String::as_str)
+    }
+
+    /* Binds (or rebinds) a variable to a value */
+    pub fn set(&mut self, name: &str, value: Value) {
+        self.vars.insert(name.to_string(), value);
+    }
+
+    /* Forgets a variable, returning the value it had */
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.vars.remove(name)
+    }
+}
@@ -0,0 +1,324 @@
+// in explain.rs - providing code for evaluating an expression one step at a time, showing each step
+
+use crate::parsemaths::ast::{self, EvalError, Node};
+use crate::parsemaths::environment::Environment;
+use crate::parsemaths::value::{DisplayOptions, Value};
+
+/*
+What explaining an expression gave: the expression after each step, starting with the expression
+itself and, if it could be evaluated, ending with its value e.g. 2 * 3 + 4, 6 + 4, 10
+If a step failed the steps stop at the expression it failed on and result is the error
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explanation {
+    pub steps: Vec<String>,
+    pub result: Result<Value, EvalError>,
+}
+
+/*
+Evaluates the expression as ast::eval does, one operation at a time, and records the expression
+after each one, with the values worked out so far in place of the parts they came from
+Operations are taken in the order eval takes them: operands left to right and each before the
+operator using them, a variable is replaced by its value when it is reached, only the branch of a
+conditional chosen is evaluated and && and || stop when their left side decides the result
+Each operation is done by eval itself, so every value and error is exactly what eval gives
+Values are shown with options e.g. as fractions or in hex, as the result would be
+*/
+pub fn explain(expr: Node, env: &mut Environment, options: &DisplayOptions) -> Explanation {
+    let mut explainer = Explainer {
+        env,
+        options,
+        values: Vec::new(),
+    };
+    let mut expr = expr;
+    let mut steps = vec![expr.to_string()];
+    let result = loop {
+        match explainer.step(&mut expr) {
+            Ok(true) => {
+                let text = explainer.show(&expr);
+                // some operations do not change how the expression looks e.g. -3 => -3
+                if steps.last() != Some(&text) {
+                    steps.push(text);
+                }
+            }
+            Ok(false) => break ast::eval(expr.clone(), explainer.env),
+            Err(e) => break Err(e),
+        }
+    };
+    // the values worked out along the way are only needed while explaining
+    for i in 0..explainer.values.len() {
+        explainer.env.remove(&placeholder(i));
+    }
+    Explanation { steps, result }
+}
+
+/*
+Name a value worked out while explaining is stored under, in place of the node it came from
+The tokenizer never reads a '#' in a name, so these cannot clash with the user's variables
+*/
+fn placeholder(index: usize) -> String {
+    format!("#{}", index)
+}
+
+fn is_placeholder(name: &str) -> bool {
+    name.starts_with('#')
+}
+
+struct Explainer<'a> {
+    env: &'a mut Environment,
+    options: &'a DisplayOptions,
+    // the text of each value worked out so far, by the number in its placeholder
+    values: Vec<String>,
+}
+
+impl<'a> Explainer<'a> {
+    /* Whether the node needs no more work: a literal or a value already worked out */
+    fn settled(node: &Node) -> bool {
+        use self::Node::*;
+        match node {
+            Number(_) | Integer(_) | Decimal(_) | Imaginary(_) | Quantity(..) | Bool(_) => true,
+            Variable(name) => is_placeholder(name),
+            _ => false,
+        }
+    }
+
+    /*
+    Takes the next step in evaluating node, changing it in place
+    Returns false when node is already settled and there is nothing left to do
+    */
+    fn step(&mut self, node: &mut Node) -> Result<bool, EvalError> {
+        use self::Node::*;
+        if Self::settled(node) {
+            return Ok(false);
+        }
+        match node {
+            // the right side is only worked out if the left side does not decide the result,
+            // which is when it is true for && and false for ||
+            And(..) | Or(..) => {
+                let needs_right = matches!(node, And(..));
+                let (And(a, b) | Or(a, b)) = node else {
+                    unreachable!("matched above")
+                };
+                if self.step(a)? {
+                    return Ok(true);
+                }
+                let left = ast::expect_bool(ast::eval((**a).clone(), self.env)?)?;
+                if left == needs_right && self.step(b)? {
+                    return Ok(true);
+                }
+            }
+            // the conditional is replaced by the branch chosen, which is then worked out
+            Conditional(cond, a, b) => {
+                if self.step(cond)? {
+                    return Ok(true);
+                }
+                let chosen = if ast::expect_bool(ast::eval((**cond).clone(), self.env)?)? {
+                    a
+                } else {
+                    b
+                };
+                let branch = std::mem::replace(&mut **chosen, Bool(false));
+                *node = branch;
+                return Ok(true);
+            }
+            _ => {
+                for child in node.children_mut() {
+                    if self.step(child)? {
+                        return Ok(true);
+                    }
+                }
+            }
+        }
+        // every part of the node is settled, so eval works it out in one go
+        let value = ast::eval(node.clone(), self.env)?;
+        let name = placeholder(self.values.len());
+        self.values.push(value.format(self.options));
+        self.env.set(&name, value);
+        *node = Variable(name);
+        Ok(true)
+    }
+
+    /* The expression as it now stands, with each value worked out written in place */
+    fn show(&self, node: &Node) -> String {
+        match node {
+            // the whole expression has been worked out
+            Node::Variable(name) if is_placeholder(name) => self.value_text(name).to_string(),
+            _ => self.with_values(node.clone()).to_string(),
+        }
+    }
+
+    fn value_text(&self, name: &str) -> &str {
+        let index: usize = name[1..].parse().expect("placeholders are numbered");
+        &self.values[index]
+    }
+
+    /*
+    Replaces each placeholder with a node that is written as its value
+    A negative number is a negation, so it gets the parentheses it needs e.g. (-3) ^ 2, and any
+    other value written with spaces or a '/' is put in parentheses e.g. (1 + 2i) * 3 or (-3/10) * x
+    */
+    fn with_values(&self, node: Node) -> Node {
+        match node {
+            Node::Variable(name) if is_placeholder(&name) => {
+                let text = self.value_text(&name);
+                // a quantity is written like a literal e.g. 5 km, which never needs parentheses
+                let is_quantity = matches!(self.env.get(&name), Some(Value::Quantity(_)));
+                let atom = is_quantity || !text.contains([' ', '/']);
+                match text.strip_prefix('-') {
+                    Some(rest) if atom => Node::Negative(Box::new(Node::Variable(rest.into()))),
+                    _ if atom => Node::Variable(text.into()),
+                    _ => Node::Variable(format!("({})", text)),
+                }
+            }
+            node => node.map_children(|child| self.with_values(child)),
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::environment::NumberMode;
+    use crate::parsemaths::parser::Parser;
+    use crate::parsemaths::value::ExactFormat;
+
+    fn steps(expr: &str, env: &mut Environment) -> Vec<String> {
+        let ast = Parser::new(expr).unwrap().parse().unwrap();
+        explain(ast, env, &DisplayOptions::default()).steps
+    }
+
+    #[test]
+    fn test_steps() {
+        let mut env = Environment::new();
+        assert_eq!(steps("2*3+4", &mut env), ["2 * 3 + 4", "6 + 4", "10"]);
+        assert_eq!(
+            steps("(1 + 2) * (3 + 4) ^ 2", &mut env),
+            [
+                "(1 + 2) * (3 + 4) ^ 2",
+                "3 * (3 + 4) ^ 2",
+                "3 * 7 ^ 2",
+                "3 * 49",
+                "147"
+            ]
+        );
+        // a negative value keeps the parentheses it needs
+        env.set("x", Value::Real(5.0));
+        assert_eq!(
+            steps("(1 - 4) ^ 2 - 2 * -x", &mut env),
+            [
+                "(1 - 4) ^ 2 - 2 * -x",
+                "(-3) ^ 2 - 2 * -x",
+                "9 - 2 * -x",
+                "9 - 2 * -5",
+                "9 - -10",
+                "19"
+            ]
+        );
+        assert_eq!(
+            steps("let y = max(x, 2) + 1", &mut env),
+            [
+                "let y = max(x, 2) + 1",
+                "let y = max(5, 2) + 1",
+                "let y = 5 + 1",
+                "let y = 6",
+                "6"
+            ]
+        );
+        assert_eq!(env.get("y"), Some(Value::Real(6.0)));
+        // only what eval evaluates is shown, so 1 / 0 never is
+        assert_eq!(
+            steps("x > 1 || 1 / 0 > 1 ? x : 1 / 0", &mut env),
+            [
+                "x > 1 || 1 / 0 > 1 ? x : 1 / 0",
+                "5 > 1 || 1 / 0 > 1 ? x : 1 / 0",
+                "true || 1 / 0 > 1 ? x : 1 / 0",
+                "true ? x : 1 / 0",
+                "x",
+                "5"
+            ]
+        );
+        // none of the values worked out are left behind
+        assert!(env.names().all(|name| !name.starts_with('#')));
+    }
+
+    #[test]
+    fn test_values_and_errors() {
+        let mut env = Environment::new();
+        env.set_mode(NumberMode::Exact);
+        let options = DisplayOptions {
+            exact: ExactFormat::Fraction,
+            ..DisplayOptions::default()
+        };
+        let ast = Parser::new("1/3 + 1/6 * 2").unwrap().parse().unwrap();
+        let explanation = explain(ast, &mut env, &options);
+        assert_eq!(
+            explanation.steps,
+            [
+                "1 / 3 + 1 / 6 * 2",
+                "(1/3) + 1 / 6 * 2",
+                "(1/3) + (1/6) * 2",
+                "(1/3) + (1/3)",
+                "2/3"
+            ]
+        );
+        assert_eq!(explanation.result.unwrap().to_string(), "2/3");
+
+        // a negative complex number is not a negation of all of it, a negative quantity is
+        let mut env = Environment::new();
+        let options = DisplayOptions::default();
+        let ast = Parser::new("-(1 - 2i) * 2").unwrap().parse().unwrap();
+        assert_eq!(
+            explain(ast, &mut env, &options).steps,
+            ["-(1 - 2i) * 2", "(-1 + 2i) * 2", "-2 + 4i"]
+        );
+        let ast = Parser::new("(1 km - 3 km) ^ 2").unwrap().parse().unwrap();
+        assert_eq!(
+            explain(ast, &mut env, &options).steps,
+            ["(1 km - 3 km) ^ 2", "(-2 km) ^ 2", "4000000 m^2"]
+        );
+
+        // a failed step ends the explanation, with the error eval gives
+        let mut env = Environment::new();
+        let ast = Parser::new("2 * (1 / (3 - 3)) + y")
+            .unwrap()
+            .parse()
+            .unwrap();
+        let explanation = explain(ast.clone(), &mut env, &DisplayOptions::default());
+        assert_eq!(
+            explanation.steps,
+            ["2 * (1 / (3 - 3)) + y", "2 * (1 / 0) + y"]
+        );
+        assert_eq!(explanation.result, ast::eval(ast, &mut env));
+        assert!(env.names().next().is_none());
+    }
+
+    #[test]
+    fn test_same_as_eval() {
+        let exprs = [
+            "let a = 2 ^ 3 ^ 2 % 7 - -a0",
+            "let a0 = 5! / 20% // 3",
+            "a0 >= 2 && !(a0 == 3) || 1 / 0 > 0",
+            "false && 1 || 2",
+            "true ? 1 : x",
+            "min(a0, sqrt(16), pi) to m",
+            "3 km + 2 kg",
+            "~0xff & 7 | 1 << 4 xor 3",
+            "2 m * 3 s to km",
+        ];
+        let (mut env, mut expected_env) = (Environment::new(), Environment::new());
+        for expr in exprs {
+            let ast = Parser::new(expr).unwrap().parse().unwrap();
+            let explanation = explain(ast.clone(), &mut env, &DisplayOptions::default());
+            assert_eq!(
+                explanation.result,
+                ast::eval(ast, &mut expected_env),
+                "{}",
+                expr
+            );
+            if let Ok(val) = explanation.result {
+                assert_eq!(explanation.steps.last(), Some(&val.to_string()), "{}", expr);
+            }
+        }
+    }
+}
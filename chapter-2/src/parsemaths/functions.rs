@@ -0,0 +1,398 @@
+// in functions.rs - providing code for the built-in functions and constants
+
+use crate::parsemaths::complex::Complex;
+use std::f64::consts;
+use std::fmt;
+
+/*
+Number of arguments a built-in function accepts
+Checked by ast::eval before the function is called so a bad call is an error rather than a panic
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exact(usize),
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exact(n) => count == n,
+            Arity::Range(min, max) => (min..=max).contains(&count),
+            Arity::AtLeast(min) => count >= min,
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Arity::Exact(1) => write!(f, "1 argument"),
+            Arity::Exact(n) => write!(f, "{} arguments", n),
+            Arity::Range(min, max) => write!(f, "{} to {} arguments", min, max),
+            Arity::AtLeast(min) => write!(f, "at least {} argument(s)", min),
+        }
+    }
+}
+
+/* Integer version of a function, None when the result is undefined */
+pub type IntegerFn = fn(&[i128]) -> Option<i128>;
+
+/*
+A function that can be called from an expression e.g. sqrt(2)
+The arguments have already been evaluated and their count checked against arity
+func is used for real arguments, complex (if the function has one) when any argument is complex
+or when the real result is undefined in the complex number mode e.g. sqrt(-1)
+integer is the only version used in the integer number mode, functions without one are an error
+and None from it means the result is undefined e.g. factorial(-1)
+*/
+#[derive(Debug)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub arity: Arity,
+    pub func: fn(&[f64]) -> f64,
+    pub complex: Option<fn(&[Complex]) -> Complex>,
+    pub integer: Option<IntegerFn>,
+}
+
+/* Registry of every built-in function, searched by name when a call is evaluated */
+pub static BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "sin",
+        arity: Arity::Exact(1),
+        func: |a| a[0].sin(),
+        complex: Some(|a| a[0].sin()),
+        integer: None,
+    },
+    Builtin {
+        name: "cos",
+        arity: Arity::Exact(1),
+        func: |a| a[0].cos(),
+        complex: Some(|a| a[0].cos()),
+        integer: None,
+    },
+    Builtin {
+        name: "tan",
+        arity: Arity::Exact(1),
+        func: |a| a[0].tan(),
+        complex: Some(|a| a[0].tan()),
+        integer: None,
+    },
+    Builtin {
+        name: "asin",
+        arity: Arity::Exact(1),
+        func: |a| a[0].asin(),
+        complex: Some(|a| a[0].asin()),
+        integer: None,
+    },
+    Builtin {
+        name: "acos",
+        arity: Arity::Exact(1),
+        func: |a| a[0].acos(),
+        complex: Some(|a| a[0].acos()),
+        integer: None,
+    },
+    Builtin {
+        name: "atan",
+        arity: Arity::Exact(1),
+        func: |a| a[0].atan(),
+        complex: None,
+        integer: None,
+    },
+    Builtin {
+        name: "atan2",
+        arity: Arity::Exact(2),
+        func: |a| a[0].atan2(a[1]),
+        complex: None,
+        integer: None,
+    },
+    Builtin {
+        name: "sinh",
+        arity: Arity::Exact(1),
+        func: |a| a[0].sinh(),
+        complex: Some(|a| a[0].sinh()),
+        integer: None,
+    },
+    Builtin {
+        name: "cosh",
+        arity: Arity::Exact(1),
+        func: |a| a[0].cosh(),
+        complex: Some(|a| a[0].cosh()),
+        integer: None,
+    },
+    Builtin {
+        name: "tanh",
+        arity: Arity::Exact(1),
+        func: |a| a[0].tanh(),
+        complex: Some(|a| a[0].tanh()),
+        integer: None,
+    },
+    Builtin {
+        name: "sqrt",
+        arity: Arity::Exact(1),
+        func: |a| a[0].sqrt(),
+        complex: Some(|a| a[0].sqrt()),
+        integer: None,
+    },
+    Builtin {
+        name: "cbrt",
+        arity: Arity::Exact(1),
+        func: |a| a[0].cbrt(),
+        complex: None,
+        integer: None,
+    },
+    Builtin {
+        name: "exp",
+        arity: Arity::Exact(1),
+        func: |a| a[0].exp(),
+        complex: Some(|a| a[0].exp()),
+        integer: None,
+    },
+    Builtin {
+        name: "ln",
+        arity: Arity::Exact(1),
+        func: |a| a[0].ln(),
+        complex: Some(|a| a[0].ln()),
+        integer: None,
+    },
+    Builtin {
+        name: "log10",
+        arity: Arity::Exact(1),
+        func: |a| a[0].log10(),
+        complex: Some(|a| a[0].ln().div(Complex::new(10f64.ln(), 0.0))),
+        integer: None,
+    },
+    Builtin {
+        name: "log2",
+        arity: Arity::Exact(1),
+        func: |a| a[0].log2(),
+        complex: Some(|a| a[0].ln().div(Complex::new(2f64.ln(), 0.0))),
+        integer: None,
+    },
+    // log(x) is base 10, log(x, b) is base b
+    Builtin {
+        name: "log",
+        arity: Arity::Range(1, 2),
+        func: |a| a[0].log(*a.get(1).unwrap_or(&10.0)),
+        complex: Some(|a| {
+            a[0].ln()
+                .div(a.get(1).map_or(Complex::new(10.0, 0.0), |b| *b).ln())
+        }),
+        integer: None,
+    },
+    Builtin {
+        name: "abs",
+        arity: Arity::Exact(1),
+        func: |a| a[0].abs(),
+        complex: Some(|a| Complex::new(a[0].abs(), 0.0)),
+        integer: Some(|a| Some(a[0].abs())),
+    },
+    Builtin {
+        name: "floor",
+        arity: Arity::Exact(1),
+        func: |a| a[0].floor(),
+        complex: None,
+        integer: Some(|a| Some(a[0])),
+    },
+    Builtin {
+        name: "ceil",
+        arity: Arity::Exact(1),
+        func: |a| a[0].ceil(),
+        complex: None,
+        integer: Some(|a| Some(a[0])),
+    },
+    Builtin {
+        name: "round",
+        arity: Arity::Exact(1),
+        func: |a| a[0].round(),
+        complex: None,
+        integer: Some(|a| Some(a[0])),
+    },
+    Builtin {
+        name: "hypot",
+        arity: Arity::Exact(2),
+        func: |a| a[0].hypot(a[1]),
+        complex: None,
+        integer: None,
+    },
+    Builtin {
+        name: "min",
+        arity: Arity::AtLeast(1),
+        func: |a| a.iter().copied().fold(f64::INFINITY, f64::min),
+        complex: None,
+        integer: Some(|a| Some(a.iter().copied().fold(i128::MAX, i128::min))),
+    },
+    Builtin {
+        name: "max",
+        arity: Arity::AtLeast(1),
+        func: |a| a.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        complex: None,
+        integer: Some(|a| Some(a.iter().copied().fold(i128::MIN, i128::max))),
+    },
+    // x! is written as a postfix operator too, and extends to non-integers as gamma(x + 1)
+    Builtin {
+        name: "factorial",
+        arity: Arity::Exact(1),
+        func: |a| factorial(a[0]),
+        complex: None,
+        integer: Some(|a| {
+            // a result past i128 is still reported as an overflow by the integer number mode
+            (a[0] >= 0).then(|| {
+                (1..=a[0])
+                    .try_fold(1i128, i128::checked_mul)
+                    .unwrap_or(i128::MAX)
+            })
+        }),
+    },
+    Builtin {
+        name: "gamma",
+        arity: Arity::Exact(1),
+        func: |a| gamma(a[0]),
+        complex: None,
+        integer: None,
+    },
+    // parts of a complex number - a real number is its own real part
+    Builtin {
+        name: "re",
+        arity: Arity::Exact(1),
+        func: |a| a[0],
+        complex: Some(|a| Complex::new(a[0].re, 0.0)),
+        integer: Some(|a| Some(a[0])),
+    },
+    Builtin {
+        name: "im",
+        arity: Arity::Exact(1),
+        func: |_| 0.0,
+        complex: Some(|a| Complex::new(a[0].im, 0.0)),
+        integer: Some(|_| Some(0)),
+    },
+    Builtin {
+        name: "arg",
+        arity: Arity::Exact(1),
+        func: |a| if a[0] < 0.0 { consts::PI } else { 0.0 },
+        complex: Some(|a| Complex::new(a[0].arg(), 0.0)),
+        integer: None,
+    },
+    Builtin {
+        name: "conj",
+        arity: Arity::Exact(1),
+        func: |a| a[0],
+        complex: Some(|a| a[0].conj()),
+        integer: Some(|a| Some(a[0])),
+    },
+];
+
+/* Named constants, used when a variable of the same name has not been assigned */
+pub static CONSTANTS: &[(&str, f64)] =
+    &[("pi", consts::PI), ("e", consts::E), ("tau", consts::TAU)];
+
+/*
+Gamma function, by the Lanczos approximation (g = 7, 9 terms) with the reflection formula below 1/2
+Undefined (NaN) at zero and the negative integers, where it has poles
+At the positive integers it is the factorial of one less, which is exact e.g. gamma(5) = 4! = 24
+*/
+pub fn gamma(x: f64) -> f64 {
+    const G: f64 = 7.0;
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x <= 0.0 && x.fract() == 0.0 {
+        return f64::NAN;
+    }
+    if (1.0..=171.0).contains(&x) && x.fract() == 0.0 {
+        return factorial(x - 1.0);
+    }
+    if x < 0.5 {
+        return consts::PI / ((consts::PI * x).sin() * gamma(1.0 - x));
+    }
+    let x = x - 1.0;
+    let sum = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    let t = x + G + 0.5;
+    // t^(x + 1/2) is taken in halves so it does not overflow before e^-t brings it back down
+    let half = t.powf((x + 0.5) / 2.0);
+    (2.0 * consts::PI).sqrt() * sum * half * (-t).exp() * half
+}
+
+/* x! - whole numbers are multiplied out so they stay exact, anything else is gamma(x + 1) */
+pub fn factorial(x: f64) -> f64 {
+    if (0.0..=170.0).contains(&x) && x.fract() == 0.0 {
+        (1..=x as u32).map(f64::from).product()
+    } else {
+        gamma(x + 1.0)
+    }
+}
+
+pub fn lookup(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|builtin| builtin.name == name)
+}
+
+pub fn constant(name: &str) -> Option<f64> {
+    CONSTANTS
+        .iter()
+        .find(|(constant, _)| *constant == name)
+        .map(|(_, value)| *value)
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arity() {
+        assert!(lookup("sqrt").unwrap().arity.accepts(1));
+        assert!(!lookup("sqrt").unwrap().arity.accepts(2));
+        assert!(lookup("log").unwrap().arity.accepts(2));
+        assert!(!lookup("max").unwrap().arity.accepts(0));
+    }
+
+    #[test]
+    fn test_functions_and_constants() {
+        assert_eq!((lookup("max").unwrap().func)(&[1.0, 5.0, 3.0]), 5.0);
+        assert_eq!((lookup("log").unwrap().func)(&[8.0, 2.0]), 3.0);
+        assert_eq!(constant("tau"), Some(2.0 * consts::PI));
+        assert_eq!(constant("x"), None);
+    }
+
+    #[test]
+    fn test_factorial() {
+        assert_eq!(factorial(0.0), 1.0);
+        assert_eq!(factorial(20.0), 2_432_902_008_176_640_000.0);
+        assert!((factorial(0.5) - consts::PI.sqrt() / 2.0).abs() < 1e-14);
+        assert!((gamma(-0.5) + 2.0 * consts::PI.sqrt()).abs() < 1e-13);
+        assert!((gamma(171.5) / 9.483_367_566_824_801e307 - 1.0).abs() < 1e-12);
+        assert!(factorial(-1.0).is_nan());
+        // the integers are exact, rather than 24.000000000000004 from the approximation
+        assert_eq!(gamma(5.0), 24.0);
+        assert_eq!(gamma(1.0), 1.0);
+        assert_eq!(gamma(21.0), factorial(20.0));
+        assert!(factorial(171.0).is_infinite());
+        let integer = lookup("factorial").unwrap().integer.unwrap();
+        assert_eq!(integer(&[5]), Some(120));
+        assert_eq!(integer(&[-1]), None);
+        assert_eq!(integer(&[40]), Some(i128::MAX));
+    }
+
+    #[test]
+    fn test_complex_functions() {
+        let sqrt = lookup("sqrt").unwrap().complex.unwrap();
+        assert_eq!(sqrt(&[Complex::new(-9.0, 0.0)]), Complex::new(0.0, 3.0));
+        let abs = lookup("abs").unwrap().complex.unwrap();
+        assert_eq!(abs(&[Complex::new(3.0, -4.0)]), Complex::new(5.0, 0.0));
+        assert!(lookup("floor").unwrap().complex.is_none());
+    }
+}
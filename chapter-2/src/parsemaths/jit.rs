@@ -0,0 +1,614 @@
+// in jit.rs - providing code for compiling an AST to x86-64 machine code, with the jit feature
+
+use crate::parsemaths::ast::{EvalError, Node};
+use crate::parsemaths::bytecode::{self, BinaryOp, Program};
+use crate::parsemaths::environment::{Environment, EvalPolicy};
+use crate::parsemaths::functions::{self, Builtin};
+use crate::parsemaths::value::Value;
+use std::ffi::{c_int, c_void};
+use std::{mem, ptr, slice};
+
+/*
+An expression compiled for evaluating many times with different f64 values of its variables
+Expressions made of numbers, variables, arithmetic, function calls and conditionals on
+comparisons run as native SSE2 code, anything else e.g. bitwise operators or 2i runs on the
+bytecode VM
+Results are those of ast::eval in the real number mode with the IEEE policy e.g. 1/0 = inf,
+as there is no way to return an error - an error, or a result that is not a real number,
+is NaN
+*/
+pub struct JitFunction {
+    arity: usize,
+    code: Code,
+}
+
+enum Code {
+    Native(ExecutableMemory),
+    // the slot of each argument, None for arguments the program does not use
+    Interpreted {
+        program: Program,
+        env: Environment,
+        slots: Vec<Option<usize>>,
+    },
+}
+
+/*
+Compiles expr as a function of the variables vars, in that order e.g. compile(expr, &["x", "y"])
+is called as f.call(&[x, y])
+Other variables may only be the built-in constants such as pi
+*/
+pub fn compile(expr: &Node, vars: &[&str]) -> JitFunction {
+    let native = Assembler::function(expr, vars).and_then(|code| ExecutableMemory::new(&code));
+    let code = match native {
+        Some(memory) => Code::Native(memory),
+        None => {
+            let env = ieee_environment();
+            let program = bytecode::compile(expr, &env);
+            let slots = vars.iter().map(|name| program.slot(name)).collect();
+            Code::Interpreted {
+                program,
+                env,
+                slots,
+            }
+        }
+    };
+    JitFunction {
+        arity: vars.len(),
+        code,
+    }
+}
+
+impl JitFunction {
+    /* Value of the expression for the values of its variables, which must all be given */
+    pub fn call(&self, args: &[f64]) -> f64 {
+        assert_eq!(args.len(), self.arity, "wrong number of arguments");
+        match &self.code {
+            Code::Native(memory) => {
+                // SAFETY: the code was generated for this function, and reads only arity values
+                let entry: extern "C" fn(*const f64) -> f64 = unsafe { mem::transmute(memory.ptr) };
+                entry(args.as_ptr())
+            }
+            Code::Interpreted {
+                program,
+                env,
+                slots,
+            } => {
+                let mut values = program.bind(env);
+                for (slot, arg) in slots.iter().zip(args) {
+                    if let Some(slot) = slot {
+                        values[*slot] = Some(Value::Real(*arg));
+                    }
+                }
+                real(program.run(&mut values, env))
+            }
+        }
+    }
+
+    /* Checks whether the expression was compiled to machine code, rather than bytecode */
+    pub fn is_native(&self) -> bool {
+        matches!(self.code, Code::Native(_))
+    }
+}
+
+fn ieee_environment() -> Environment {
+    let mut env = Environment::new();
+    env.set_policy(EvalPolicy::Ieee);
+    env
+}
+
+/* An evaluated result as an f64, NaN for an error or a value that is not a real number */
+fn real(result: Result<Value, EvalError>) -> f64 {
+    match result {
+        Ok(Value::Real(x)) => x,
+        _ => f64::NAN,
+    }
+}
+
+// ucomisd xmm1, xmm0 compares b with a, ucomisd xmm0, xmm1 compares a with b
+const B_WITH_A: [u8; 4] = [0x66, 0x0f, 0x2e, 0xc8];
+const A_WITH_B: [u8; 4] = [0x66, 0x0f, 0x2e, 0xc1];
+// conditional jumps with a 32 bit offset
+const JB: [u8; 2] = [0x0f, 0x82];
+const JE: [u8; 2] = [0x0f, 0x84];
+const JNE: [u8; 2] = [0x0f, 0x85];
+const JBE: [u8; 2] = [0x0f, 0x86];
+const JP: [u8; 2] = [0x0f, 0x8a];
+
+/*
+Builds the machine code of a function extern "C" fn(args: *const f64) -> f64
+Every node leaves its value in xmm0, with the left operand of an operator kept on the machine
+stack while the right one is worked out, and rbx holds args
+*/
+struct Assembler {
+    code: Vec<u8>,
+    // number of values pushed on the machine stack, which must be even at a call
+    depth: usize,
+}
+
+impl Assembler {
+    /* Machine code for expr - None if it uses anything the native code does not support */
+    fn function(expr: &Node, vars: &[&str]) -> Option<Vec<u8>> {
+        let mut asm = Assembler {
+            code: Vec::new(),
+            depth: 0,
+        };
+        asm.emit(&[0x53]); // push rbx
+        asm.emit(&[0x48, 0x89, 0xfb]); // mov rbx, rdi
+        asm.lower(expr, vars)?;
+        asm.emit(&[0x5b]); // pop rbx
+        asm.emit(&[0xc3]); // ret
+        Some(asm.code)
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /* Emits the code that leaves the value of expr in xmm0 */
+    fn lower(&mut self, expr: &Node, vars: &[&str]) -> Option<()> {
+        use self::Node::*;
+        match expr {
+            Number(x) => self.constant(*x),
+            Integer(n) => self.constant(*n as f64),
+            Decimal(text) => self.constant(text.parse().ok()?),
+            Variable(name) => match vars.iter().position(|var| var == name) {
+                Some(i) => {
+                    // movsd xmm0, [rbx + 8*i]
+                    self.emit(&[0xf2, 0x0f, 0x10, 0x83]);
+                    self.emit(&(8 * i32::try_from(i).ok()?).to_le_bytes());
+                }
+                None => self.constant(functions::constant(name)?),
+            },
+            Add(a, b) => self.arithmetic(a, b, 0x58, vars)?,
+            Subtract(a, b) => self.arithmetic(a, b, 0x5c, vars)?,
+            Multiply(a, b) => self.arithmetic(a, b, 0x59, vars)?,
+            Divide(a, b) => self.arithmetic(a, b, 0x5e, vars)?,
+            Percent(a) => self.arithmetic(a, &Number(100.0), 0x5e, vars)?,
+            Caret(a, b) => self.operator_call(a, b, power, vars)?,
+            Modulo(a, b) => self.operator_call(a, b, modulo, vars)?,
+            FloorDivide(a, b) => self.operator_call(a, b, floor_div, vars)?,
+            Negative(a) => {
+                self.lower(a, vars)?;
+                // flips the sign bit: mov rax, 1 << 63; movq xmm1, rax; xorpd xmm0, xmm1
+                self.emit(&[0x48, 0xb8]);
+                self.emit(&(1u64 << 63).to_le_bytes());
+                self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc8, 0x66, 0x0f, 0x57, 0xc1]);
+            }
+            Call(name, args) => self.function_call(name, args, vars)?,
+            Conditional(cond, a, b) => {
+                let to_else = self.condition(cond, vars)?;
+                self.lower(a, vars)?;
+                let to_end = self.jump(&[0xe9]);
+                self.patch_all(to_else);
+                self.lower(b, vars)?;
+                self.patch(to_end);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /* mov rax, x; movq xmm0, rax */
+    fn constant(&mut self, x: f64) {
+        self.emit(&[0x48, 0xb8]);
+        self.emit(&x.to_bits().to_le_bytes());
+        self.emit(&[0x66, 0x48, 0x0f, 0x6e, 0xc0]);
+    }
+
+    /* Leaves the value of a in xmm0 and the value of b in xmm1 */
+    fn operands(&mut self, a: &Node, b: &Node, vars: &[&str]) -> Option<()> {
+        self.lower(a, vars)?;
+        self.push();
+        self.lower(b, vars)?;
+        self.emit(&[0x66, 0x0f, 0x28, 0xc8]); // movapd xmm1, xmm0
+        self.pop();
+        Some(())
+    }
+
+    /* a op b for the SSE2 instruction op xmm0, xmm1 e.g. 0x58 for addsd */
+    fn arithmetic(&mut self, a: &Node, b: &Node, op: u8, vars: &[&str]) -> Option<()> {
+        self.operands(a, b, vars)?;
+        self.emit(&[0xf2, 0x0f, op, 0xc1]);
+        Some(())
+    }
+
+    /* a op b for an operator worked out by a function called from the machine code */
+    fn operator_call(
+        &mut self,
+        a: &Node,
+        b: &Node,
+        op: extern "C" fn(f64, f64) -> f64,
+        vars: &[&str],
+    ) -> Option<()> {
+        self.operands(a, b, vars)?;
+        self.call(op as usize);
+        Some(())
+    }
+
+    /* sub rsp, 8; movsd [rsp], xmm0 */
+    fn push(&mut self) {
+        self.emit(&[0x48, 0x81, 0xec, 8, 0, 0, 0]);
+        self.emit(&[0xf2, 0x0f, 0x11, 0x04, 0x24]);
+        self.depth += 1;
+    }
+
+    /* movsd xmm0, [rsp]; add rsp, 8 */
+    fn pop(&mut self) {
+        self.emit(&[0xf2, 0x0f, 0x10, 0x04, 0x24]);
+        self.drop_values(1);
+    }
+
+    /* add rsp, 8 * count */
+    fn drop_values(&mut self, count: usize) {
+        self.emit(&[0x48, 0x81, 0xc4]);
+        self.emit(&((8 * count) as u32).to_le_bytes());
+        self.depth -= count;
+    }
+
+    /* Calls the function at address, keeping the stack 16 byte aligned as the ABI needs */
+    fn call(&mut self, address: usize) {
+        let pad = self.depth % 2 == 1;
+        if pad {
+            self.emit(&[0x48, 0x81, 0xec, 8, 0, 0, 0]);
+        }
+        self.emit(&[0x48, 0xb8]); // mov rax, address
+        self.emit(&(address as u64).to_le_bytes());
+        self.emit(&[0xff, 0xd0]); // call rax
+        if pad {
+            self.emit(&[0x48, 0x81, 0xc4, 8, 0, 0, 0]);
+        }
+    }
+
+    /*
+    Calls a built-in function with its arguments on the machine stack, first argument on top
+    sqrt is the SSE2 instruction sqrtsd, which gives the same correctly rounded result
+    */
+    fn function_call(&mut self, name: &str, args: &[Node], vars: &[&str]) -> Option<()> {
+        let builtin = functions::lookup(name).filter(|f| f.arity.accepts(args.len()))?;
+        if let ("sqrt", [arg]) = (name, args) {
+            self.lower(arg, vars)?;
+            self.emit(&[0xf2, 0x0f, 0x51, 0xc0]);
+            return Some(());
+        }
+        for arg in args.iter().rev() {
+            self.lower(arg, vars)?;
+            self.push();
+        }
+        self.emit(&[0x48, 0x89, 0xe6]); // mov rsi, rsp
+        self.emit(&[0x48, 0xbf]); // mov rdi, builtin
+        self.emit(&(builtin as *const Builtin as u64).to_le_bytes());
+        self.emit(&[0x48, 0xba]); // mov rdx, count
+        self.emit(&(args.len() as u64).to_le_bytes());
+        self.call(call_function as *const () as usize);
+        self.drop_values(args.len());
+        Some(())
+    }
+
+    /*
+    Emits the code that tests cond, falling through when it is true
+    Returns the jumps taken when it is false, for the caller to point at the code that follows
+    Comparisons with NaN are false, as ucomisd reports it as unordered
+    */
+    fn condition(&mut self, cond: &Node, vars: &[&str]) -> Option<Vec<usize>> {
+        use self::Node::*;
+        match cond {
+            Less(a, b) => self.comparison(a, b, B_WITH_A, &[JBE], vars),
+            LessEqual(a, b) => self.comparison(a, b, B_WITH_A, &[JB], vars),
+            Greater(a, b) => self.comparison(a, b, A_WITH_B, &[JBE], vars),
+            GreaterEqual(a, b) => self.comparison(a, b, A_WITH_B, &[JB], vars),
+            Equal(a, b) => self.comparison(a, b, A_WITH_B, &[JP, JNE], vars),
+            NotEqual(a, b) => {
+                // unordered means not equal, so it skips the jump taken for equal values
+                self.operands(a, b, vars)?;
+                self.emit(&A_WITH_B);
+                let unordered = self.jump(&JP);
+                let equal = self.jump(&JE);
+                self.patch(unordered);
+                Some(vec![equal])
+            }
+            And(a, b) => {
+                let mut to_false = self.condition(a, vars)?;
+                to_false.extend(self.condition(b, vars)?);
+                Some(to_false)
+            }
+            Or(a, b) => {
+                let a_false = self.condition(a, vars)?;
+                let to_true = self.jump(&[0xe9]);
+                self.patch_all(a_false);
+                let to_false = self.condition(b, vars)?;
+                self.patch(to_true);
+                Some(to_false)
+            }
+            Not(a) => {
+                let a_false = self.condition(a, vars)?;
+                let to_false = self.jump(&[0xe9]);
+                self.patch_all(a_false);
+                Some(vec![to_false])
+            }
+            Bool(true) => Some(Vec::new()),
+            Bool(false) => Some(vec![self.jump(&[0xe9])]),
+            _ => None,
+        }
+    }
+
+    /* Compares a with b using ucomisd, returning the given jumps taken when the test is false */
+    fn comparison(
+        &mut self,
+        a: &Node,
+        b: &Node,
+        order: [u8; 4],
+        jumps: &[[u8; 2]],
+        vars: &[&str],
+    ) -> Option<Vec<usize>> {
+        self.operands(a, b, vars)?;
+        self.emit(&order);
+        Some(jumps.iter().map(|jump| self.jump(jump)).collect())
+    }
+
+    /* Emits a jump with a 32 bit offset to fill in later, returning where the offset is */
+    fn jump(&mut self, opcode: &[u8]) -> usize {
+        self.emit(opcode);
+        self.emit(&[0; 4]);
+        self.code.len() - 4
+    }
+
+    /* Points the jump whose offset is at index at to the next instruction to be emitted */
+    fn patch(&mut self, at: usize) {
+        let offset = (self.code.len() - (at + 4)) as i32;
+        self.code[at..at + 4].copy_from_slice(&offset.to_le_bytes());
+    }
+
+    fn patch_all(&mut self, jumps: Vec<usize>) {
+        for at in jumps {
+            self.patch(at);
+        }
+    }
+}
+
+// Functions called from the machine code, which work exactly as ast::eval does
+
+unsafe extern "C" fn call_function(builtin: *const Builtin, args: *const f64, count: usize) -> f64 {
+    // SAFETY: the machine code passes a builtin from functions::lookup and its pushed arguments
+    let (builtin, args) = unsafe { (&*builtin, slice::from_raw_parts(args, count)) };
+    (builtin.func)(args)
+}
+
+extern "C" fn power(a: f64, b: f64) -> f64 {
+    real_op(Value::pow, a, b)
+}
+
+extern "C" fn modulo(a: f64, b: f64) -> f64 {
+    real_op(Value::modulo, a, b)
+}
+
+extern "C" fn floor_div(a: f64, b: f64) -> f64 {
+    real_op(Value::floor_div, a, b)
+}
+
+thread_local! {
+    // the environment the operations above run in, built once per thread rather than per call
+    static IEEE_ENVIRONMENT: Environment = ieee_environment();
+}
+
+fn real_op(op: BinaryOp, a: f64, b: f64) -> f64 {
+    IEEE_ENVIRONMENT.with(|env| real(op(Value::Real(a), Value::Real(b), env)))
+}
+
+// memory protection flags and options for mmap, from <sys/mman.h>
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const PROT_EXEC: c_int = 4;
+const MAP_PRIVATE: c_int = 2;
+const MAP_ANONYMOUS: c_int = 0x20;
+
+extern "C" {
+    fn mmap(
+        addr: *mut c_void,
+        len: usize,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: i64,
+    ) -> *mut c_void;
+    fn mprotect(addr: *mut c_void, len: usize, prot: c_int) -> c_int;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+}
+
+/*
+Pages holding machine code, which are never writable and executable at the same time
+The pages are unmapped when it is dropped
+*/
+struct ExecutableMemory {
+    ptr: *mut c_void,
+    len: usize,
+}
+
+impl ExecutableMemory {
+    /* Copies code into new pages and makes them executable - None if the system refuses */
+    fn new(code: &[u8]) -> Option<ExecutableMemory> {
+        let len = code.len();
+        // SAFETY: a new private mapping, written only within its length before it is executable
+        unsafe {
+            let ptr = mmap(
+                ptr::null_mut(),
+                len,
+                PROT_READ | PROT_WRITE,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            );
+            // MAP_FAILED
+            if ptr as isize == -1 {
+                return None;
+            }
+            let memory = ExecutableMemory { ptr, len };
+            ptr::copy_nonoverlapping(code.as_ptr(), ptr.cast::<u8>(), len);
+            (mprotect(ptr, len, PROT_READ | PROT_EXEC) == 0).then_some(memory)
+        }
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: the pages were mapped by ExecutableMemory::new and nothing else refers to them
+        unsafe {
+            munmap(self.ptr, self.len);
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::ast::eval;
+    use crate::parsemaths::parser::Parser;
+
+    fn parse(expr: &str) -> Node {
+        Parser::new(expr).unwrap().parse().unwrap()
+    }
+
+    /* Value of expr from the tree walker, in the form the JIT gives it */
+    fn expected(expr: &Node, x: f64, y: f64) -> f64 {
+        let mut env = ieee_environment();
+        env.set("x", Value::Real(x));
+        env.set("y", Value::Real(y));
+        real(eval(expr.clone(), &mut env))
+    }
+
+    fn same(a: f64, b: f64) -> bool {
+        a.to_bits() == b.to_bits() || (a.is_nan() && b.is_nan())
+    }
+
+    const INPUTS: [f64; 9] = [
+        0.0,
+        -0.0,
+        1.0,
+        -2.5,
+        0.75,
+        3.0,
+        1e300,
+        f64::INFINITY,
+        f64::NAN,
+    ];
+
+    #[test]
+    fn test_native_code() {
+        let exprs = [
+            "x * x - 3 * x / y + 1",
+            "-x ^ 2 + 2 ^ -y",
+            "sqrt(x) + sin(x) * cos(y) + max(x, y, 0.5) - hypot(x, y)",
+            "x % y + x // y",
+            "x < y ? x : y",
+            "x == y || !(x >= 1 && y != 2) ? pi : -e",
+            "1 / x + ln(x) + atan2(y, x)",
+            "(x <= y) ? (true ? 1 : 2) : (false || x > y ? 3 : 4)",
+        ];
+        for text in exprs {
+            let expr = parse(text);
+            let f = compile(&expr, &["x", "y"]);
+            assert!(f.is_native(), "{}", text);
+            for x in INPUTS {
+                for y in INPUTS {
+                    let (jit, walker) = (f.call(&[x, y]), expected(&expr, x, y));
+                    assert!(
+                        same(jit, walker),
+                        "{} at {}, {}: {} != {}",
+                        text,
+                        x,
+                        y,
+                        jit,
+                        walker
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_interpreter_fallback() {
+        for text in ["x & 3", "x + 2i", "x < y", "z + x", "x > 0 ? foo(x) : y"] {
+            let expr = parse(text);
+            let f = compile(&expr, &["x", "y"]);
+            assert!(!f.is_native(), "{}", text);
+            for x in INPUTS {
+                assert!(same(f.call(&[x, 2.0]), expected(&expr, x, 2.0)), "{}", text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_threads() {
+        // the operations called back from native code each have their environment in every thread
+        let handles: Vec<_> = (0..4)
+            .map(|n| {
+                std::thread::spawn(move || {
+                    let expr = parse("x % y + x // y + x ^ y");
+                    let f = compile(&expr, &["x", "y"]);
+                    assert!(f.is_native());
+                    for x in INPUTS {
+                        let y = n as f64 - 1.5;
+                        assert!(same(f.call(&[x, y]), expected(&expr, x, y)), "{}", x);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+
+    /* Random tree of the nodes the native code supports */
+    fn random_tree(seed: &mut u64, depth: u32) -> Node {
+        use self::Node::*;
+        // xorshift, so the same trees are tested on every run
+        *seed ^= *seed << 13;
+        *seed ^= *seed >> 7;
+        *seed ^= *seed << 17;
+        let pick = *seed;
+        let mut sub = || Box::new(random_tree(seed, depth - 1));
+        match if depth == 0 { pick % 4 } else { pick % 16 } {
+            0 => Number((pick >> 8) as f64 % 7.0 / 2.0),
+            1 => Variable("x".to_string()),
+            2 => Variable("y".to_string()),
+            3 => Variable("pi".to_string()),
+            4 => Add(sub(), sub()),
+            5 => Subtract(sub(), sub()),
+            6 => Multiply(sub(), sub()),
+            7 => Divide(sub(), sub()),
+            8 => Caret(sub(), sub()),
+            9 => Modulo(sub(), sub()),
+            10 => Negative(sub()),
+            11 => Call("sin".to_string(), vec![*sub()]),
+            12 => Call("min".to_string(), vec![*sub(), *sub(), *sub()]),
+            13 => Call("sqrt".to_string(), vec![*sub()]),
+            14 => Conditional(Box::new(Less(sub(), sub())), sub(), sub()),
+            _ => {
+                let not = Not(Box::new(GreaterEqual(sub(), sub())));
+                let cond = Or(Box::new(Equal(sub(), sub())), Box::new(not));
+                Conditional(Box::new(cond), sub(), sub())
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_expressions() {
+        let mut seed = 0x9e37_79b9_7f4a_7c15;
+        for _ in 0..300 {
+            let expr = random_tree(&mut seed, 4);
+            let f = compile(&expr, &["x", "y"]);
+            assert!(f.is_native(), "{}", expr);
+            for x in INPUTS {
+                let (jit, walker) = (f.call(&[x, -1.5]), expected(&expr, x, -1.5));
+                assert!(
+                    same(jit, walker),
+                    "{} at x = {}: {} != {}",
+                    expr,
+                    x,
+                    jit,
+                    walker
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,458 @@
+// in json.rs - providing code for reading and writing JSON, used for machine-readable output
+
+use super::value::real_text;
+use std::{error, fmt};
+
+/*
+A JSON value, written out compactly by Display e.g. {"input":"1+1","value":2}
+Objects keep their keys in the order they were given
+Numbers JSON cannot hold (infinities and NaN) are written as null, and very large or small ones
+use an exponent e.g. 1e-320 rather than 320 digits
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    /* Builds an object from its keys and values e.g. Json::object([("ok", true.into())]) */
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    /* Value of the given key of an object - None if there is no such key or this is not an object */
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /* Reads a JSON value from text, which must hold that one value and nothing else but whitespace */
+    pub fn parse(text: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader { text, pos: 0 };
+        let value = reader.value()?;
+        reader.skip_space();
+        match reader.peek() {
+            None => Ok(value),
+            Some(_) => Err(reader.error("expected end of input")),
+        }
+    }
+}
+
+/*
+Error from reading JSON
+Syntax => the text is not JSON, with the byte offset where reading failed
+Shape => the text is JSON but not in the form expected, with the path to the value at fault e.g. $.args[1]
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonError {
+    Syntax { offset: usize, message: String },
+    Shape { path: String, message: String },
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::Syntax { offset, message } => {
+                write!(f, "Invalid JSON at byte {}: {}", offset, message)
+            }
+            JsonError::Shape { path, message } => {
+                write!(f, "Unexpected JSON at {}: {}", path, message)
+            }
+        }
+    }
+}
+
+impl error::Error for JsonError {}
+
+/* Recursive descent reader over the text, pos being the byte offset of the next character */
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::Syntax {
+            offset: self.pos,
+            message: message.to_string(),
+        }
+    }
+
+    /* Skips past the expected character, or fails without moving */
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        self.skip_space();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_space();
+        match self.peek() {
+            Some('{') => self.object(),
+            Some('[') => self.array(),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let start = self.pos;
+                while let Some('a'..='z') = self.peek() {
+                    self.pos += 1;
+                }
+                match &self.text[start..self.pos] {
+                    "null" => Ok(Json::Null),
+                    "true" => Ok(Json::Bool(true)),
+                    "false" => Ok(Json::Bool(false)),
+                    _ => {
+                        self.pos = start;
+                        Err(self.error("expected a value"))
+                    }
+                }
+            }
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_space();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.skip_space();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a string key"));
+            }
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.value()?));
+            self.skip_space();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_space();
+        if self.peek() == Some(']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_space();
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    /* Reads a number as JSON writes it e.g. -12.5e3, which Rust's own parser then converts exactly */
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.pos;
+        let digits = |reader: &mut Self| {
+            let from = reader.pos;
+            while let Some('0'..='9') = reader.peek() {
+                reader.pos += 1;
+            }
+            reader.pos > from
+        };
+        if self.peek() == Some('-') {
+            self.pos += 1;
+        }
+        let mut valid = digits(self);
+        if valid && self.peek() == Some('.') {
+            self.pos += 1;
+            valid = digits(self);
+        }
+        if valid && matches!(self.peek(), Some('e' | 'E')) {
+            self.pos += 1;
+            if let Some('+' | '-') = self.peek() {
+                self.pos += 1;
+            }
+            valid = digits(self);
+        }
+        if !valid {
+            return Err(self.error("expected a digit"));
+        }
+        match self.text[start..self.pos].parse() {
+            Ok(n) => Ok(Json::Number(n)),
+            Err(_) => Err(JsonError::Syntax {
+                offset: start,
+                message: "invalid number".to_string(),
+            }),
+        }
+    }
+
+    /* Reads a string starting at its opening quote, undoing the escapes including \uXXXX pairs */
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(s),
+                Some('\\') => {
+                    let c = match self.next() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => self.escaped_char()?,
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    s.push(c);
+                }
+                Some(c) if c < ' ' => return Err(self.error("control character in string")),
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /* The character of a \u escape, which for characters beyond U+FFFF is a pair of surrogates */
+    fn escaped_char(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xd800..0xdc00).contains(&high) {
+            if !self.text[self.pos..].starts_with("\\u") {
+                return Err(self.error("expected a low surrogate"));
+            }
+            self.pos += 2;
+            let low = self.hex4()?;
+            if !(0xdc00..0xe000).contains(&low) {
+                return Err(self.error("expected a low surrogate"));
+            }
+            0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("invalid character escape"))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = self.text.get(self.pos..self.pos + 4);
+        match hex.filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit())) {
+            Some(hex) => {
+                self.pos += 4;
+                Ok(u32::from_str_radix(hex, 16).unwrap())
+            }
+            None => Err(self.error("expected 4 hex digits")),
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self {
+        Json::Number(n)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Self {
+        Json::Array(items)
+    }
+}
+
+/* None is null */
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+/* Writes s as a JSON string, escaping quotes, backslashes and control characters */
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if c < ' ' => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", real_text(*n)),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values() {
+        let json = Json::object([
+            ("input", "1 / 3".into()),
+            ("ok", true.into()),
+            ("number", (1.0 / 4.0).into()),
+            ("whole", 6.0.into()),
+            ("span", Json::Null),
+            ("missing", Option::<f64>::None.into()),
+            ("list", vec![Json::from(1.0), f64::INFINITY.into()].into()),
+        ]);
+        assert_eq!(
+            json.to_string(),
+            r#"{"input":"1 / 3","ok":true,"number":0.25,"whole":6,"span":null,"missing":null,"list":[1,null]}"#
+        );
+        assert_eq!(Json::Object(Vec::new()).to_string(), "{}");
+
+        // very large and very small numbers switch to an exponent at the same place as values do
+        for (n, text) in [
+            (1e-320, "1e-320"),
+            (2f64.powi(70), "1.1805916207174113e21"),
+            (1e16, "1e16"),
+            (9999999999999998.0, "9999999999999998"),
+            (0.00001, "0.00001"),
+            (-0.000001, "-1e-6"),
+        ] {
+            assert_eq!(Json::Number(n).to_string(), text);
+            assert_eq!(Json::parse(text), Ok(Json::Number(n)));
+        }
+    }
+
+    #[test]
+    fn test_escaping() {
+        let json = Json::from("say \"hi\"\\\n\tπ\u{1}");
+        assert_eq!(json.to_string(), r#""say \"hi\"\\\n\tπ\u0001""#);
+    }
+
+    #[test]
+    fn test_parse() {
+        let text = r#"{"input":"1 / 3","ok":true,"number":0.25,"whole":-6,"span":null,"list":[1e3,[]],"empty":{}}"#;
+        let json = Json::parse(text).unwrap();
+        assert_eq!(json.to_string(), text.replace("1e3", "1000"));
+        assert_eq!(json.get("whole"), Some(&Json::Number(-6.0)));
+        assert_eq!(json.get("missing"), None);
+        assert_eq!(
+            Json::parse(" [ \"a\\u00e9\\n\\ud83d\\ude00\" , false ] ").unwrap(),
+            Json::Array(vec!["aé\n😀".into(), false.into()])
+        );
+        // whatever is written reads back the same
+        let json = Json::from("say \"hi\"\\\n\tπ\u{1}");
+        assert_eq!(Json::parse(&json.to_string()), Ok(json));
+        assert_eq!(Json::parse("0.1").unwrap(), Json::Number(0.1));
+
+        let offset = |text: &str| match Json::parse(text) {
+            Err(JsonError::Syntax { offset, .. }) => offset,
+            other => panic!("{:?} from {}", other, text),
+        };
+        assert_eq!(offset(""), 0);
+        assert_eq!(offset("[1,]"), 3);
+        assert_eq!(offset("{\"a\" 1}"), 5);
+        assert_eq!(offset("nul"), 0);
+        assert_eq!(offset("1."), 2);
+        assert_eq!(offset("\"abc"), 4);
+        assert_eq!(offset("[1] 2"), 4);
+        assert_eq!(offset("\"\\ud83d\""), 7);
+    }
+}
@@ -1,6 +1,75 @@
 //in mod.rs
 
 pub mod ast;
+pub mod bigint;
+pub mod bytecode;
+pub mod complex;
+pub mod derivative;
+pub mod environment;
+pub mod explain;
+pub mod functions;
+#[cfg(all(feature = "jit", target_arch = "x86_64", target_os = "linux"))]
+pub mod jit;
+pub mod json;
 pub mod parser;
+pub mod rational;
+pub mod render;
+pub mod serialise;
+pub mod simplify;
 pub mod token;
 pub mod tokenizer;
+pub mod units;
+pub mod value;
+
+use std::{error, fmt};
+
+/*
+Any error from evaluating a line of input
+Parse => the input is not a valid expression, Eval => the expression is valid but its maths is not
+*/
+#[derive(Debug)]
+pub enum CalcError {
+    Parse(parser::ParseError),
+    Eval(ast::EvalError),
+}
+
+impl CalcError {
+    /* Short name for the kind of error, for programs reading the output e.g. division_by_zero */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CalcError::Parse(e) => e.kind(),
+            CalcError::Eval(e) => e.kind(),
+        }
+    }
+
+    /* Where in the input the error is - None for errors in evaluating, which have no location */
+    pub fn span(&self) -> Option<token::Span> {
+        match self {
+            CalcError::Parse(e) => Some(e.span()),
+            CalcError::Eval(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for CalcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CalcError::Parse(e) => write!(f, "{}", e),
+            CalcError::Eval(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl error::Error for CalcError {}
+
+impl From<parser::ParseError> for CalcError {
+    fn from(e: parser::ParseError) -> Self {
+        CalcError::Parse(e)
+    }
+}
+
+impl From<ast::EvalError> for CalcError {
+    fn from(e: ast::EvalError) -> Self {
+        CalcError::Eval(e)
+    }
+}
@@ -2,47 +2,207 @@
 // parser.rs uses the output of tokenizer.rs to construct the overall AST
 
 use crate::parsemaths::ast::Node;
-use crate::parsemaths::token::{OperPrec, Token};
+use crate::parsemaths::token::{Assoc, OperPrec, Span, Token};
 use crate::parsemaths::tokenizer::Tokenizer;
-use std::fmt;
+use std::{error, fmt};
 
-pub(crate) struct Parser<'a> {
+/* Choices the user can make about how expressions are read, see Products and Negation */
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Syntax {
+    pub products: Products,
+    pub negation: Negation,
+}
+
+/*
+Whether a product can be written without '*'
+Implicit: an operand directly followed by a name, '(' or '√' is multiplied by it e.g. 2x, 2(3),
+2pi, 2sin(x) and (a)(b), and so is a number after a ')' e.g. (2)3 (the default)
+Two numbers are never multiplied e.g. 2 3, and a name followed by '(' is a call, not a product
+The product binds like '*' and '/', so '^' is applied first and the operators of a level are
+applied left to right: 2x^2 = 2 * x^2, -2x = (-2) * x and 1/2x = (1/2) * x
+Strict: every product needs an explicit '*'
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Products {
+    #[default]
+    Implicit,
+    Strict,
+}
+
+/*
+How a prefix operator ('-', '~', '!' or '√') and a following '^' are grouped
+PowerFirst: as in maths, the power is taken first, -2^2 = -(2^2) = -4 (the default)
+The prefix still binds tighter than '*', so -2 * 3 = (-2) * 3
+MinusFirst: the prefix applies to the operand right after it, -2^2 = (-2)^2 = 4
+*/
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Negation {
+    #[default]
+    PowerFirst,
+    MinusFirst,
+}
+
+pub struct Parser<'a> {
     tokenizer: Tokenizer<'a>,
     current_token: Token,
+    // location of current_token in the input, reported in any error raised at that token
+    current_span: Span,
+    // whether the token before current_token was a ')', after which a number can be a factor
+    after_paren: bool,
+    // the input expression, used to quote the offending text in error messages
+    source: &'a str,
+    syntax: Syntax,
+    // whether errors are collected in errors and parsing carries on, see parse_recovering
+    recovering: bool,
+    errors: Vec<ParseError>,
+    // spans of the nodes built so far, each after those of the nodes below it, see span_tree
+    spans: Vec<Span>,
+    // end of the token before current_token, which is where a node just built ends
+    last_end: usize,
+}
+
+/*
+Result of parse_recovering: the tree for as much of the input as could be read, with an Error node
+wherever an operand is missing or unreadable, and every error found in the order of the input
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recovered {
+    pub ast: Node,
+    pub errors: Vec<ParseError>,
+}
+
+/*
+Where in the input a node was read from, with the same for each node below it in the order of
+Node::children e.g. for 1 + 2 * 3 the Add spans 0..9 and its right side 4..9
+A node in parentheses spans them too, and an Error node spans the token it could not use
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
 }
 
 impl<'a> Parser<'a> {
-    /* Creates a new instance of the parser, creating a Tokenizer instance and stores 1st token */
+    /* Creates a new instance of the parser with the default syntax */
     pub fn new(expr: &'a str) -> Result<Self, ParseError> {
+        Parser::with_syntax(expr, Syntax::default())
+    }
+
+    /* Creates a new instance of the parser, creating a Tokenizer instance and stores 1st token */
+    pub fn with_syntax(expr: &'a str, syntax: Syntax) -> Result<Self, ParseError> {
         // lexer is a new Tokenizer with the express passed to it
         let mut lexer = Tokenizer::new(expr);
         /*
         cur_token is result of a match whereby a successful .next() stores the value in cur_token
         if no value is returned a parse error is returned with a custom message
         */
-        let cur_token = match lexer.next() {
-            Some(token) => token,
-            None => return Err(ParseError::InvalidOperator("Invalid character".into())),
-        };
+        let cur_token = lexer
+            .next()
+            .expect("the tokenizer never runs out of tokens");
+        if let Token::Invalid(reason) = cur_token.token {
+            return Err(invalid_token(expr, cur_token.span, reason));
+        }
         // function is expecting a Result so the result is passed into an Ok()
         Ok(Parser {
             tokenizer: lexer,
-            current_token: cur_token,
+            current_token: cur_token.token,
+            current_span: cur_token.span,
+            after_paren: false,
+            source: expr,
+            syntax,
+            recovering: false,
+            errors: Vec::new(),
+            spans: Vec::new(),
+            last_end: 0,
         })
     }
 
+    /*
+    Parses the whole input, carrying on after each error rather than stopping at the first
+    so every mistake in a long expression can be shown at once e.g. 2 * * 3 + (4 -
+    A missing operand becomes an Error node, a missing ')' is taken as read and anything left
+    over after a complete expression is skipped up to the next operator
+    */
+    pub fn parse_recovering(expr: &'a str, syntax: Syntax) -> Recovered {
+        let mut parser = Parser {
+            tokenizer: Tokenizer::new(expr),
+            current_token: Token::EoF,
+            current_span: Span::new(0, 0),
+            after_paren: false,
+            source: expr,
+            syntax,
+            recovering: true,
+            errors: Vec::new(),
+            spans: Vec::new(),
+            last_end: 0,
+        };
+        // in recovery no error is returned, each is recorded in parser.errors instead
+        let ast = parser
+            .get_next_token()
+            .and_then(|_| parser.parse())
+            .unwrap_or_else(|e| {
+                parser.record(e.clone());
+                Node::Error(e.span())
+            });
+        Recovered {
+            ast,
+            errors: parser.errors,
+        }
+    }
+
     /*
     Generates the AST from the tokens and is the main output of parser.rs
     Invokes the generate_ast() method (priv), a recursive method that processes the AST & returns
     */
     pub fn parse(&mut self) -> Result<Node, ParseError> {
-        let ast = self.generate_ast(OperPrec::DefaultZero);
+        // a statement of the form `let name = expr` binds the value of expr to name
+        let ast = if self.current_token == Token::Let {
+            self.parse_assignment()
+        } else {
+            self.generate_ast(OperPrec::DefaultZero)
+        };
         // if the match is successful it returns a node - if not, propagates the received error
         match ast {
-            Ok(ast) => Ok(ast),
+            Ok(mut ast) => {
+                // anything left over after a complete expression is an error e.g. '2 3' or '1)'
+                self.check_paren(Token::EoF)?;
+                // when recovering, the tokens up to the next operator are skipped and the
+                // expression carries on from there e.g. 2 3 + 4 reads as 2 + 4
+                while self.current_token != Token::EoF {
+                    self.get_next_token()?;
+                    if self.current_token.get_oper_prec() != OperPrec::DefaultZero {
+                        ast = self.continue_ast(ast, OperPrec::DefaultZero)?;
+                        self.check_paren(Token::EoF)?;
+                    }
+                }
+                Ok(ast)
+            }
             Err(e) => Err(e),
         }
     }
+
+    /*
+    Where in the input each node of ast was read from, for drawing the tree against the source
+    ast must be the tree parse() returned - None if it is some other tree
+    */
+    pub fn span_tree(&self, ast: &Node) -> Option<SpanTree> {
+        // the spans were recorded children first, so the last one is the root's and the one
+        // before it is the root of the last child's subtree
+        fn take(node: &Node, spans: &mut Vec<Span>) -> Option<SpanTree> {
+            let span = spans.pop()?;
+            let mut children = node
+                .children()
+                .into_iter()
+                .rev()
+                .map(|child| take(child, spans))
+                .collect::<Option<Vec<_>>>()?;
+            children.reverse();
+            Some(SpanTree { span, children })
+        }
+        let mut spans = self.spans.clone();
+        let tree = take(ast, &mut spans)?;
+        spans.is_empty().then_some(tree)
+    }
 }
 
 /*
@@ -62,18 +222,30 @@ impl<'a> Parser<'a> {
     ----
     1+2*3 -> Add(Number(1.0), Multiply(Number(2.0), Number(3.0)))
     1*2+3 -> Add(Multiply(Number(1.0), Number(2.0)), Number(3.0))
+    2^3^2 -> Caret(Number(2.0), Caret(Number(3.0), Number(2.0))), as '^' groups to the right
     */
     fn generate_ast(&mut self, oper_prec: OperPrec) -> Result<Node, ParseError> {
-        let mut left_expr = self.parse_number()?;
-        while oper_prec < self.current_token.get_oper_prec() {
+        let left_expr = self.parse_number()?;
+        self.continue_ast(left_expr, oper_prec)
+    }
+
+    /* Applies the operators that follow left_expr and bind tighter than oper_prec to it */
+    fn continue_ast(
+        &mut self,
+        mut left_expr: Node,
+        oper_prec: OperPrec,
+    ) -> Result<Node, ParseError> {
+        while self.takes_left_side(oper_prec) {
             // recursion base case
             if self.current_token == Token::EoF {
                 break;
             }
+            // the new node starts where left_expr does, whose span was the last recorded
+            let start = self.spans.last().map_or(0, |span| span.start);
             // declares variable as output of converting left_expr token to a node
             let right_expr = self.convert_token_to_node(left_expr.clone())?;
             // shifts the value in right_expr to left_expr to continue recursion
-            left_expr = right_expr;
+            left_expr = self.spanned(start, right_expr);
         }
         Ok(left_expr)
     }
@@ -83,36 +255,214 @@ impl<'a> Parser<'a> {
     Takes current token and checks 3 things:
     1 - Is token of form Num(i)
     2 - Does token have a sign e.g. -1+2 -> Add(Negative(Number(1)), Number(2))
-    3 - Pairs of parentheses - if an expression is found within paren it treats this as a multiply
+    3 - Pairs of parentheses - the expression within them is parsed on its own
     */
     fn parse_number(&mut self) -> Result<Node, ParseError> {
         let token = self.current_token.clone();
-        match token {
+        let start = self.current_span.start;
+        let node = match token {
             Token::Subtract => {
                 self.get_next_token()?;
-                let expr = self.generate_ast(OperPrec::Negative)?;
-                Ok(Node::Negative(Box::new(expr)))
+                let expr = self.prefix_operand()?;
+                Node::Negative(Box::new(expr))
             }
             Token::Num(i) => {
                 self.get_next_token()?;
-                Ok(Node::Number(i))
+                Node::Number(i)
+            }
+            Token::Int(i) => {
+                self.get_next_token()?;
+                Node::Integer(i)
+            }
+            Token::Decimal(text) => {
+                self.get_next_token()?;
+                Node::Decimal(text)
+            }
+            Token::Imag(i) => {
+                self.get_next_token()?;
+                Node::Imaginary(i)
+            }
+            Token::Quantity(i, unit) => {
+                self.get_next_token()?;
+                Node::Quantity(i, unit)
+            }
+            Token::BitNot => {
+                self.get_next_token()?;
+                let expr = self.prefix_operand()?;
+                Node::BitNot(Box::new(expr))
+            }
+            Token::Not => {
+                self.get_next_token()?;
+                let expr = self.prefix_operand()?;
+                Node::Not(Box::new(expr))
+            }
+            // √x is a call to sqrt, binding like a unary minus
+            Token::Sqrt => {
+                self.get_next_token()?;
+                let expr = self.prefix_operand()?;
+                Node::Call("sqrt".into(), vec![expr])
+            }
+            Token::Bool(b) => {
+                self.get_next_token()?;
+                Node::Bool(b)
             }
             Token::LeftParen => {
                 self.get_next_token()?;
                 let expr = self.generate_ast(OperPrec::DefaultZero)?;
                 self.check_paren(Token::RightParen)?;
+                // the expression is not a new node, its span is widened to take in the parentheses
+                if let Some(span) = self.spans.last_mut() {
+                    *span = Span::new(start, self.last_end);
+                }
+                return Ok(expr);
+            }
+            Token::Ident(name) => {
+                self.get_next_token()?;
+                // an identifier directly followed by '(' is a function call
                 if self.current_token == Token::LeftParen {
-                    let right = self.generate_ast(OperPrec::MulDiv)?;
-                    return Ok(Node::Multiply(Box::new(expr), Box::new(right)));
+                    let args = self.parse_arguments()?;
+                    Node::Call(name, args)
+                } else {
+                    Node::Variable(name)
+                }
+            }
+            _ => {
+                let span = self.current_span;
+                self.recover(ParseError::UnableToParse {
+                    span,
+                    expected: "a number, a name, '-', '~', '!', '√' or '('".into(),
+                    found: self.found(),
+                })?;
+                // an operator or a closing token is left for the caller, so recovery carries on
+                // from it, anything else cannot be used and is skipped e.g. the '=' of 2 + = 3
+                let closes = matches!(
+                    self.current_token,
+                    Token::RightParen | Token::Comma | Token::Colon | Token::EoF
+                );
+                if !closes && self.current_token.get_oper_prec() == OperPrec::DefaultZero {
+                    self.get_next_token()?;
                 }
-                Ok(expr)
+                self.spans.push(span);
+                return Ok(Node::Error(span));
+            }
+        };
+        Ok(self.spanned(start, node))
+    }
+
+    /* Parses the operand of a prefix operator such as a unary minus, see Negation */
+    fn prefix_operand(&mut self) -> Result<Node, ParseError> {
+        match self.syntax.negation {
+            Negation::PowerFirst => self.generate_ast(OperPrec::Negative),
+            Negation::MinusFirst => self.parse_number(),
+        }
+    }
+
+    /*
+    Parses the comma-separated arguments of a function call e.g. (1, 2+3)
+    The LeftParen token is the current token when this is called
+    */
+    fn parse_arguments(&mut self) -> Result<Vec<Node>, ParseError> {
+        self.get_next_token()?;
+        let mut args = Vec::new();
+        if self.current_token == Token::RightParen {
+            self.get_next_token()?;
+            return Ok(args);
+        }
+        loop {
+            args.push(self.generate_ast(OperPrec::DefaultZero)?);
+            self.skip_invalid()?;
+            if self.current_token == Token::Comma {
+                self.get_next_token()?;
+            } else {
+                self.check_paren(Token::RightParen)?;
+                return Ok(args);
             }
-            _ => Err(ParseError::UnableToParse("Unable to parse".to_string())),
+        }
+    }
+
+    /*
+    Parses an assignment statement: let <identifier> = <expression>
+    The Let token is the current token when this is called
+    */
+    fn parse_assignment(&mut self) -> Result<Node, ParseError> {
+        let start = self.current_span.start;
+        self.get_next_token()?;
+        let name = match self.current_token.clone() {
+            Token::Ident(name) => name,
+            _ => {
+                self.recover(ParseError::UnableToParse {
+                    span: self.current_span,
+                    expected: "a variable name after 'let'".into(),
+                    found: self.found(),
+                })?;
+                // when recovering, the value is still read but not assigned, and the token in
+                // place of the name is skipped unless it is the '=' e.g. let = 3
+                if self.current_token != Token::Assign {
+                    self.get_next_token()?;
+                }
+                self.check_paren(Token::Assign)?;
+                return self.generate_ast(OperPrec::DefaultZero);
+            }
+        };
+        self.get_next_token()?;
+        self.check_paren(Token::Assign)?;
+        let expr = self.generate_ast(OperPrec::DefaultZero)?;
+        Ok(self.spanned(start, Node::Assign(name, Box::new(expr))))
+    }
+
+    /* Records the span of a node just built, from start to the end of the last token it used */
+    fn spanned(&mut self, start: usize, node: Node) -> Node {
+        self.spans.push(Span::new(start, self.last_end));
+        node
+    }
+
+    /*
+    Checks whether the current operator takes the expression parsed at the level oper_prec as its
+    left side - an operator of a higher level always does, one of the same level only when it
+    groups to the right, which is what makes a - b - c = (a - b) - c but a ^ b ^ c = a ^ (b ^ c)
+    */
+    fn takes_left_side(&self, oper_prec: OperPrec) -> bool {
+        let prec = self.operator_precedence();
+        oper_prec < prec || (oper_prec == prec && self.current_token.get_assoc() == Assoc::Right)
+    }
+
+    /*
+    Precedence of the current token as an operator, where a token that starts an implicit product
+    is at the level of '*'
+    */
+    fn operator_precedence(&self) -> OperPrec {
+        if self.implicit_product() {
+            OperPrec::MulDiv
+        } else {
+            self.current_token.get_oper_prec()
+        }
+    }
+
+    /* Checks whether the current token starts the right side of a product without '*', see Syntax */
+    fn implicit_product(&self) -> bool {
+        self.syntax.products == Products::Implicit && self.starts_factor()
+    }
+
+    /* Checks whether the current token could be multiplied by the operand before it */
+    fn starts_factor(&self) -> bool {
+        match self.current_token {
+            Token::Ident(_) | Token::LeftParen | Token::Sqrt => true,
+            Token::Num(_)
+            | Token::Int(_)
+            | Token::Decimal(_)
+            | Token::Imag(_)
+            | Token::Quantity(..) => self.after_paren,
+            _ => false,
         }
     }
 
     /* Parses operators and converts to AST*/
     fn convert_token_to_node(&mut self, left_expr: Node) -> Result<Node, ParseError> {
+        // the token is not consumed, it is the start of the right side
+        if self.implicit_product() {
+            let right_expr = self.generate_ast(OperPrec::MulDiv)?;
+            return Ok(Node::Multiply(Box::new(left_expr), Box::new(right_expr)));
+        }
         match self.current_token {
             Token::Add => {
                 self.get_next_token()?;
@@ -140,32 +490,219 @@ impl<'a> Parser<'a> {
                 Ok(Node::Divide(Box::new(left_expr), Box::new(right_expr)))
             }
 
+            Token::FloorDivide => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::MulDiv)?;
+                Ok(Node::FloorDivide(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::Modulo => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::MulDiv)?;
+                Ok(Node::Modulo(Box::new(left_expr), Box::new(right_expr)))
+            }
+
             Token::Caret => {
                 self.get_next_token()?;
                 // Access right side of the expression
                 let right_expr = self.generate_ast(OperPrec::Power)?;
                 Ok(Node::Caret(Box::new(left_expr), Box::new(right_expr)))
             }
-            _ => Err(ParseError::InvalidOperator(format!(
-                "Please enter a valid operator {:?}",
-                self.current_token
-            ))),
+
+            Token::BitAnd => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::BitAnd)?;
+                Ok(Node::BitAnd(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::BitOr => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::BitOr)?;
+                Ok(Node::BitOr(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::BitXor => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::BitXor)?;
+                Ok(Node::BitXor(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::ShiftLeft => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Shift)?;
+                Ok(Node::ShiftLeft(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::ShiftRight => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Shift)?;
+                Ok(Node::ShiftRight(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::Equal => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::Equal(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::NotEqual => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::NotEqual(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::Less => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::Less(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::LessEqual => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::LessEqual(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::Greater => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::Greater(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::GreaterEqual => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Comparison)?;
+                Ok(Node::GreaterEqual(
+                    Box::new(left_expr),
+                    Box::new(right_expr),
+                ))
+            }
+
+            Token::And => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::And)?;
+                Ok(Node::And(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            Token::Or => {
+                self.get_next_token()?;
+                let right_expr = self.generate_ast(OperPrec::Or)?;
+                Ok(Node::Or(Box::new(left_expr), Box::new(right_expr)))
+            }
+
+            // cond ? a : b - the branch after ':' takes in any further '?', so they nest to the right
+            // a conversion after the branch applies to the whole conditional
+            Token::Question => {
+                self.get_next_token()?;
+                let then_expr = self.generate_ast(OperPrec::DefaultZero)?;
+                self.check_paren(Token::Colon)?;
+                let else_expr = self.generate_ast(OperPrec::Convert)?;
+                Ok(Node::Conditional(
+                    Box::new(left_expr),
+                    Box::new(then_expr),
+                    Box::new(else_expr),
+                ))
+            }
+
+            // postfix operators have no right side either
+            // x! is factorial(x), so it extends to non-integers as gamma(x + 1) e.g. 0.5!
+            Token::Not => {
+                self.get_next_token()?;
+                Ok(Node::Call("factorial".into(), vec![left_expr]))
+            }
+            // x% is x / 100 e.g. price * 15%
+            Token::Percent => {
+                self.get_next_token()?;
+                Ok(Node::Percent(Box::new(left_expr)))
+            }
+
+            // a conversion has no right side, the unit is part of the To token
+            // without a unit the error points at what is there instead e.g. the end of 2 m to
+            Token::To(ref unit) => {
+                let unit = unit.clone();
+                self.get_next_token()?;
+                match unit {
+                    Some(unit) => Ok(Node::Convert(Box::new(left_expr), unit)),
+                    None => {
+                        self.recover(ParseError::UnableToParse {
+                            span: self.current_span,
+                            expected: "a unit after 'to'".into(),
+                            found: self.found(),
+                        })?;
+                        Ok(left_expr)
+                    }
+                }
+            }
+            _ => Err(ParseError::InvalidOperator {
+                span: self.current_span,
+                expected: "an operator".into(),
+                found: self.found(),
+            }),
         }
     }
 
     /*
     Helper Method
     Checks for matching parentheses in expression
+    When recovering, a missing token is recorded as an error and taken as read, except after a token
+    that could not be read, which was reported already and is skipped e.g. the second " of x ? "a" : 1
     */
     fn check_paren(&mut self, expect: Token) -> Result<(), ParseError> {
+        let skipped = self.skip_invalid()?;
         if expect == self.current_token {
             self.get_next_token()?;
             Ok(())
+        } else if skipped {
+            Ok(())
         } else {
-            Err(ParseError::InvalidOperator(format!(
-                "Expected {:?}\tGot {:?}",
-                expect, self.current_token
-            )))
+            // in the strict syntax a missing '*' is the likely mistake e.g. 2x
+            let expected = if self.syntax.products == Products::Strict && self.starts_factor() {
+                format!("{} or '*'", expect)
+            } else {
+                expect.to_string()
+            };
+            self.recover(ParseError::InvalidOperator {
+                span: self.current_span,
+                expected,
+                found: self.found(),
+            })
+        }
+    }
+
+    /*
+    When recovering, skips the current token if it could not be read, as its error was recorded
+    when it was read - returns whether it did
+    */
+    fn skip_invalid(&mut self) -> Result<bool, ParseError> {
+        let invalid = self.recovering && matches!(self.current_token, Token::Invalid(_));
+        if invalid {
+            self.get_next_token()?;
+        }
+        Ok(invalid)
+    }
+
+    /* Records the error and carries on when recovering, otherwise returns it */
+    fn recover(&mut self, error: ParseError) -> Result<(), ParseError> {
+        if !self.recovering {
+            return Err(error);
+        }
+        self.record(error);
+        Ok(())
+    }
+
+    /*
+    Adds an error to the list, unless one was already found at the same place
+    A single mistake often upsets more than one rule e.g. (1 + has no operand and no ')' at the
+    end, and only the first of these is worth reporting
+    */
+    fn record(&mut self, error: ParseError) {
+        let start = error.span().start;
+        if self
+            .errors
+            .last()
+            .is_none_or(|last| last.span().start != start)
+        {
+            self.errors.push(error);
         }
     }
 
@@ -176,26 +713,105 @@ impl<'a> Parser<'a> {
      */
     fn get_next_token(&mut self) -> Result<(), ParseError> {
         // match used to also catch errors that may appear
-        let next_token = match self.tokenizer.next() {
-            Some(token) => token,
-            None => return Err(ParseError::InvalidOperator("Invalid character".into())),
-        };
+        let next_token = self
+            .tokenizer
+            .next()
+            .expect("the tokenizer never runs out of tokens");
+        // when recovering, the Invalid token is kept so the parser skips over it like any other
+        // token it cannot use
+        if let Token::Invalid(reason) = next_token.token {
+            self.recover(invalid_token(self.source, next_token.span, reason))?;
+        }
         // simply assigns the value of Parser.current_token to the result of success match arm
-        self.current_token = next_token;
+        self.after_paren = self.current_token == Token::RightParen;
+        self.last_end = self.current_span.end;
+        self.current_token = next_token.token;
+        self.current_span = next_token.span;
         // Empty tuple in Ok(()) - if no error occurs no concrete value returns
         Ok(())
     }
+
+    /* Describes the current token for an error message by quoting it from the input */
+    fn found(&self) -> String {
+        match self.current_token {
+            Token::EoF => Token::EoF.to_string(),
+            _ => format!(
+                "'{}'",
+                &self.source[self.current_span.start..self.current_span.end]
+            ),
+        }
+    }
+}
+
+/* Error for characters the tokenizer could not turn into a token e.g. '1.2.3' */
+fn invalid_token(source: &str, span: Span, reason: &str) -> ParseError {
+    ParseError::InvalidToken {
+        span,
+        found: format!("'{}'", &source[span.start..span.end]),
+        reason: reason.into(),
+    }
 }
 
 /*
 Defining custom error types as an enum
-2 options both returning a String to the user of the application
+2 options, both recording where in the input parsing failed, what was expected and what was found
 Debug & Display are needed to print the errors
 */
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParseError {
-    UnableToParse(String),
-    InvalidOperator(String),
+    UnableToParse {
+        span: Span,
+        expected: String,
+        found: String,
+    },
+    InvalidOperator {
+        span: Span,
+        expected: String,
+        found: String,
+    },
+    InvalidToken {
+        span: Span,
+        found: String,
+        reason: String,
+    },
+}
+
+impl ParseError {
+    /* Short name for the kind of error, for programs reading the output */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ParseError::UnableToParse { .. } => "unable_to_parse",
+            ParseError::InvalidOperator { .. } => "invalid_operator",
+            ParseError::InvalidToken { .. } => "invalid_token",
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            ParseError::UnableToParse { span, .. }
+            | ParseError::InvalidOperator { span, .. }
+            | ParseError::InvalidToken { span, .. } => *span,
+        }
+    }
+
+    /*
+    Builds a marker line to print underneath the input e.g.
+    2 * * 3
+        ^
+    The span is in bytes, so it is converted to a count of characters for the terminal column
+    */
+    pub fn marker(&self, source: &str) -> String {
+        let span = self.span();
+        let start = source.get(..span.start).map_or(0, |s| s.chars().count());
+        let width = source
+            .get(span.start..span.end)
+            .map_or(0, |s| s.chars().count());
+        format!(
+            "{}^{}",
+            " ".repeat(start),
+            "~".repeat(width.saturating_sub(1))
+        )
+    }
 }
 
 /*
@@ -204,26 +820,33 @@ Implements Display for the custom type otherwise it would not be possible to pri
 impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match &self {
-            self::ParseError::UnableToParse(e) => write!(f, "Error in evaluating {}", e),
-            self::ParseError::InvalidOperator(e) => write!(f, "Error in evaluating {}", e),
+            self::ParseError::UnableToParse {
+                expected, found, ..
+            } => write!(f, "Unable to parse: expected {}, found {}", expected, found),
+            self::ParseError::InvalidOperator {
+                expected, found, ..
+            } => write!(
+                f,
+                "Invalid operator: expected {}, found {}",
+                expected, found
+            ),
+            self::ParseError::InvalidToken { found, reason, .. } => {
+                write!(f, "Invalid token: {} {}", found, reason)
+            }
         }
     }
 }
 
-/*
-Implements conversion from Box<dyn Error> into the custom ParseError error
-*/
-impl std::convert::From<std::boxed::Box<dyn std::error::Error>> for ParseError {
-    fn from(_evalerr: std::boxed::Box<dyn std::error::Error>) -> Self {
-        ParseError::UnableToParse("Unable to parse".into())
-    }
-}
+impl error::Error for ParseError {}
 
 // Unit Tests
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parsemaths::ast::Node::{Add, Number};
+    use crate::parsemaths::ast::Node::{
+        Add, Assign, BitAnd, BitNot, BitOr, Call, Integer, Modulo, Multiply, Number, ShiftLeft,
+        Variable,
+    };
 
     #[test]
     fn test_addition() {
@@ -231,4 +854,385 @@ mod tests {
         let expected = Add(Box::new(Number(1.0)), Box::new(Number(2.0)));
         assert_eq!(parser.parse().unwrap(), expected);
     }
+
+    #[test]
+    fn test_assignment() {
+        let mut parser = Parser::new("let x = ans + 2").unwrap();
+        let expected = Assign(
+            "x".into(),
+            Box::new(Add(Box::new(Variable("ans".into())), Box::new(Number(2.0)))),
+        );
+        assert_eq!(parser.parse().unwrap(), expected);
+        assert!(Parser::new("let 2 = 3").unwrap().parse().is_err());
+        assert!(Parser::new("2 3").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_function_call() {
+        let mut parser = Parser::new("max(x, 2)").unwrap();
+        let expected = Call("max".into(), vec![Variable("x".into()), Number(2.0)]);
+        assert_eq!(parser.parse().unwrap(), expected);
+        assert!(Parser::new("max(1,").unwrap().parse().is_err());
+        // √ is a prefix for sqrt
+        let mut parser = Parser::new("√x × 2").unwrap();
+        let expected = Multiply(
+            Box::new(Call("sqrt".into(), vec![Variable("x".into())])),
+            Box::new(Number(2.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        // | binds loosest, then &, then shifts, then arithmetic
+        let mut parser = Parser::new("1 | 0xf0 & 1 << 4 % 3").unwrap();
+        let expected = BitOr(
+            Box::new(Number(1.0)),
+            Box::new(BitAnd(
+                Box::new(Integer(0xf0)),
+                Box::new(ShiftLeft(
+                    Box::new(Number(1.0)),
+                    Box::new(Modulo(Box::new(Number(4.0)), Box::new(Number(3.0)))),
+                )),
+            )),
+        );
+        assert_eq!(parser.parse().unwrap(), expected);
+        let mut parser = Parser::new("~x").unwrap();
+        let expected = BitNot(Box::new(Variable("x".into())));
+        assert_eq!(parser.parse().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_conditional() {
+        use crate::parsemaths::ast::Node::{And, Conditional, Greater, Less};
+
+        // comparisons bind tighter than &&, which binds tighter than '?'
+        let mut parser = Parser::new("load > 0.8 && mem < 0.5 ? 1 : 0").unwrap();
+        let expected = Conditional(
+            Box::new(And(
+                Box::new(Greater(
+                    Box::new(Variable("load".into())),
+                    Box::new(Number(0.8)),
+                )),
+                Box::new(Less(
+                    Box::new(Variable("mem".into())),
+                    Box::new(Number(0.5)),
+                )),
+            )),
+            Box::new(Number(1.0)),
+            Box::new(Number(0.0)),
+        );
+        assert_eq!(parser.parse().unwrap(), expected);
+        assert!(Parser::new("x ? 1").unwrap().parse().is_err());
+    }
+
+    #[test]
+    fn test_implicit_multiplication() {
+        let parse = |expr: &str, products: Products| {
+            let syntax = Syntax {
+                products,
+                ..Syntax::default()
+            };
+            Parser::with_syntax(expr, syntax)
+                .and_then(|mut parser| parser.parse())
+                .map(|ast| ast.to_string())
+        };
+        let cases = [
+            ("2(3)", "2 * 3"),
+            ("(2)3", "2 * 3"),
+            ("(a)(b)", "a * b"),
+            ("2pi", "2 * pi"),
+            ("3x", "3 * x"),
+            ("2sin(x)", "2 * sin(x)"),
+            ("2x^2", "2 * x ^ 2"),
+            ("-2x", "-2 * x"),
+            ("1/2x", "1 / 2 * x"),
+            ("2^3x", "2 ^ 3 * x"),
+            ("2 x y + 1", "2 * x * y + 1"),
+            ("4(4-5)", "4 * (4 - 5)"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                parse(expr, Products::Implicit),
+                Ok(expected.into()),
+                "{}",
+                expr
+            );
+            assert!(parse(expr, Products::Strict).is_err(), "{}", expr);
+        }
+        // two numbers in a row are a mistake rather than a product
+        assert!(parse("2 3", Products::Implicit).is_err());
+        assert!(parse("x 2", Products::Implicit).is_err());
+        assert_eq!(parse("2 * x", Products::Strict), Ok("2 * x".into()));
+        assert_eq!(
+            parse("2x", Products::Strict).unwrap_err().to_string(),
+            "Invalid operator: expected end of input or '*', found 'x'"
+        );
+        assert_eq!(
+            parse("(2)(3)", Products::Strict).unwrap_err().to_string(),
+            "Invalid operator: expected end of input or '*', found '('"
+        );
+        // a unit is part of its number, so a name after it is a product and one before it is not
+        let cases = [
+            ("2 m x", "2 m * x"),
+            ("2m x", "2 m * x"),
+            ("2 km y^2", "2 km * y ^ 2"),
+            ("2 m (3)", "2 m * 3"),
+            ("2 x m", "2 * x * m"),
+            ("(2) m", "2 * m"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(
+                parse(expr, Products::Implicit),
+                Ok(expected.into()),
+                "{}",
+                expr
+            );
+            assert!(parse(expr, Products::Strict).is_err(), "{}", expr);
+        }
+        assert_eq!(parse("2 m", Products::Strict), Ok("2 m".into()));
+        assert_eq!(parse("2m * x", Products::Strict), Ok("2 m * x".into()));
+    }
+
+    #[test]
+    fn test_postfix_operators() {
+        use crate::parsemaths::ast::{eval, EvalError};
+        use crate::parsemaths::environment::{Environment, IntWidth, NumberMode};
+        use crate::parsemaths::value::Value;
+
+        let parse = |expr: &str| Parser::new(expr).unwrap().parse().unwrap();
+        let cases = [
+            ("3!", "factorial(3)"),
+            ("-3!", "-factorial(3)"),
+            ("2^3!", "2 ^ factorial(3)"),
+            ("x!^2", "factorial(x) ^ 2"),
+            ("3!!", "factorial(factorial(3))"),
+            ("price * 15%", "price * 15%"),
+            ("200 - 5%", "200 - 5%"),
+            ("5% - 1", "5% - 1"),
+            ("(5%) - 1", "5% - 1"),
+            ("15% + 1%", "15% + 1%"),
+            ("7 % 3", "7 % 3"),
+            ("7 % (-3)", "7 % (-3)"),
+            ("7 mod -3", "7 % (-3)"),
+            ("x%(y)", "x % y"),
+            ("5% xor 1 != x% to mi", "5% xor 1 != x% to mi"),
+        ];
+        for (expr, expected) in cases {
+            assert_eq!(parse(expr).to_string(), expected, "{}", expr);
+        }
+        let mut env = Environment::new();
+        let mut value = |expr: &str| eval(parse(expr), &mut env).unwrap().to_f64();
+        assert_eq!(value("5!"), 120.0);
+        assert_eq!(value("80 * 15%"), 12.0);
+        // a sign after '%' takes away from or adds to the percentage
+        assert_eq!(value("5% - 1"), -0.95);
+        assert_eq!(value("200 * 15% - 5"), 25.0);
+        assert_eq!(value("200 * 15% + 2"), 32.0);
+        assert_eq!(value("15% + 1%"), 0.16);
+        assert_eq!(value("let price = 80"), 80.0);
+        assert_eq!(value("price * 15% - 2"), 10.0);
+        assert_eq!(value("7 mod -3"), -2.0);
+        assert_eq!(value("7 % (-3)"), -2.0);
+        assert!((value("0.5!") - 0.886_226_925_452_758).abs() < 1e-14);
+        // 3!=6 is still a comparison, the factorial needs a space e.g. 3! == 6
+        assert_eq!(parse("3!=6").to_string(), "3 != 6");
+        assert!(eval(parse("(-1)!"), &mut Environment::new()).is_err());
+        // a negative divisor is written with 'mod' or in parentheses, and integers have no
+        // percentages
+        let mut env = Environment::new();
+        env.set_mode(NumberMode::Integer(IntWidth::I64));
+        assert_eq!(eval(parse("7 % (-3)"), &mut env), Ok(Value::Integer(-2)));
+        assert_eq!(eval(parse("7 mod -3"), &mut env), Ok(Value::Integer(-2)));
+        assert_eq!(
+            eval(parse("50%"), &mut env),
+            Err(EvalError::TypeError(
+                "percentages are not available for integers".into()
+            ))
+        );
+        assert_eq!(
+            eval(parse("7 % -3"), &mut env),
+            Err(EvalError::TypeError(
+                "percentages are not available for integers".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_error_recovery() {
+        let recover = |expr: &str| {
+            let recovered = Parser::parse_recovering(expr, Syntax::default());
+            let spans: Vec<(usize, usize)> = recovered
+                .errors
+                .iter()
+                .map(|e| (e.span().start, e.span().end))
+                .collect();
+            (recovered.ast.to_string(), spans)
+        };
+        assert_eq!(
+            recover("2 * * 3 + (4 -"),
+            (
+                "2 * <error> * 3 + (4 - <error>)".into(),
+                vec![(4, 5), (14, 14)]
+            )
+        );
+        assert_eq!(
+            recover("1 $ 2 + max(1, ) ) * 3"),
+            (
+                "(1 + max(1, <error>)) * 3".into(),
+                vec![(2, 3), (15, 16), (17, 18)]
+            )
+        );
+        assert_eq!(
+            recover("let 2 = 1.2.3 + x ? 1"),
+            (
+                "<error> + x ? 1 : <error>".into(),
+                vec![(4, 5), (8, 13), (21, 21)]
+            )
+        );
+        // a mistake is reported once, without errors that only follow from it
+        let errors = |expr: &str| {
+            Parser::parse_recovering(expr, Syntax::default())
+                .errors
+                .iter()
+                .map(|e| e.to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            errors("let = 3"),
+            ["Unable to parse: expected a variable name after 'let', found '='"]
+        );
+        assert_eq!(
+            errors("x ? \"a\" : 1"),
+            [
+                "Invalid token: '\"' is not a valid character",
+                "Invalid token: '\"' is not a valid character"
+            ]
+        );
+        assert_eq!(recover("x ? \"a\" : 1").0, "x ? <error> * a : 1");
+        assert_eq!(
+            errors("let 2 3"),
+            [
+                "Unable to parse: expected a variable name after 'let', found '2'",
+                "Invalid operator: expected '=', found '3'"
+            ]
+        );
+        assert_eq!(
+            errors("(1 + 2 #) * 3"),
+            ["Invalid token: '#' is not a valid character"]
+        );
+        assert_eq!(recover("max(1 #, 2)").0, "max(1, 2)");
+        assert_eq!(
+            errors("1_.5 + 0x"),
+            [
+                "Invalid token: '1_.5' has a '_' that is not between two digits",
+                "Invalid token: '0x' is not a valid number"
+            ]
+        );
+        assert_eq!(
+            errors("max(1 #, 2)"),
+            ["Invalid token: '#' is not a valid character"]
+        );
+        // a valid expression gives the same tree as parse, and no errors
+        let recovered = Parser::parse_recovering("2x + 1", Syntax::default());
+        assert_eq!(Ok(recovered.ast), Parser::new("2x + 1").unwrap().parse());
+        assert!(recovered.errors.is_empty());
+        // an error node cannot be evaluated
+        let ast = Parser::parse_recovering("1 +", Syntax::default()).ast;
+        assert_eq!(
+            crate::parsemaths::ast::eval(ast, &mut Default::default()),
+            Err(crate::parsemaths::ast::EvalError::SyntaxError)
+        );
+    }
+
+    #[test]
+    fn test_error_location() {
+        let err = Parser::new("2 * * 3").unwrap().parse().unwrap_err();
+        assert_eq!(err.span(), Span::new(4, 5));
+        assert_eq!(err.marker("2 * * 3"), "    ^");
+        let err = Parser::new("(1 + 2").unwrap().parse().unwrap_err();
+        assert_eq!(
+            err,
+            ParseError::InvalidOperator {
+                span: Span::new(6, 6),
+                expected: "')'".into(),
+                found: "end of input".into()
+            }
+        );
+        let err = Parser::new("1 + abc $").unwrap().parse().unwrap_err();
+        assert_eq!(err.marker("1 + abc $"), "        ^");
+        let err = Parser::new("let 12.5 = 3").unwrap().parse().unwrap_err();
+        assert_eq!(err.marker("let 12.5 = 3"), "    ^~~~");
+        let err = Parser::new("2 * 1.2.3").unwrap().parse().unwrap_err();
+        assert_eq!(err.marker("2 * 1.2.3"), "    ^~~~~");
+        assert_eq!(
+            err.to_string(),
+            "Invalid token: '1.2.3' has more than one decimal point"
+        );
+        // a conversion without a unit points at where the unit should be
+        let err = Parser::new("2 m to").unwrap().parse().unwrap_err();
+        assert_eq!(err.marker("2 m to"), "      ^");
+        assert_eq!(
+            err.to_string(),
+            "Unable to parse: expected a unit after 'to', found end of input"
+        );
+        let err = Parser::new("2 m to x + 1").unwrap().parse().unwrap_err();
+        assert_eq!(err.marker("2 m to x + 1"), "       ^");
+        assert_eq!(
+            err.to_string(),
+            "Unable to parse: expected a unit after 'to', found 'x'"
+        );
+        assert_eq!(
+            Parser::parse_recovering("2 m to + 1", Syntax::default())
+                .errors
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_span_tree() {
+        // the text of each node's span is what the node was read from
+        fn texts<'a>(spans: &SpanTree, expr: &'a str, out: &mut Vec<&'a str>) {
+            out.push(&expr[spans.span.start..spans.span.end]);
+            for child in &spans.children {
+                texts(child, expr, out);
+            }
+        }
+        let read = |expr: &'static str| {
+            let mut parser = Parser::new(expr).unwrap();
+            let ast = parser.parse().unwrap();
+            let mut out = Vec::new();
+            texts(&parser.span_tree(&ast).unwrap(), expr, &mut out);
+            out
+        };
+        assert_eq!(read("1 + 2 * 3"), ["1 + 2 * 3", "1", "2 * 3", "2", "3"]);
+        assert_eq!(
+            read(" (1 + 2) * 3 "),
+            ["(1 + 2) * 3", "(1 + 2)", "1", "2", "3"]
+        );
+        assert_eq!(read("-2^2"), ["-2^2", "2^2", "2", "2"]);
+        assert_eq!(read("2x * 15%"), ["2x * 15%", "2x", "2", "x", "15%", "15"]);
+        assert_eq!(
+            read("let a = max(1, 3!)"),
+            ["let a = max(1, 3!)", "max(1, 3!)", "1", "3!", "3"]
+        );
+        assert_eq!(
+            read("x > 0 ? √x : 5 km to m"),
+            [
+                "x > 0 ? √x : 5 km to m",
+                "x > 0 ? √x : 5 km",
+                "x > 0",
+                "x",
+                "0",
+                "√x",
+                "x",
+                "5 km"
+            ]
+        );
+        // a tree the parser did not return has no spans
+        let mut parser = Parser::new("1 + 2").unwrap();
+        parser.parse().unwrap();
+        assert_eq!(parser.span_tree(&Node::Number(1.0)), None);
+    }
 }
@@ -0,0 +1,242 @@
+// in rational.rs - providing code for exact fractions used by the exact number mode
+
+use crate::parsemaths::bigint::BigInt;
+use std::cmp::Ordering;
+use std::fmt;
+
+/*
+Exact fraction numerator/denominator with arbitrary precision parts
+Always kept in lowest terms with a positive denominator, so two equal values have equal fields
+*/
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    num: BigInt,
+    den: BigInt,
+}
+
+impl Rational {
+    /* Builds num/den in lowest terms - None if den is zero */
+    pub fn new(num: BigInt, den: BigInt) -> Option<Self> {
+        if den.is_zero() {
+            return None;
+        }
+        let divisor = num.gcd(&den);
+        let (mut num, mut den) = (num.div_rem(&divisor).0, den.div_rem(&divisor).0);
+        if den.is_negative() {
+            num = num.neg();
+            den = den.neg();
+        }
+        Some(Rational { num, den })
+    }
+
+    pub fn from_integer(num: BigInt) -> Self {
+        Rational {
+            num,
+            den: BigInt::from_u64(1),
+        }
+    }
+
+    /*
+    Parses decimal text such as "12", "-0.1" or "6.02e23" into the exact value it denotes
+    Returns None if the text is not a decimal number
+    */
+    pub fn parse_decimal(text: &str) -> Option<Self> {
+        let (mantissa, exponent) = match text.find(['e', 'E']) {
+            Some(i) => (&text[..i], text[i + 1..].parse::<i32>().ok()?),
+            None => (text, 0),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let num = BigInt::parse(&format!("{}{}", whole, fraction))?;
+        // every digit after the point divides by another 10, the exponent scales the other way
+        let scale = exponent - fraction.len() as i32;
+        let ten = BigInt::from_u64(10);
+        if scale >= 0 {
+            Some(Rational::from_integer(num.mul(&ten.pow(scale as u32))))
+        } else {
+            Rational::new(num, ten.pow(scale.unsigned_abs()))
+        }
+    }
+
+    /*
+    Exact value of the decimal literal that an f64 was read from
+    Rust prints an f64 as the shortest decimal text that reads back to the same f64, so 0.1 gives
+    exactly 1/10 rather than the binary approximation stored in the f64
+    This holds for literals of up to 15 significant digits, longer ones the tokenizer keeps as text
+    Returns None for infinity and NaN
+    */
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        Rational::parse_decimal(&value.to_string())
+    }
+
+    pub fn numer(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.den == BigInt::from_u64(1)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.num.is_zero()
+    }
+
+    pub fn add(&self, other: &Rational) -> Rational {
+        let num = self.num.mul(&other.den).add(&other.num.mul(&self.den));
+        Rational::new(num, self.den.mul(&other.den)).expect("denominators are non-zero")
+    }
+
+    pub fn sub(&self, other: &Rational) -> Rational {
+        let num = self.num.mul(&other.den).sub(&other.num.mul(&self.den));
+        Rational::new(num, self.den.mul(&other.den)).expect("denominators are non-zero")
+    }
+
+    pub fn mul(&self, other: &Rational) -> Rational {
+        let (num, den) = (self.num.mul(&other.num), self.den.mul(&other.den));
+        Rational::new(num, den).expect("denominators are non-zero")
+    }
+
+    /* None when dividing by zero */
+    pub fn div(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(self.num.mul(&other.den), self.den.mul(&other.num))
+    }
+
+    pub fn neg(&self) -> Rational {
+        Rational {
+            num: self.num.neg(),
+            den: self.den.clone(),
+        }
+    }
+
+    /* Largest integer not greater than the value e.g. floor(-7/2) = -4 */
+    pub fn floor(&self) -> BigInt {
+        let (quotient, remainder) = self.num.div_rem(&self.den);
+        // division truncates towards zero, which rounds a negative value up instead of down
+        if remainder.is_negative() {
+            quotient.sub(&BigInt::from_u64(1))
+        } else {
+            quotient
+        }
+    }
+
+    /* Exact integer power - None for 0 raised to a negative power */
+    pub fn pow(&self, exponent: i64) -> Option<Rational> {
+        let magnitude = u32::try_from(exponent.unsigned_abs()).ok()?;
+        let (num, den) = (self.num.pow(magnitude), self.den.pow(magnitude));
+        if exponent < 0 {
+            Rational::new(den, num)
+        } else {
+            Rational::new(num, den)
+        }
+    }
+
+    /*
+    Nearest f64
+    The numerator is scaled so the integer quotient has about 64 significant bits, which keeps
+    the result accurate even when the numerator and denominator are both too large for an f64
+    */
+    pub fn to_f64(&self) -> f64 {
+        let shift = 64 + self.den.bits() as i64 - self.num.bits() as i64;
+        let quotient = if shift >= 0 {
+            self.num.shl(shift as usize).div_rem(&self.den).0
+        } else {
+            self.num
+                .div_rem(&self.den.shl(shift.unsigned_abs() as usize))
+                .0
+        };
+        // scaled in two steps so an intermediate power of 2 does not overflow or underflow
+        let half = (shift / 2).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let rest = (shift - half as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        quotient.to_f64() * 2f64.powi(-half) * 2f64.powi(-rest)
+    }
+
+    /*
+    Decimal text with exactly `digits` digits after the point, rounded half away from zero
+    e.g. 1/3 with 4 digits => 0.3333, 2/3 => 0.6667
+    */
+    pub fn to_decimal(&self, digits: usize) -> String {
+        let scale = BigInt::from_u64(10).pow(digits as u32);
+        let (quotient, remainder) = self.num.abs().mul(&scale).div_rem(&self.den);
+        // round up when the remainder is at least half the denominator
+        let scaled = if remainder.shl(1) >= self.den {
+            quotient.add(&BigInt::from_u64(1))
+        } else {
+            quotient
+        };
+        let mut text = format!("{:0>width$}", scaled.to_string(), width = digits + 1);
+        if digits > 0 {
+            text.insert(text.len() - digits, '.');
+        }
+        if self.num.is_negative() && scaled != BigInt::zero() {
+            text.insert(0, '-');
+        }
+        text
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Self) -> Ordering {
+        // denominators are positive so cross multiplying keeps the order
+        self.num.mul(&other.den).cmp(&other.num.mul(&self.den))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/* Prints integers as they are and everything else as a fraction e.g. 3/10 */
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_integer() {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Rational {
+        Rational::parse_decimal(text).unwrap()
+    }
+
+    #[test]
+    fn test_exact_decimals() {
+        let sum = decimal("0.1").add(&decimal("0.2"));
+        assert_eq!(sum, decimal("0.3"));
+        assert_eq!(sum.to_string(), "3/10");
+        assert_eq!(Rational::from_f64(0.1), Some(decimal("0.1")));
+        assert_eq!(decimal("-2.50e2").to_string(), "-250");
+        assert_eq!(Rational::from_f64(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_operations() {
+        let third = decimal("1").div(&decimal("3")).unwrap();
+        assert_eq!(third.mul(&decimal("3")), decimal("1"));
+        assert_eq!(third.pow(-2).unwrap().to_string(), "9");
+        assert_eq!(decimal("0").pow(-1), None);
+        assert_eq!(decimal("1").div(&decimal("0")), None);
+        assert!(third < decimal("0.34"));
+        assert_eq!(decimal("-3.5").floor(), BigInt::from_i128(-4));
+    }
+
+    #[test]
+    fn test_conversions() {
+        let two_thirds = decimal("-2").div(&decimal("3")).unwrap();
+        assert_eq!(two_thirds.to_decimal(4), "-0.6667");
+        assert_eq!(decimal("0.005").to_decimal(2), "0.01");
+        assert_eq!(decimal("12.5").to_decimal(0), "13");
+        assert_eq!(two_thirds.to_f64(), -2.0 / 3.0);
+        assert_eq!(decimal("1e-300").to_f64(), 1e-300);
+    }
+}
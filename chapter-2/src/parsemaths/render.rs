@@ -0,0 +1,204 @@
+// in render.rs - providing code for drawing the AST as a text tree or a Graphviz graph
+
+use crate::parsemaths::ast::Node;
+use crate::parsemaths::parser::SpanTree;
+
+/*
+Short name of a node for a drawing: its operator e.g. + or let x =, or for a leaf the leaf itself
+e.g. 2.5 or 5 km
+A unary minus is 'negate' and a percentage 'percent' so they are not mistaken for a subtraction
+or a modulo with a missing side
+*/
+fn label(node: &Node) -> String {
+    use self::Node::*;
+    match node {
+        Negative(_) => "negate".into(),
+        BitNot(_) => "~".into(),
+        Not(_) => "!".into(),
+        Percent(_) => "percent".into(),
+        Conditional(..) => "? :".into(),
+        Assign(name, _) => format!("let {} =", name),
+        Call(name, _) => format!("{}()", name),
+        Convert(_, unit) => format!("to {}", unit),
+        _ => match node.binary() {
+            Some((symbol, _, _)) => symbol.into(),
+            None => node.to_string(),
+        },
+    }
+}
+
+/*
+Where a node came from, as its byte offsets and the text there e.g. 4..9 2 * 3
+The text is left out when it is just the label, as it is for most leaves
+*/
+fn origin(label: &str, spans: Option<&SpanTree>, source: &str) -> Option<String> {
+    let span = spans?.span;
+    let text = source.get(span.start..span.end).unwrap_or_default();
+    if text.is_empty() || text == label {
+        Some(format!("{}..{}", span.start, span.end))
+    } else {
+        Some(format!("{}..{}  {}", span.start, span.end, text))
+    }
+}
+
+/*
+Draws the node as a tree with box-drawing characters, one node per line below its parent
+With the spans from the parser each node shows where in source it was read from:
++  0..9  1 + 2 * 3
+├── 1  0..1
+└── *  4..9  2 * 3
+    ├── 2  4..5
+    └── 3  8..9
+*/
+pub fn tree(node: &Node, spans: Option<&SpanTree>, source: &str) -> String {
+    let mut lines = Vec::new();
+    draw_tree(node, spans, source, "", "", &mut lines);
+    lines.join("\n")
+}
+
+/* Adds the lines of the node, its own line starting with first and those below it with rest */
+fn draw_tree(
+    node: &Node,
+    spans: Option<&SpanTree>,
+    source: &str,
+    first: &str,
+    rest: &str,
+    lines: &mut Vec<String>,
+) {
+    let label = label(node);
+    match origin(&label, spans, source) {
+        Some(origin) => lines.push(format!("{}{}  {}", first, label, origin)),
+        None => lines.push(format!("{}{}", first, label)),
+    }
+    let children = node.children();
+    for (i, child) in children.iter().enumerate() {
+        let child_spans = spans.and_then(|spans| spans.children.get(i));
+        let (branch, below) = if i + 1 == children.len() {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        draw_tree(
+            child,
+            child_spans,
+            source,
+            &format!("{}{}", rest, branch),
+            &format!("{}{}", rest, below),
+            lines,
+        );
+    }
+}
+
+/* Escapes quotes and backslashes in text for a DOT string */
+fn dot_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/*
+Draws the node as a Graphviz graph in the DOT language, for rendering with e.g. dot -Tsvg
+Each node is a box labelled as in tree, with where it was read from on the line below, and the
+nodes below it are kept in the order they are written so a - b is not drawn as b - a
+*/
+pub fn dot(node: &Node, spans: Option<&SpanTree>, source: &str) -> String {
+    let mut lines = vec![
+        "digraph ast {".to_string(),
+        "    ordering=out;".to_string(),
+        "    node [shape=box, fontname=\"monospace\"];".to_string(),
+    ];
+    draw_dot(node, spans, source, &mut 0, &mut lines);
+    lines.push("}".to_string());
+    lines.join("\n")
+}
+
+/* Adds the node and the edges to the nodes below it, numbering them from next, and returns its id */
+fn draw_dot(
+    node: &Node,
+    spans: Option<&SpanTree>,
+    source: &str,
+    next: &mut usize,
+    lines: &mut Vec<String>,
+) -> usize {
+    let id = *next;
+    *next += 1;
+    let label = label(node);
+    // the origin goes on a second line, which is written \n in DOT
+    let text = match origin(&label, spans, source) {
+        Some(origin) => format!("{}\\n{}", dot_escape(&label), dot_escape(&origin)),
+        None => dot_escape(&label),
+    };
+    lines.push(format!("    n{} [label=\"{}\"];", id, text));
+    for (i, child) in node.children().into_iter().enumerate() {
+        let child_spans = spans.and_then(|spans| spans.children.get(i));
+        let child_id = draw_dot(child, child_spans, source, next, lines);
+        lines.push(format!("    n{} -> n{};", id, child_id));
+    }
+    id
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::parser::Parser;
+
+    fn draw(expr: &str, render: fn(&Node, Option<&SpanTree>, &str) -> String) -> String {
+        let mut parser = Parser::new(expr).unwrap();
+        let ast = parser.parse().unwrap();
+        render(&ast, parser.span_tree(&ast).as_ref(), expr)
+    }
+
+    #[test]
+    fn test_tree() {
+        assert_eq!(
+            draw("1 + 2 * 3", tree),
+            "\
++  0..9  1 + 2 * 3
+├── 1  0..1
+└── *  4..9  2 * 3
+    ├── 2  4..5
+    └── 3  8..9"
+        );
+        assert_eq!(
+            draw("let y = -(x - 1)^2 + max(x, 5 km to m)", tree),
+            "\
+let y =  0..38  let y = -(x - 1)^2 + max(x, 5 km to m)
+└── +  8..38  -(x - 1)^2 + max(x, 5 km to m)
+    ├── negate  8..18  -(x - 1)^2
+    │   └── ^  9..18  (x - 1)^2
+    │       ├── -  9..16  (x - 1)
+    │       │   ├── x  10..11
+    │       │   └── 1  14..15
+    │       └── 2  17..18
+    └── max()  21..38  max(x, 5 km to m)
+        ├── x  25..26
+        └── to m  28..37  5 km to m
+            └── 5 km  28..32"
+        );
+        // without spans only the labels are drawn
+        let ast = Parser::new("x ? 1 : 20%").unwrap().parse().unwrap();
+        assert_eq!(
+            tree(&ast, None, ""),
+            "? :\n├── x\n├── 1\n└── percent\n    └── 20"
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(
+            draw("a - f(2)", dot),
+            r#"digraph ast {
+    ordering=out;
+    node [shape=box, fontname="monospace"];
+    n0 [label="-\n0..8  a - f(2)"];
+    n1 [label="a\n0..1"];
+    n0 -> n1;
+    n2 [label="f()\n4..8  f(2)"];
+    n3 [label="2\n6..7"];
+    n2 -> n3;
+    n0 -> n2;
+}"#
+        );
+        let ast = Node::Variable("say \"hi\\".into());
+        assert!(dot(&ast, None, "").contains(r#"n0 [label="say \"hi\\"];"#));
+    }
+}
@@ -0,0 +1,659 @@
+// in serialise.rs - providing code for writing the AST as S-expressions or JSON and reading it back
+
+use crate::parsemaths::ast::Node;
+use crate::parsemaths::json::{Json, JsonError};
+use crate::parsemaths::rational::Rational;
+use crate::parsemaths::token::Span;
+use crate::parsemaths::units::{self, Unit};
+use crate::parsemaths::value::real_text;
+use std::{error, fmt};
+
+/*
+Both forms are lossless, so reading back what was written gives the same tree, and stable, so the
+same tree is always written the same way e.g. for golden-file tests
+Each operator is named by its symbol, with '-' for both subtraction and negation and '%' for both
+modulo and a percentage:
+    + - * / // % ^ & | xor << >> == != < <= > >= && || ~ ! ?
+and the nodes that are not plain operators by a word:
+    let (assignment), call, to (unit conversion), quantity, decimal (a number with more digits than
+    an f64 keeps) and error (a syntax error)
+*/
+
+/* Operator named by head with the given operands - None if there is no such operator of that many */
+fn operator(head: &str, args: Vec<Node>) -> Option<Node> {
+    use self::Node::*;
+    let mut args = args.into_iter().map(Box::new);
+    let node = match (head, args.len()) {
+        ("-", 1) => Negative(args.next()?),
+        ("~", 1) => BitNot(args.next()?),
+        ("!", 1) => Not(args.next()?),
+        ("%", 1) => Percent(args.next()?),
+        ("?", 3) => Conditional(args.next()?, args.next()?, args.next()?),
+        (_, 2) => {
+            let constructor = match head {
+                "+" => Add,
+                "-" => Subtract,
+                "*" => Multiply,
+                "/" => Divide,
+                "//" => FloorDivide,
+                "%" => Modulo,
+                "^" => Caret,
+                "&" => BitAnd,
+                "|" => BitOr,
+                "xor" => BitXor,
+                "<<" => ShiftLeft,
+                ">>" => ShiftRight,
+                "==" => Equal,
+                "!=" => NotEqual,
+                "<" => Less,
+                "<=" => LessEqual,
+                ">" => Greater,
+                ">=" => GreaterEqual,
+                "&&" => And,
+                "||" => Or,
+                _ => return None,
+            };
+            constructor(args.next()?, args.next()?)
+        }
+        _ => return None,
+    };
+    Some(node)
+}
+
+/* Symbol and operands of an operator whose parts are all nodes - None for every other node */
+fn operator_parts(node: &Node) -> Option<(&'static str, Vec<&Node>)> {
+    use self::Node::*;
+    match node {
+        Negative(a) => Some(("-", vec![a])),
+        BitNot(a) => Some(("~", vec![a])),
+        Not(a) => Some(("!", vec![a])),
+        Percent(a) => Some(("%", vec![a])),
+        Conditional(cond, a, b) => Some(("?", vec![cond, a, b])),
+        _ => node.binary().map(|(symbol, a, b)| (symbol, vec![a, b])),
+    }
+}
+
+/* Reads back a unit written by its name, which must be all of the text */
+fn read_unit(text: &str) -> Option<Unit> {
+    match units::read_unit(text) {
+        Some((unit, len)) if len == text.len() => Some(unit),
+        _ => None,
+    }
+}
+
+/* Whether a name can be written as a bare atom, which is when the parser would read it as a name */
+fn is_plain_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && name != "true"
+        && name != "false"
+}
+
+/*
+Writes the node as an S-expression e.g. 1 + 2 * x => (+ 1 (* 2 x))
+Numbers are written exactly, with integers in hex e.g. 0xff and imaginary numbers with an i e.g. 2.5i
+Names are bare atoms, or strings if they are not names the parser could read, and units are strings
+e.g. (to (quantity 5 "km") "mi")
+Numbers that are not finite are written (number inf) or (imaginary NaN), and decimals with all
+their digits e.g. (decimal 0.1234567890123456789)
+*/
+pub fn to_sexpr(node: &Node) -> String {
+    let mut out = String::new();
+    write_sexpr(&mut out, node);
+    out
+}
+
+fn write_sexpr(out: &mut String, node: &Node) {
+    use self::Node::*;
+    let name = |name: &str| {
+        if is_plain_name(name) {
+            name.to_string()
+        } else {
+            Json::from(name).to_string()
+        }
+    };
+    let unit = |unit: &Unit| Json::from(unit.to_string()).to_string();
+    let (head, args) = match node {
+        Number(n) if n.is_finite() => return out.push_str(&real_text(*n)),
+        Number(n) => return out.push_str(&format!("(number {})", n)),
+        Integer(n) => return out.push_str(&format!("{:#x}", n)),
+        Decimal(text) => return out.push_str(&format!("(decimal {})", text)),
+        Imaginary(n) if n.is_finite() => return out.push_str(&format!("{}i", real_text(*n))),
+        Imaginary(n) => return out.push_str(&format!("(imaginary {})", n)),
+        Bool(b) => return out.push_str(&b.to_string()),
+        Variable(var) => return out.push_str(&name(var)),
+        Quantity(n, u) => {
+            return out.push_str(&format!("(quantity {} {})", real_text(*n), unit(u)));
+        }
+        Error(span) => return out.push_str(&format!("(error {} {})", span.start, span.end)),
+        Assign(var, value) => (format!("let {}", name(var)), vec![&**value]),
+        Call(function, args) => (format!("call {}", name(function)), args.iter().collect()),
+        Convert(value, u) => {
+            out.push_str("(to ");
+            write_sexpr(out, value);
+            return out.push_str(&format!(" {})", unit(u)));
+        }
+        _ => match operator_parts(node) {
+            Some((symbol, args)) => (symbol.to_string(), args),
+            None => unreachable!("every other node is an operator"),
+        },
+    };
+    out.push('(');
+    out.push_str(&head);
+    for arg in args {
+        out.push(' ');
+        write_sexpr(out, arg);
+    }
+    out.push(')');
+}
+
+/* Error from reading an S-expression, with the byte offset in the text of what could not be read */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SexprError {
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SexprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid S-expression at byte {}: {}",
+            self.offset, self.message
+        )
+    }
+}
+
+impl error::Error for SexprError {}
+
+/*
+Reads a tree written by to_sexpr, which must be all of the text
+Any amount of whitespace may separate the parts, so trees can be written over several lines
+*/
+pub fn from_sexpr(text: &str) -> Result<Node, SexprError> {
+    let mut reader = SexprReader { text, pos: 0 };
+    let node = reader.node()?;
+    reader.skip_space();
+    match reader.peek() {
+        None => Ok(node),
+        Some(_) => Err(reader.error(reader.pos, "expected end of input")),
+    }
+}
+
+/* Recursive descent reader over the text, pos being the byte offset of the next character */
+struct SexprReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> SexprReader<'a> {
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn error(&self, offset: usize, message: &str) -> SexprError {
+        SexprError {
+            offset,
+            message: message.to_string(),
+        }
+    }
+
+    /* The next atom, with its offset - an atom runs up to whitespace, a parenthesis or a quote */
+    fn atom(&mut self) -> Result<(usize, &'a str), SexprError> {
+        self.skip_space();
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || matches!(c, '(' | ')' | '"') {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+        if self.pos == start {
+            return Err(self.error(start, "expected an atom"));
+        }
+        Ok((start, &self.text[start..self.pos]))
+    }
+
+    /* The next string, written as in JSON, with its offset */
+    fn string(&mut self) -> Result<(usize, String), SexprError> {
+        self.skip_space();
+        let start = self.pos;
+        if self.peek() != Some('"') {
+            return Err(self.error(start, "expected a string"));
+        }
+        // finds the closing quote, skipping over escaped characters, and lets JSON undo the escapes
+        let mut escaped = false;
+        let end = self.text[start + 1..].char_indices().find_map(|(i, c)| {
+            let end = !escaped && c == '"';
+            escaped = !escaped && c == '\\';
+            end.then_some(start + 1 + i + 1)
+        });
+        let end = end.ok_or_else(|| self.error(start, "unterminated string"))?;
+        match Json::parse(&self.text[start..end]) {
+            Ok(Json::String(s)) => {
+                self.pos = end;
+                Ok((start, s))
+            }
+            _ => Err(self.error(start, "invalid string")),
+        }
+    }
+
+    /* A name, which is a bare atom or a string */
+    fn name(&mut self) -> Result<String, SexprError> {
+        self.skip_space();
+        match self.peek() {
+            Some('"') => Ok(self.string()?.1),
+            _ => Ok(self.atom()?.1.to_string()),
+        }
+    }
+
+    fn unit(&mut self) -> Result<Unit, SexprError> {
+        let (offset, text) = self.string()?;
+        read_unit(&text).ok_or_else(|| self.error(offset, "unknown unit"))
+    }
+
+    fn number(&mut self) -> Result<f64, SexprError> {
+        let (offset, text) = self.atom()?;
+        text.parse()
+            .map_err(|_| self.error(offset, "expected a number"))
+    }
+
+    fn decimal(&mut self) -> Result<String, SexprError> {
+        let (offset, text) = self.atom()?;
+        match Rational::parse_decimal(text) {
+            Some(_) => Ok(text.to_string()),
+            None => Err(self.error(offset, "expected a decimal number")),
+        }
+    }
+
+    fn close(&mut self) -> Result<(), SexprError> {
+        self.skip_space();
+        match self.peek() {
+            Some(')') => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(self.error(self.pos, "expected ')'")),
+        }
+    }
+
+    fn node(&mut self) -> Result<Node, SexprError> {
+        self.skip_space();
+        match self.peek() {
+            Some('(') => self.list(),
+            Some('"') => Ok(Node::Variable(self.string()?.1)),
+            Some(')') | None => Err(self.error(self.pos, "expected a node")),
+            Some(_) => {
+                let (offset, atom) = self.atom()?;
+                leaf(atom).ok_or_else(|| self.error(offset, "expected a number or a name"))
+            }
+        }
+    }
+
+    /* A node written as a list, from its opening parenthesis */
+    fn list(&mut self) -> Result<Node, SexprError> {
+        let start = self.pos;
+        self.pos += 1;
+        let (_, head) = self.atom()?;
+        let node = match head {
+            "number" => Node::Number(self.number()?),
+            "decimal" => Node::Decimal(self.decimal()?),
+            "imaginary" => Node::Imaginary(self.number()?),
+            "quantity" => Node::Quantity(self.number()?, self.unit()?),
+            "error" => {
+                let (start, end) = (self.number()?, self.number()?);
+                Node::Error(Span::new(start as usize, end as usize))
+            }
+            "let" => Node::Assign(self.name()?, Box::new(self.node()?)),
+            "to" => Node::Convert(Box::new(self.node()?), self.unit()?),
+            _ => {
+                let call = head == "call";
+                let function = if call { Some(self.name()?) } else { None };
+                let mut args = Vec::new();
+                self.skip_space();
+                while !matches!(self.peek(), Some(')') | None) {
+                    args.push(self.node()?);
+                    self.skip_space();
+                }
+                self.close()?;
+                return match function {
+                    Some(function) => Ok(Node::Call(function, args)),
+                    None => operator(head, args).ok_or_else(|| {
+                        self.error(start, "unknown operator or wrong number of operands")
+                    }),
+                };
+            }
+        };
+        self.close()?;
+        Ok(node)
+    }
+}
+
+/* The leaf node an atom stands for - None if it is not a number or a name */
+fn leaf(atom: &str) -> Option<Node> {
+    let mut chars = atom.chars();
+    let numeric = match chars.next()? {
+        '0'..='9' | '.' => true,
+        '-' | '+' => matches!(chars.next(), Some('0'..='9' | '.')),
+        _ => false,
+    };
+    if let Some(hex) = atom.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok().map(Node::Integer)
+    } else if let Some(n) = atom.strip_suffix('i').filter(|_| numeric) {
+        n.parse().ok().map(Node::Imaginary)
+    } else if numeric {
+        atom.parse().ok().map(Node::Number)
+    } else {
+        match atom {
+            "true" => Some(Node::Bool(true)),
+            "false" => Some(Node::Bool(false)),
+            _ if is_plain_name(atom) => Some(Node::Variable(atom.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/*
+Converts the node to JSON, with every node an object named by what it is
+Leaves: {"number":2}, {"integer":"255"}, {"decimal":"0.1234567890123456789"}, {"imaginary":2.5},
+{"bool":true}, {"variable":"x"}, {"quantity":5,"unit":"km"} and {"error":[3,5]}
+Everything else: {"op":"+","args":[...]}, with a "name" for let and call and a "unit" for to
+Integers and decimals are strings as JSON numbers cannot hold all their digits, and numbers that are not finite are
+the strings "inf", "-inf" or "NaN"
+*/
+pub fn to_json(node: &Node) -> Json {
+    use self::Node::*;
+    let number = |n: f64| {
+        if n.is_finite() {
+            Json::Number(n)
+        } else {
+            Json::String(n.to_string())
+        }
+    };
+    let op = |head: &str, args: Vec<&Node>| -> Vec<(String, Json)> {
+        vec![
+            ("op".to_string(), head.into()),
+            (
+                "args".to_string(),
+                args.into_iter().map(to_json).collect::<Vec<_>>().into(),
+            ),
+        ]
+    };
+    match node {
+        Number(n) => Json::object([("number", number(*n))]),
+        Integer(n) => Json::object([("integer", n.to_string().into())]),
+        Decimal(text) => Json::object([("decimal", text.as_str().into())]),
+        Imaginary(n) => Json::object([("imaginary", number(*n))]),
+        Bool(b) => Json::object([("bool", (*b).into())]),
+        Variable(name) => Json::object([("variable", name.as_str().into())]),
+        Quantity(n, unit) => {
+            Json::object([("quantity", number(*n)), ("unit", unit.to_string().into())])
+        }
+        Error(span) => Json::object([("error", vec![span.start.into(), span.end.into()].into())]),
+        Assign(name, value) => {
+            let mut fields = op("let", vec![value]);
+            fields.insert(1, ("name".to_string(), name.as_str().into()));
+            Json::Object(fields)
+        }
+        Call(name, args) => {
+            let mut fields = op("call", args.iter().collect());
+            fields.insert(1, ("name".to_string(), name.as_str().into()));
+            Json::Object(fields)
+        }
+        Convert(value, unit) => {
+            let mut fields = op("to", vec![value]);
+            fields.insert(1, ("unit".to_string(), unit.to_string().into()));
+            Json::Object(fields)
+        }
+        _ => match operator_parts(node) {
+            Some((symbol, args)) => Json::Object(op(symbol, args)),
+            None => unreachable!("every other node is an operator"),
+        },
+    }
+}
+
+/*
+Reads a tree written by to_json
+Errors give the path to the part of the JSON that is not a node e.g. $.args[1].unit
+*/
+pub fn from_json(json: &Json) -> Result<Node, JsonError> {
+    json_node(json, "$")
+}
+
+fn json_node(json: &Json, path: &str) -> Result<Node, JsonError> {
+    let error = |path: String, message: &str| JsonError::Shape {
+        path,
+        message: message.to_string(),
+    };
+    let field = |key: &str| {
+        json.get(key)
+            .ok_or_else(|| error(path.to_string(), &format!("expected a \"{}\" key", key)))
+    };
+    let number = |key: &str| match field(key)? {
+        Json::Number(n) => Ok(*n),
+        Json::String(s) if s.parse::<f64>().is_ok_and(|n| !n.is_finite()) => Ok(s.parse().unwrap()),
+        _ => Err(error(format!("{}.{}", path, key), "expected a number")),
+    };
+    let string = |key: &str| match field(key)? {
+        Json::String(s) => Ok(s.clone()),
+        _ => Err(error(format!("{}.{}", path, key), "expected a string")),
+    };
+    let unit = |key: &str| {
+        read_unit(&string(key)?).ok_or_else(|| error(format!("{}.{}", path, key), "unknown unit"))
+    };
+    let Json::Object(fields) = json else {
+        return Err(error(path.to_string(), "expected an object"));
+    };
+    let kind = fields.first().map_or("", |(key, _)| key.as_str());
+    Ok(match kind {
+        "number" => Node::Number(number("number")?),
+        "integer" => Node::Integer(
+            string("integer")?
+                .parse()
+                .map_err(|_| error(format!("{}.integer", path), "expected a whole number"))?,
+        ),
+        "decimal" => {
+            let text = string("decimal")?;
+            if Rational::parse_decimal(&text).is_none() {
+                return Err(error(
+                    format!("{}.decimal", path),
+                    "expected a decimal number",
+                ));
+            }
+            Node::Decimal(text)
+        }
+        "imaginary" => Node::Imaginary(number("imaginary")?),
+        "bool" => match field("bool")? {
+            Json::Bool(b) => Node::Bool(*b),
+            _ => return Err(error(format!("{}.bool", path), "expected true or false")),
+        },
+        "variable" => Node::Variable(string("variable")?),
+        "quantity" => Node::Quantity(number("quantity")?, unit("unit")?),
+        "error" => match field("error")? {
+            Json::Array(items) => match items.as_slice() {
+                [Json::Number(start), Json::Number(end)] => {
+                    Node::Error(Span::new(*start as usize, *end as usize))
+                }
+                _ => return Err(error(format!("{}.error", path), "expected [start, end]")),
+            },
+            _ => return Err(error(format!("{}.error", path), "expected [start, end]")),
+        },
+        "op" => {
+            let head = string("op")?;
+            let args = match field("args")? {
+                Json::Array(items) => items
+                    .iter()
+                    .enumerate()
+                    .map(|(i, item)| json_node(item, &format!("{}.args[{}]", path, i)))
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => return Err(error(format!("{}.args", path), "expected an array")),
+            };
+            let one = |mut args: Vec<Node>| match args.len() {
+                1 => Ok(Box::new(args.remove(0))),
+                _ => Err(error(format!("{}.args", path), "expected one node")),
+            };
+            match head.as_str() {
+                "let" => Node::Assign(string("name")?, one(args)?),
+                "call" => Node::Call(string("name")?, args),
+                "to" => Node::Convert(one(args)?, unit("unit")?),
+                _ => operator(&head, args).ok_or_else(|| {
+                    error(
+                        path.to_string(),
+                        "unknown operator or wrong number of operands",
+                    )
+                })?,
+            }
+        }
+        _ => return Err(error(path.to_string(), "expected a node")),
+    })
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::parser::{Parser, Syntax};
+
+    /* Trees of every kind of node, from expressions and built by hand for those the parser never makes */
+    fn trees() -> Vec<Node> {
+        let mut trees: Vec<Node> = [
+            "1 + 2 * 3",
+            "-x ^ 2 + (-x) ^ 2",
+            "a - (b - c) // 0.5 % 7",
+            "~0xff & 1 | 2 xor 3 << 4 >> 5",
+            "!(x == 1) && y != 2 || z < 3 ? x <= 4 : y > 5 && z >= 6",
+            "let area = pi * r ^ 2",
+            "max(sin(x), 2.5i, 1e300, 6.02e-23, 9007199254740993, pi())",
+            "5 km to mi + 9.81 m/s^2",
+            "true == false",
+            "5! + 20%",
+            "0.1234567890123456789 * x",
+        ]
+        .iter()
+        .map(|expr| Parser::new(expr).unwrap().parse().unwrap())
+        .collect();
+        trees.extend([
+            Node::Number(f64::NAN),
+            Node::Number(f64::NEG_INFINITY),
+            Node::Number(-0.0),
+            Node::Imaginary(f64::INFINITY),
+            Node::Integer(u64::MAX),
+            Node::Variable("has space".to_string()),
+            Node::Variable("true".to_string()),
+            Node::Call("f(x)".to_string(), Vec::new()),
+            Parser::parse_recovering("1 + * 2", Syntax::default()).ast,
+        ]);
+        trees
+    }
+
+    /* NaN is not equal to itself, so trees are compared by their Debug text */
+    fn same(a: &Node, b: &Node) -> bool {
+        format!("{:?}", a) == format!("{:?}", b)
+    }
+
+    #[test]
+    fn test_sexpr() {
+        let sexpr = |expr: &str| to_sexpr(&Parser::new(expr).unwrap().parse().unwrap());
+        assert_eq!(sexpr("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(sexpr("-x ^ 2 - 0.5"), "(- (- (^ x 2)) 0.5)");
+        assert_eq!(sexpr("let y = max(x, 0xff)"), "(let y (call max x 0xff))");
+        assert_eq!(sexpr("2.5i + 1e300"), "(+ 2.5i 1e300)");
+        assert_eq!(
+            sexpr("x > 1 ? 5 km to mi : 0"),
+            "(? (> x 1) (to (quantity 5 \"km\") \"mi\") 0)"
+        );
+        assert_eq!(to_sexpr(&Node::Number(f64::NAN)), "(number NaN)");
+        assert_eq!(
+            sexpr("0.1234567890123456789"),
+            "(decimal 0.1234567890123456789)"
+        );
+        assert_eq!(to_sexpr(&Node::Variable("a b".to_string())), "\"a b\"");
+
+        for tree in trees() {
+            let text = to_sexpr(&tree);
+            let read = from_sexpr(&text).unwrap_or_else(|e| panic!("{} in {}", e, text));
+            assert!(same(&read, &tree), "{}", text);
+        }
+        // any whitespace separates the parts
+        assert_eq!(
+            from_sexpr(" (+\n  1\t(* x 2) ) ").unwrap(),
+            Parser::new("1 + x * 2").unwrap().parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sexpr_errors() {
+        let offset = |text: &str| from_sexpr(text).unwrap_err().offset;
+        assert_eq!(offset(""), 0);
+        assert_eq!(offset("(+ 1 2"), 6);
+        assert_eq!(offset("(+ 1 2) 3"), 8);
+        assert_eq!(offset("(+ 1)"), 0);
+        assert_eq!(offset("(frobnicate 1 2)"), 0);
+        assert_eq!(offset("(* 1 @)"), 5);
+        assert_eq!(offset("(to x \"parsecs\")"), 6);
+        assert_eq!(offset("(let x)"), 6);
+        assert_eq!(offset("(+ 1 \"x)"), 5);
+        assert_eq!(offset("(number pi)"), 8);
+        assert_eq!(offset("(decimal 1.2.3)"), 9);
+        assert_eq!(
+            from_sexpr(")").unwrap_err().to_string(),
+            "Invalid S-expression at byte 0: expected a node"
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let json = |expr: &str| to_json(&Parser::new(expr).unwrap().parse().unwrap()).to_string();
+        assert_eq!(
+            json("1 + 2 * x"),
+            r#"{"op":"+","args":[{"number":1},{"op":"*","args":[{"number":2},{"variable":"x"}]}]}"#
+        );
+        assert_eq!(
+            json("let y = f(5 km to mi)"),
+            r#"{"op":"let","name":"y","args":[{"op":"call","name":"f","args":[{"op":"to","unit":"mi","args":[{"quantity":5,"unit":"km"}]}]}]}"#
+        );
+        assert_eq!(
+            to_json(&Node::Integer(u64::MAX)).to_string(),
+            r#"{"integer":"18446744073709551615"}"#
+        );
+        assert_eq!(
+            json("10000000000000000.01"),
+            r#"{"decimal":"10000000000000000.01"}"#
+        );
+
+        for tree in trees() {
+            let text = to_json(&tree).to_string();
+            let read = from_json(&Json::parse(&text).unwrap())
+                .unwrap_or_else(|e| panic!("{} in {}", e, text));
+            assert!(same(&read, &tree), "{}", text);
+        }
+
+        let error = |text: &str| {
+            from_json(&Json::parse(text).unwrap())
+                .unwrap_err()
+                .to_string()
+        };
+        assert_eq!(error("[]"), "Unexpected JSON at $: expected an object");
+        assert_eq!(
+            error(
+                r#"{"op":"-","args":[{"number":1},{"op":"to","unit":"ly","args":[{"bool":true}]}]}"#
+            ),
+            "Unexpected JSON at $.args[1].unit: unknown unit"
+        );
+        assert_eq!(
+            error(r#"{"op":"?","args":[]}"#),
+            "Unexpected JSON at $: unknown operator or wrong number of operands"
+        );
+        assert_eq!(
+            error(r#"{"number":"1"}"#),
+            "Unexpected JSON at $.number: expected a number"
+        );
+    }
+}
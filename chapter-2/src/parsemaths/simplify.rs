@@ -0,0 +1,420 @@
+// in simplify.rs - providing code for simplifying an AST into a canonical form
+
+use crate::parsemaths::ast::Node;
+use crate::parsemaths::functions;
+use std::cmp::Ordering;
+
+// 2^53 - whole numbers up to this size are exact in every number mode, so they are safe to fold
+const MAX_FOLDED: i64 = 1 << 53;
+
+// functions that have a value for every real argument, so a call to one never fails
+const TOTAL_FUNCTIONS: &[&str] = &[
+    "sin", "cos", "atan", "atan2", "sinh", "cosh", "tanh", "cbrt", "exp", "abs", "floor", "ceil",
+    "round", "hypot", "min", "max",
+];
+
+/*
+Rewrites a tree into a simpler, canonical tree with the same value, working up from the leaves
+- arithmetic on whole numbers is worked out e.g. 2 * 3 => 6, while 6 / 4 is left alone
+  decimals are never folded, so no precision is lost in any number mode
+- identities are removed e.g. x + 0 => x, x * 1 => x, x ^ 1 => x, x ^ 0 => 1 and x / x => 1
+- a double negative cancels e.g. -(-x) => x
+- like terms are combined e.g. x + 2*x - y => 3*x - y, 3 m + 2 m => 5 m and x * y * x => x^2 * y
+- the operands of a sum, a product and the other commutative operators are put in a fixed order,
+  numbers first in a product and last in a sum, so b + a and a + b give the same tree
+A subtree is only dropped or stripped of its operator by a rule when it is plain, see plain, so an
+error or a unit is kept e.g. 0 * (1/0), (1/0) ^ 0, true / 1 and 3 m - 3 m => 0 m
+Variables are taken to be plain numbers, as they are in a formula e.g. x - x => 0
+A negative number is kept as Negative(Number), which is what the parser produces for -2
+*/
+pub fn simplify(expr: Node) -> Node {
+    use self::Node::*;
+    match expr.map_children(simplify) {
+        expr @ (Add(..) | Subtract(..) | Negative(_)) => simplify_sum(expr),
+        expr @ Multiply(..) => simplify_product(expr),
+        Divide(a, b) if is_number(&b, 1) && plain(&a) => *a,
+        Divide(a, b) if a == b && plain(&a) && !is_number(&a, 0) => Number(1.0),
+        Caret(a, b) if is_number(&b, 0) && plain(&a) => Number(1.0),
+        Caret(a, b) if is_number(&b, 1) && plain(&a) => *a,
+        Caret(a, b) if is_number(&a, 1) && plain(&b) => *a,
+        BitAnd(a, b) => commutative(BitAnd, a, b),
+        BitOr(a, b) => commutative(BitOr, a, b),
+        BitXor(a, b) => commutative(BitXor, a, b),
+        Equal(a, b) => commutative(Equal, a, b),
+        NotEqual(a, b) => commutative(NotEqual, a, b),
+        Not(a) => match *a {
+            Bool(b) => Bool(!b),
+            a => Not(Box::new(a)),
+        },
+        expr => fold(&expr).unwrap_or(expr),
+    }
+}
+
+/*
+Checks whether two formulas simplify to the same canonical tree, which proves they are equal
+false does not prove they differ, as the simplifier does not know every identity e.g. it does
+not expand x * (x + 1) into x^2 + x
+*/
+pub fn equivalent(a: &Node, b: &Node) -> bool {
+    simplify(a.clone()) == simplify(b.clone())
+}
+
+/*
+Rebuilds a sum as its list of terms - coefficient * factor - with like terms added together
+e.g. x - 3 + 2*x => 3*x - 3
+*/
+fn simplify_sum(expr: Node) -> Node {
+    let mut terms = Vec::new();
+    collect_terms(expr, 1, &mut terms);
+    let mut combined: Vec<(i64, Option<Node>)> = Vec::new();
+    for (coefficient, factor) in terms {
+        let like = combined.iter().position(|(other_coefficient, other)| {
+            *other == factor
+                && combinable(&factor)
+                && fits(other_coefficient.saturating_add(coefficient))
+        });
+        match like {
+            Some(i) => combined[i].0 += coefficient,
+            None => combined.push((coefficient, factor)),
+        }
+    }
+    combined.retain(|(coefficient, factor)| {
+        *coefficient != 0 || factor.as_ref().is_some_and(|factor| !plain(factor))
+    });
+    combined.sort_by(|(_, a), (_, b)| term_order(a, b));
+
+    let mut terms = combined
+        .into_iter()
+        .map(|(coefficient, factor)| (coefficient, term(coefficient.abs(), factor)));
+    let mut sum = match terms.next() {
+        Some((coefficient, first)) if coefficient < 0 => Node::Negative(Box::new(first)),
+        Some((_, first)) => first,
+        None => return Node::Number(0.0),
+    };
+    for (coefficient, term) in terms {
+        sum = if coefficient < 0 {
+            Node::Subtract(Box::new(sum), Box::new(term))
+        } else {
+            Node::Add(Box::new(sum), Box::new(term))
+        };
+    }
+    sum
+}
+
+/* Splits a sum into (coefficient, factor) terms, with sign applied to every coefficient */
+fn collect_terms(expr: Node, sign: i64, terms: &mut Vec<(i64, Option<Node>)>) {
+    match expr {
+        Node::Add(a, b) => {
+            collect_terms(*a, sign, terms);
+            collect_terms(*b, sign, terms);
+        }
+        Node::Subtract(a, b) => {
+            collect_terms(*a, sign, terms);
+            collect_terms(*b, -sign, terms);
+        }
+        Node::Negative(a) => collect_terms(*a, -sign, terms),
+        other => {
+            let (coefficient, factor) = match (whole(&other), other) {
+                (Some(n), _) => (n, None),
+                (None, Node::Multiply(a, b)) if whole(&a).is_some() => {
+                    (whole(&a).expect("checked above"), Some(*b))
+                }
+                (None, other) => (1, Some(other)),
+            };
+            // a whole number of a unit is that many of one unit e.g. 3 m => 3 * (1 m)
+            let (coefficient, factor) = match factor {
+                Some(Node::Quantity(n, unit))
+                    if n.fract() == 0.0 && fits(coefficient.saturating_mul(n as i64)) =>
+                {
+                    (coefficient * n as i64, Some(Node::Quantity(1.0, unit)))
+                }
+                factor => (coefficient, factor),
+            };
+            terms.push((sign * coefficient, factor));
+        }
+    }
+}
+
+/*
+Terms whose factor is or starts with a decimal e.g. 0.5*x are never added together, as the
+new coefficient would need floating point arithmetic
+*/
+fn combinable(factor: &Option<Node>) -> bool {
+    match factor {
+        Some(Node::Number(_)) => false,
+        Some(Node::Multiply(a, _)) => !matches!(**a, Node::Number(_)),
+        _ => true,
+    }
+}
+
+/* coefficient * factor, where no factor means the constant term */
+fn term(coefficient: i64, factor: Option<Node>) -> Node {
+    match factor {
+        None => number(coefficient),
+        Some(Node::Quantity(1.0, unit)) => Node::Quantity(coefficient as f64, unit),
+        Some(factor) if coefficient == 1 => factor,
+        Some(factor) => Node::Multiply(Box::new(number(coefficient)), Box::new(factor)),
+    }
+}
+
+/* Terms are ordered by their text, with numbers after everything else */
+fn term_order(a: &Option<Node>, b: &Option<Node>) -> Ordering {
+    let constant = |factor: &Option<Node>| matches!(factor, None | Some(Node::Number(_)));
+    let text = |factor: &Option<Node>| factor.as_ref().map(Node::to_string);
+    (constant(a), text(a)).cmp(&(constant(b), text(b)))
+}
+
+/*
+Rebuilds a product as coefficient * factors, with the whole numbers multiplied into the
+coefficient and the powers of the same base added together e.g. 2 * x * 3 * x^2 => 6 * x^3
+The coefficient goes first, with any sign taken outside e.g. x * -2 => -(2 * x)
+*/
+fn simplify_product(expr: Node) -> Node {
+    let mut factors = Vec::new();
+    collect_factors(expr, &mut factors);
+    let mut coefficient: i64 = 1;
+    let mut decimals = Vec::new();
+    let mut powers: Vec<(Node, i64)> = Vec::new();
+    for factor in factors {
+        if let Some(n) = whole(&factor).filter(|n| fits(coefficient.saturating_mul(*n))) {
+            coefficient *= n;
+            continue;
+        }
+        let (base, exponent) = match factor {
+            Node::Number(_) => {
+                decimals.push(factor);
+                continue;
+            }
+            Node::Caret(base, exponent) if whole(&exponent).is_some() => {
+                (*base, whole(&exponent).expect("checked above"))
+            }
+            other => (other, 1),
+        };
+        let like = powers.iter().position(|(other_base, other_exponent)| {
+            *other_base == base && fits(other_exponent.saturating_add(exponent))
+        });
+        match like {
+            Some(i) => powers[i].1 += exponent,
+            None => powers.push((base, exponent)),
+        }
+    }
+    let plain_factors = decimals.iter().chain(powers.iter().map(|(base, _)| base));
+    if coefficient == 0 && plain_factors.clone().all(plain) {
+        return Node::Number(0.0);
+    }
+    decimals.sort_by_key(|decimal| decimal.to_string());
+    powers.retain(|(_, exponent)| *exponent != 0);
+    powers.sort_by_key(|(base, _)| base.to_string());
+
+    let rest = decimals
+        .into_iter()
+        .chain(powers.into_iter().map(|(base, exponent)| match exponent {
+            1 => base,
+            _ => Node::Caret(Box::new(base), Box::new(number(exponent))),
+        }))
+        .reduce(|product, factor| Node::Multiply(Box::new(product), Box::new(factor)));
+    let product = match rest {
+        None => return number(coefficient),
+        Some(rest) => term(coefficient.abs(), Some(rest)),
+    };
+    if coefficient < 0 {
+        Node::Negative(Box::new(product))
+    } else {
+        product
+    }
+}
+
+/* Splits a product into its factors, with each negative becoming a factor of -1 */
+fn collect_factors(expr: Node, factors: &mut Vec<Node>) {
+    match expr {
+        Node::Multiply(a, b) => {
+            collect_factors(*a, factors);
+            collect_factors(*b, factors);
+        }
+        Node::Negative(a) if whole(&a).is_none() => {
+            factors.push(number(-1));
+            collect_factors(*a, factors);
+        }
+        other => factors.push(other),
+    }
+}
+
+/* Rebuilds an operator whose operands can be swapped, with the operands in text order */
+fn commutative(operator: fn(Box<Node>, Box<Node>) -> Node, a: Box<Node>, b: Box<Node>) -> Node {
+    let node = if b.to_string() < a.to_string() {
+        operator(b, a)
+    } else {
+        operator(a, b)
+    };
+    fold(&node).unwrap_or(node)
+}
+
+/*
+Works out an operation on two whole numbers, when the answer is a whole number that is exact in
+every number mode
+*/
+fn fold(expr: &Node) -> Option<Node> {
+    let (_, a, b) = expr.binary()?;
+    let (a, b) = (whole(a)?, whole(b)?);
+    // division rounded down, as done by '//'
+    let floor_div = || {
+        let quotient = a.checked_div(b)?;
+        Some(if a % b != 0 && (a < 0) != (b < 0) {
+            quotient - 1
+        } else {
+            quotient
+        })
+    };
+    let result = match expr {
+        Node::Divide(..) => a.checked_div(b).filter(|quotient| quotient * b == a)?,
+        Node::FloorDivide(..) => floor_div()?,
+        Node::Modulo(..) => a - b * floor_div()?,
+        Node::Caret(..) => a.checked_pow(u32::try_from(b).ok()?)?,
+        Node::Equal(..) => return Some(Node::Bool(a == b)),
+        Node::NotEqual(..) => return Some(Node::Bool(a != b)),
+        Node::Less(..) => return Some(Node::Bool(a < b)),
+        Node::LessEqual(..) => return Some(Node::Bool(a <= b)),
+        Node::Greater(..) => return Some(Node::Bool(a > b)),
+        Node::GreaterEqual(..) => return Some(Node::Bool(a >= b)),
+        _ => return None,
+    };
+    fits(result).then(|| number(result))
+}
+
+/* Value of a whole number, including a negated one e.g. -(2) */
+fn whole(expr: &Node) -> Option<i64> {
+    match expr {
+        Node::Number(n) if n.fract() == 0.0 && n.abs() <= MAX_FOLDED as f64 => Some(*n as i64),
+        Node::Negative(inner) => whole(inner).map(|n| -n),
+        _ => None,
+    }
+}
+
+/*
+Checks whether expr is a plain number, which is when evaluating it cannot fail and it has no unit
+e.g. 2 * x ^ 2 + sin(y), while 1 / x, foo(1) and 3 m are not
+Only such a subtree can be dropped without changing what the expression means
+*/
+fn plain(expr: &Node) -> bool {
+    match expr {
+        Node::Number(n) => n.is_finite(),
+        Node::Integer(_) | Node::Variable(_) => true,
+        Node::Decimal(text) => text.parse::<f64>().is_ok_and(f64::is_finite),
+        Node::Negative(a) => plain(a),
+        Node::Add(a, b) | Node::Subtract(a, b) | Node::Multiply(a, b) => plain(a) && plain(b),
+        // a negative power of zero is a division by zero
+        Node::Caret(a, b) => plain(a) && whole(b).is_some_and(|n| n >= 0),
+        Node::Call(name, args) => {
+            TOTAL_FUNCTIONS.contains(&name.as_str())
+                && functions::lookup(name).is_some_and(|f| f.arity.accepts(args.len()))
+                && args.iter().all(plain)
+        }
+        _ => false,
+    }
+}
+
+fn is_number(expr: &Node, n: i64) -> bool {
+    whole(expr) == Some(n)
+}
+
+fn fits(n: i64) -> bool {
+    n.abs() <= MAX_FOLDED
+}
+
+/* A whole number as a node, negative numbers as Negative(Number) */
+fn number(n: i64) -> Node {
+    let magnitude = Node::Number(n.unsigned_abs() as f64);
+    if n < 0 {
+        Node::Negative(Box::new(magnitude))
+    } else {
+        magnitude
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parsemaths::parser::Parser;
+
+    fn parse(expr: &str) -> Node {
+        Parser::new(expr).unwrap().parse().unwrap()
+    }
+
+    fn simplified(expr: &str) -> String {
+        simplify(parse(expr)).to_string()
+    }
+
+    #[test]
+    fn test_identities() {
+        assert_eq!(simplified("x * 1 + 0"), "x");
+        assert_eq!(simplified("(y ^ 1) ^ 0"), "1");
+        assert_eq!(simplified("0 * sin(x) + 0 - x"), "-x");
+        assert_eq!(simplified("-(-(x / 1))"), "x");
+    }
+
+    #[test]
+    fn test_folding() {
+        assert_eq!(simplified("2 * 3 * x"), "6 * x");
+        assert_eq!(simplified("x ^ (2 - 1)"), "x");
+        assert_eq!(simplified("1 - 3"), "-2");
+        assert_eq!(simplified("2 ^ 10 < 1000"), "false");
+        // neither 1/3 nor 0.1 + 0.2 has an exact f64 value, so they are left as they are
+        assert_eq!(simplified("1 / 3"), "1 / 3");
+        assert_eq!(simplified("0.1 + 0.2"), "0.1 + 0.2");
+    }
+
+    #[test]
+    fn test_like_terms() {
+        assert_eq!(simplified("x + 2*x - y + 1"), "3 * x - y + 1");
+        assert_eq!(simplified("x - x"), "0");
+        assert_eq!(simplified("x * y * x * -2"), "-(2 * (x ^ 2 * y))");
+        assert_eq!(simplified("x^2 / x^2 * x^-2 * x^2"), "1");
+        assert_eq!(simplified("3 m + 2 m - 4 m"), "1 m");
+        assert_eq!(simplified("2 * 3 km + 1 km"), "7 km");
+        assert_eq!(simplified("0.5*x + 0.5*x"), "0.5 * x + 0.5 * x");
+    }
+
+    #[test]
+    fn test_errors_and_units_kept() {
+        assert_eq!(simplified("0 * (1/0)"), "0 * (1 / 0)");
+        assert_eq!(simplified("0*foo(1)"), "0 * foo(1)");
+        assert_eq!(simplified("sqrt(x) - sqrt(x)"), "0 * sqrt(x)");
+        assert_eq!(simplified("3 m - 3 m"), "0 m");
+        assert_eq!(simplified("0 * x^2 * sin(y)"), "0");
+        assert_eq!(simplified("x / x"), "1");
+        assert_eq!(simplified("0 / 0"), "0 / 0");
+        assert_eq!(simplified("(1/x) / (1/x)"), "1 / x / (1 / x)");
+        assert_eq!(simplified("(1/0)^0"), "(1 / 0) ^ 0");
+        assert_eq!(simplified("foo(1)^0"), "foo(1) ^ 0");
+        assert_eq!(simplified("1^foo(1)"), "1 ^ foo(1)");
+        assert_eq!(simplified("(3 m)^0"), "3 m ^ 0");
+        assert_eq!(simplified("(1/0)^1"), "(1 / 0) ^ 1");
+        assert_eq!(simplified("true / 1"), "true / 1");
+        // plain subtrees still go
+        assert_eq!(
+            simplified("(x + 1)^0 + 1^sin(x) + y^1 + y / 1"),
+            "2 * y + 2"
+        );
+    }
+
+    #[test]
+    fn test_canonical_form() {
+        assert!(equivalent(&parse("b + a*2"), &parse("2*a + b")));
+        assert!(equivalent(&parse("(a | b) == 3"), &parse("3 == (b | a)")));
+        assert!(equivalent(&parse("x*(y*x) - -y"), &parse("y + x^2*y")));
+        assert!(!equivalent(&parse("a - b"), &parse("b - a")));
+        assert!(equivalent(&parse("x / x"), &parse("1")));
+        // simplifying a canonical tree leaves it unchanged
+        for expr in [
+            "x + 2*x - y + 1",
+            "x * y * x * -2",
+            "-(a - b) * c",
+            "2 - x",
+            "0 * foo(1) + 3 m - 3 m",
+        ] {
+            let once = simplify(parse(expr));
+            assert_eq!(simplify(once.clone()), once, "{}", expr);
+        }
+    }
+}
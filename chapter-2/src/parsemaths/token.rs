@@ -1,5 +1,8 @@
 // in token.rs - providing code for the token data structures
 
+use crate::parsemaths::units::Unit;
+use std::fmt;
+
 /*
 Enum to define the precedence of the operators accepted by the parser
 Order is from Lowest to Highest
@@ -9,36 +12,192 @@ Order is from Lowest to Highest
 data structure for the OUTPUT */
 #[derive(Debug, PartialEq, Clone)]
 pub enum Token {
-    Add,        // '+'
-    Subtract,   // '-'
-    Multiply,   // '*'
-    Divide,     // '/'
-    Caret,      // '^'
-    LeftParen,  // '('
-    RightParen, // ')'
-    Num(f64),   // '1.0'
-    EoF,        // ''
-}
-
-#[derive(Debug, PartialEq, PartialOrd)]
+    Add,                   // '+'
+    Subtract,              // '-'
+    Multiply,              // '*'
+    Divide,                // '/'
+    FloorDivide,           // '//'
+    Modulo,                // '%' or 'mod'
+    Percent,               // '%' after an operand e.g. 15%
+    Caret,                 // '^'
+    Sqrt,                  // '√'
+    BitAnd,                // '&'
+    BitOr,                 // '|'
+    BitXor,                // 'xor'
+    BitNot,                // '~'
+    ShiftLeft,             // '<<'
+    ShiftRight,            // '>>'
+    Equal,                 // '=='
+    NotEqual,              // '!='
+    Less,                  // '<'
+    LessEqual,             // '<='
+    Greater,               // '>'
+    GreaterEqual,          // '>='
+    And,                   // '&&'
+    Or,                    // '||'
+    Not,                   // '!', and factorial after an operand e.g. 5!
+    Question,              // '?'
+    Colon,                 // ':'
+    LeftParen,             // '('
+    RightParen,            // ')'
+    Num(f64),              // '1.0'
+    Int(u64),              // '0xff' or '9007199254740993'
+    Decimal(String),       // '0.1234567890123456789', which an f64 would round
+    Imag(f64),             // '2.5i' or 'i'
+    Bool(bool),            // 'true' or 'false'
+    Quantity(f64, Unit),   // '9.81 m/s^2'
+    To(Option<Unit>),      // 'to km/h', with no unit if none follows
+    Ident(String),         // 'x'
+    Let,                   // 'let'
+    Assign,                // '='
+    Comma,                 // ','
+    EoF,                   // ''
+    Invalid(&'static str), // '1.2.3' or '#', with the reason it is not a token
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
 pub enum OperPrec {
     DefaultZero, // default -> lowest precedence
+    Convert,     // applied if operation is a unit conversion 'to'
+    Conditional, // applied if operation is the conditional '?'
+    Or,          // applied if operation is logical or
+    And,         // applied if operation is logical and
+    Comparison,  // applied if operation is a comparison
+    BitOr,       // applied if operation is bitwise or
+    BitXor,      // applied if operation is bitwise xor
+    BitAnd,      // applied if operation is bitwise and
+    Shift,       // applied if operation is a shift
     AddSub,      // applied if operation is add/sub
-    MulDiv,      // applied if operation is mul/div
+    MulDiv,      // applied if operation is mul/div/floor div/modulo
+    Negative,    // applied if operation is negative number, below caret so -2^2 = -(2^2)
     Power,       // applied if operation is caret
-    Negative,    // applied if operation is negative number
+    Postfix,     // applied if operation is a factorial or percentage, so -3! = -(3!) and 2^3! = 2^6
+}
+
+/*
+How a chain of operators of the same precedence is grouped
+Left: a - b - c = (a - b) - c, which is every operator except '^'
+Right: 2^3^2 = 2^(3^2) = 512
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
 }
 
 impl Token {
     pub fn get_oper_prec(&self) -> OperPrec {
-        use self::OperPrec::*;
+        use self::OperPrec::{
+            AddSub, Comparison, Conditional, DefaultZero, MulDiv, Postfix, Power, Shift,
+        };
         use self::Token::*;
 
         match *self {
+            // these operators share their names with their precedence levels
+            Token::Or => OperPrec::Or,
+            Token::And => OperPrec::And,
+            Token::BitOr => OperPrec::BitOr,
+            Token::BitXor => OperPrec::BitXor,
+            Token::BitAnd => OperPrec::BitAnd,
+            ShiftLeft | ShiftRight => Shift,
+            Equal | NotEqual | Less | LessEqual | Greater | GreaterEqual => Comparison,
+            Question => Conditional,
+            To(_) => OperPrec::Convert,
             Add | Subtract => AddSub,
-            Multiply | Divide => MulDiv,
+            Multiply | Divide | FloorDivide | Modulo => MulDiv,
             Caret => Power,
+            // only reached after an operand, where '!' is a factorial
+            Not | Percent => Postfix,
             _ => DefaultZero,
         }
     }
+
+    pub fn get_assoc(&self) -> Assoc {
+        match self {
+            Token::Caret => Assoc::Right,
+            _ => Assoc::Left,
+        }
+    }
+}
+
+/*
+Describes a token in an error message e.g. "expected ')', found end of input"
+*/
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Add => write!(f, "'+'"),
+            Token::Subtract => write!(f, "'-'"),
+            Token::Multiply => write!(f, "'*'"),
+            Token::Divide => write!(f, "'/'"),
+            Token::FloorDivide => write!(f, "'//'"),
+            Token::Modulo => write!(f, "'%'"),
+            Token::Percent => write!(f, "'%'"),
+            Token::Caret => write!(f, "'^'"),
+            Token::Sqrt => write!(f, "'√'"),
+            Token::BitAnd => write!(f, "'&'"),
+            Token::BitOr => write!(f, "'|'"),
+            Token::BitXor => write!(f, "'xor'"),
+            Token::BitNot => write!(f, "'~'"),
+            Token::ShiftLeft => write!(f, "'<<'"),
+            Token::ShiftRight => write!(f, "'>>'"),
+            Token::Equal => write!(f, "'=='"),
+            Token::NotEqual => write!(f, "'!='"),
+            Token::Less => write!(f, "'<'"),
+            Token::LessEqual => write!(f, "'<='"),
+            Token::Greater => write!(f, "'>'"),
+            Token::GreaterEqual => write!(f, "'>='"),
+            Token::And => write!(f, "'&&'"),
+            Token::Or => write!(f, "'||'"),
+            Token::Not => write!(f, "'!'"),
+            Token::Question => write!(f, "'?'"),
+            Token::Colon => write!(f, "':'"),
+            Token::LeftParen => write!(f, "'('"),
+            Token::RightParen => write!(f, "')'"),
+            Token::Num(i) => write!(f, "number {}", i),
+            Token::Int(i) => write!(f, "number {}", i),
+            Token::Decimal(text) => write!(f, "number {}", text),
+            Token::Imag(i) => write!(f, "imaginary number {}i", i),
+            Token::Bool(b) => write!(f, "'{}'", b),
+            Token::Quantity(i, unit) => write!(f, "quantity {} {}", i, unit),
+            Token::To(Some(unit)) => write!(f, "'to {}'", unit),
+            Token::To(None) => write!(f, "'to'"),
+            Token::Ident(name) => write!(f, "name '{}'", name),
+            Token::Let => write!(f, "'let'"),
+            Token::Assign => write!(f, "'='"),
+            Token::Comma => write!(f, "','"),
+            Token::EoF => write!(f, "end of input"),
+            Token::Invalid(_) => write!(f, "an invalid token"),
+        }
+    }
+}
+
+/*
+Byte offsets into the input expression, start inclusive and end exclusive
+The EoF token has an empty span positioned at the end of the input
+*/
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/* A token together with the location in the input it was read from */
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/* Allows a spanned token to be compared directly with a plain token e.g. in tests */
+impl PartialEq<Token> for SpannedToken {
+    fn eq(&self, other: &Token) -> bool {
+        &self.token == other
+    }
 }
@@ -1,14 +1,37 @@
 // in tokenizer.rs - providing code for the tokenizer functionality
 
-use crate::parsemaths::token::Token;
+use crate::parsemaths::rational::Rational;
+use crate::parsemaths::token::{Span, SpannedToken, Token};
+use crate::parsemaths::units::{self, Unit};
 use std::iter::Peekable;
-use std::str::Chars;
+use std::str::CharIndices;
+
+// 2^53 - every whole number up to this size is stored exactly by an f64
+pub const MAX_EXACT_FLOAT_INTEGER: u64 = 1 << 53;
+
+/*
+Checks whether the f64 read from decimal text lost some of its digits e.g. 0.1234567890123456789
+An f64 keeps any 15 significant digits, so only longer numbers are compared with their exact value
+*/
+fn rounded(text: &str, number: f64) -> bool {
+    let mantissa = text.split(['e', 'E']).next().unwrap_or_default();
+    let digits = mantissa
+        .chars()
+        .filter(char::is_ascii_digit)
+        .skip_while(|c| *c == '0')
+        .count();
+    digits > 15 && Rational::from_f64(number) != Rational::parse_decimal(text)
+}
 
 /* structs can hold references, but explicit lifetimes required when used
 => any reference to the Tokenizer struct cannot outlive the reference to the contained chars
 data structure for the INPUT */
 pub struct Tokenizer<'a> {
-    expr: Peekable<Chars<'a>>,
+    expr: Peekable<CharIndices<'a>>,
+    // the whole input, so units can be read by units::read_unit
+    source: &'a str,
+    // byte length of the input, used as the position of the EoF token
+    len: usize,
 }
 
 /*
@@ -21,56 +44,315 @@ impl<'a> Tokenizer<'a> {
         Tokenizer {
             /*
             new_expr: string slice
-            new_expr.chars(): iterator over string slice
-            new_expr.chars().peekable(): peekable iterator over string slice
+            new_expr.char_indices(): iterator over the chars of the string slice and their byte offsets
+            new_expr.char_indices().peekable(): peekable iterator over string slice
             */
-            expr: new_expr.chars().peekable(),
+            expr: new_expr.char_indices().peekable(),
+            source: new_expr,
+            len: new_expr.len(),
         }
     }
-}
 
-// Implements Iterator trait on the Tokenizer struct
-// Enables the use of the .peek() method to build the logic for the Tokenizer
-impl<'a> Iterator for Tokenizer<'a> {
-    type Item = Token;
+    /* Byte offset of the next unread character */
+    fn offset(&mut self) -> usize {
+        match self.expr.peek() {
+            Some((i, _)) => *i,
+            None => self.len,
+        }
+    }
 
-    fn next(&mut self) -> Option<Token> {
-        // reads the next character in the store arithmetic expression in the Tokenizer struct field
-        let next_char = self.expr.next();
+    /* Checks whether the next character is an 'i' that does not start a longer name */
+    fn imaginary_suffix(&mut self) -> bool {
+        let mut lookahead = self.expr.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some((_, 'i')), Some((_, c))) => !(c.is_alphanumeric() || c == '_'),
+            (Some((_, 'i')), None) => true,
+            _ => false,
+        }
+    }
+
+    /*
+    Reads a unit that follows, with or without a space e.g. the km/h of 2.5 km/h or the m of 2m
+    A name directly followed by '(' is a function call rather than a unit e.g. 2 min(x, 1)
+    Nothing is consumed if there is no unit
+    */
+    fn unit_after(&mut self) -> Option<Unit> {
+        let rest = &self.source[self.offset()..];
+        let start = self.offset() + rest.len() - rest.trim_start().len();
+        let (unit, len) = units::read_unit(&self.source[start..])?;
+        if self.source[start + len..].starts_with('(') {
+            return None;
+        }
+        while self.offset() < start + len {
+            self.expr.next();
+        }
+        Some(unit)
+    }
+
+    /*
+    Checks whether the next character after any whitespace starts an operand e.g. the 3 of x % 3
+    This makes a '%' the modulo operator, otherwise it is a percentage e.g. 15% or 5% - 1
+    A sign is an operator after a percentage, so a negative divisor is written 7 % (-3) or 7 mod -3,
+    and '!=' and the operator words xor, mod and to do not start an operand either
+    */
+    fn operand_follows(&mut self) -> bool {
+        let rest = self.source[self.offset()..].trim_start();
+        let word: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        match rest.chars().next() {
+            Some('!') => !rest.starts_with("!="),
+            Some(c) if c.is_alphabetic() => !matches!(word.as_str(), "xor" | "mod" | "to"),
+            Some(c) => c.is_alphanumeric() || matches!(c, '_' | '.' | '(' | '√' | '~'),
+            None => false,
+        }
+    }
+
+    /* Checks whether the '0' just read starts a radix prefix 0x, 0o or 0b */
+    fn radix_prefix(&mut self) -> Option<u32> {
+        match self.expr.peek()?.1 {
+            'x' | 'X' => Some(16),
+            'o' | 'O' => Some(8),
+            'b' | 'B' => Some(2),
+            _ => None,
+        }
+    }
+
+    /* Checks whether the next character is a '_' separating two digits of the given base */
+    fn separator_follows(&mut self, radix: u32) -> bool {
+        let mut lookahead = self.expr.clone();
+        match (lookahead.next(), lookahead.next()) {
+            (Some((_, '_')), Some((_, c))) => c.is_digit(radix),
+            _ => false,
+        }
+    }
+
+    /*
+    Skips the rest of a malformed number e.g. the _0 of 1__0 or the _.5 of 1_.5, so that the
+    Invalid token returned for it covers all of it rather than leaving a name or number behind
+    */
+    fn skip_literal(&mut self) {
+        while self
+            .expr
+            .next_if(|(_, c)| c.is_alphanumeric() || matches!(c, '_' | '.'))
+            .is_some()
+        {}
+    }
+
+    /*
+    Reads the digits of an integer after its radix prefix, skipping digit separators
+    Returns an Invalid token if there are no digits e.g. 0x, a letter or digit follows that is not
+    of the base e.g. 0xZZ or 0b12, a '_' is not between two digits or the value does not fit in
+    64 bits
+    */
+    fn read_radix_integer(&mut self, radix: u32) -> Token {
+        let mut digits = String::new();
+        while let Some(&(_, next_char)) = self.expr.peek() {
+            if next_char.is_digit(radix) {
+                digits.push(next_char);
+                self.expr.next();
+            } else if !digits.is_empty() && self.separator_follows(radix) {
+                self.expr.next();
+            } else if next_char.is_alphanumeric() || next_char == '_' {
+                self.skip_literal();
+                return Token::Invalid("is not a valid number");
+            } else {
+                break;
+            }
+        }
+        if digits.is_empty() {
+            return Token::Invalid("is not a valid number");
+        }
+        match u64::from_str_radix(&digits, radix) {
+            Ok(integer) => Token::Int(integer),
+            Err(_) => Token::Invalid("is too large for 64 bits"),
+        }
+    }
+
+    /*
+    Reads the exponent of a number e.g. the e-9 of 1e-9
+    The 'e' must be followed by digits, after an optional sign, so 2e alone is 2 followed by e
+    */
+    fn read_exponent(&mut self) -> Option<String> {
+        let mut lookahead = self.expr.clone();
+        let mut exponent = match lookahead.next()?.1 {
+            'e' | 'E' => String::from("e"),
+            _ => return None,
+        };
+        if let Some((_, sign @ ('+' | '-'))) = lookahead.peek() {
+            exponent.push(*sign);
+            lookahead.next();
+        }
+        lookahead.next_if(|(_, c)| c.is_ascii_digit())?;
+        self.expr.nth(exponent.len() - 1);
+        while let Some((_, digit)) = self.expr.next_if(|(_, c)| c.is_ascii_digit()) {
+            exponent.push(digit);
+        }
+        Some(exponent)
+    }
+
+    /*
+    Reads a decimal number that starts with c, which is a digit or the '.' of e.g. .5
+    Digit separators are skipped e.g. 1_000_000, and an exponent may follow e.g. 6.02E23
+    A number written with more than one '.' e.g. 1.2.3, or with a '_' that is not between two
+    digits e.g. 1__0, 1_ or 1._5, is an Invalid token
+    */
+    fn read_number(&mut self, c: char) -> Token {
+        let mut number = c.to_string();
+        while let Some(&(_, next_char)) = self.expr.peek() {
+            if next_char.is_ascii_digit() || next_char == '.' {
+                number.push(next_char);
+                self.expr.next();
+            } else if number.ends_with(|c: char| c.is_ascii_digit()) && self.separator_follows(10) {
+                self.expr.next();
+            } else if next_char == '_' {
+                self.skip_literal();
+                return Token::Invalid("has a '_' that is not between two digits");
+            } else {
+                break;
+            }
+        }
+        if number.matches('.').count() > 1 {
+            return Token::Invalid("has more than one decimal point");
+        }
+        let exponent = self.read_exponent();
+        // whole numbers an f64 cannot hold exactly keep every digit as an Int
+        if let (Ok(integer), None) = (number.parse::<u64>(), &exponent) {
+            if integer > MAX_EXACT_FLOAT_INTEGER && !self.imaginary_suffix() {
+                return Token::Int(integer);
+            }
+        }
+        number.push_str(exponent.as_deref().unwrap_or_default());
+        let text = number;
+        let Ok(number) = text.parse::<f64>() else {
+            return Token::Invalid("is not a valid number");
+        };
+        // a number immediately followed by a lone 'i' is imaginary e.g. 2.5i
+        if self.imaginary_suffix() {
+            self.expr.next();
+            return Token::Imag(number);
+        }
+        // a unit after a number makes a quantity e.g. 3 m or 3in, otherwise 3x is 3 * x
+        match self.unit_after() {
+            Some(unit) => Token::Quantity(number, unit),
+            None if rounded(&text, number) => Token::Decimal(text),
+            None => Token::Num(number),
+        }
+    }
+
+    /*
+    Reads an operator that may be one or two characters long e.g. '<' or '<='
+    Consumes the next character and returns long if it is second, otherwise returns short
+    */
+    fn either(&mut self, second: char, long: Token, short: Token) -> Token {
+        match self.expr.next_if(|(_, next_char)| *next_char == second) {
+            Some(_) => long,
+            None => short,
+        }
+    }
+
+    /*
+    Reads the rest of the token that starts with the character c
+    Characters that do not form a valid token give an Invalid token
+    */
+    fn read_token(&mut self, c: char) -> Token {
         // next character is evaluated via a match statement - pattern matching to return the token
-        match next_char {
-            // if a value inclusively between 0 -> 9
-            Some('0'..='9') => {
-                // mutable String variable assigned to next value in expression
-                let mut number = next_char?.to_string();
-                // while let is a loop checking an if condition
-                // if value of next_char is next char when peeking on string slice
-                while let Some(next_char) = self.expr.peek() {
-                    // if numeric or decimal, push the next value onto number
-                    if next_char.is_numeric() || next_char == &'.' {
-                        // consume the next character once known to be numeric value
-                        number.push(self.expr.next()?);
-                    } else if next_char == &'(' {
-                        return None;
+        match c {
+            // 0x, 0o and 0b start an integer written in base 16, 8 or 2
+            '0' => match self.radix_prefix() {
+                Some(radix) => {
+                    self.expr.next();
+                    self.read_radix_integer(radix)
+                }
+                None => self.read_number(c),
+            },
+            '1'..='9' => self.read_number(c),
+            '.' if matches!(self.expr.peek(), Some((_, next_char)) if next_char.is_ascii_digit()) => {
+                self.read_number(c)
+            }
+            // identifiers start with a letter or underscore and may then contain digits
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = c.to_string();
+                while let Some(&(_, next_char)) = self.expr.peek() {
+                    if next_char.is_alphanumeric() || next_char == '_' {
+                        ident.push(next_char);
+                        self.expr.next();
                     } else {
                         break;
                     }
                 }
-                Some(Token::Num(number.parse::<f64>().unwrap()))
+                match ident.as_str() {
+                    "let" => Token::Let,
+                    "xor" => Token::BitXor,
+                    "mod" => Token::Modulo,
+                    "true" => Token::Bool(true),
+                    "false" => Token::Bool(false),
+                    "i" => Token::Imag(1.0),
+                    "π" => Token::Ident("pi".into()),
+                    // 'to' converts to the unit after it, which the parser reports if it is missing
+                    "to" => Token::To(self.unit_after()),
+                    _ => Token::Ident(ident),
+                }
             }
-            Some('+') => Some(Token::Add),
-            Some('-') => Some(Token::Subtract),
-            Some('*') => Some(Token::Multiply),
-            Some('/') => Some(Token::Divide),
-            Some('^') => Some(Token::Caret),
-            Some('(') => Some(Token::LeftParen),
-            Some(')') => Some(Token::RightParen),
-            None => Some(Token::EoF),
-            _ => None,
+            '+' => Token::Add,
+            '-' | '−' => Token::Subtract,
+            '*' | '×' => Token::Multiply,
+            '/' => self.either('/', Token::FloorDivide, Token::Divide),
+            '÷' => Token::Divide,
+            '%' if self.operand_follows() => Token::Modulo,
+            '%' => Token::Percent,
+            '^' => Token::Caret,
+            '√' => Token::Sqrt,
+            '&' => self.either('&', Token::And, Token::BitAnd),
+            '|' => self.either('|', Token::Or, Token::BitOr),
+            '~' => Token::BitNot,
+            '<' => match self.either('<', Token::ShiftLeft, Token::Less) {
+                Token::Less => self.either('=', Token::LessEqual, Token::Less),
+                shift => shift,
+            },
+            '>' => match self.either('>', Token::ShiftRight, Token::Greater) {
+                Token::Greater => self.either('=', Token::GreaterEqual, Token::Greater),
+                shift => shift,
+            },
+            '!' => self.either('=', Token::NotEqual, Token::Not),
+            '?' => Token::Question,
+            ':' => Token::Colon,
+            '(' => Token::LeftParen,
+            ')' => Token::RightParen,
+            '=' => self.either('=', Token::Equal, Token::Assign),
+            ',' => Token::Comma,
+            _ => Token::Invalid("is not a valid character"),
         }
     }
 }
 
+// Implements Iterator trait on the Tokenizer struct
+// Enables the use of the .peek() method to build the logic for the Tokenizer
+// Every token is returned together with the byte span of the characters it was read from
+impl<'a> Iterator for Tokenizer<'a> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        // whitespace only separates tokens, so it is consumed before reading the next character
+        while let Some((_, next_char)) = self.expr.peek() {
+            if next_char.is_whitespace() {
+                self.expr.next();
+            } else {
+                break;
+            }
+        }
+        // reads the next character in the store arithmetic expression in the Tokenizer struct field
+        // it never runs out, as the end of the input is an EoF token however often it is read
+        let (start, token) = match self.expr.next() {
+            Some((start, c)) => (start, self.read_token(c)),
+            None => (self.len, Token::EoF),
+        };
+        let span = Span::new(start, self.offset());
+        Some(SpannedToken { token, span })
+    }
+}
+
 // Unit Tests
 #[cfg(test)]
 mod test {
@@ -89,9 +371,267 @@ mod test {
     }
 
     #[test]
-    #[ignore]
+    fn test_assignment() {
+        // the tokenizer keeps yielding EoF once the input is exhausted, so only take 5 tokens
+        let tokens: Vec<Token> = Tokenizer::new(" let total_2 = x ")
+            .take(5)
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Let,
+                Token::Ident("total_2".into()),
+                Token::Assign,
+                Token::Ident("x".into()),
+                Token::EoF
+            ]
+        )
+    }
+
+    #[test]
+    fn test_imaginary() {
+        let tokens: Vec<Token> = Tokenizer::new("2.5i + i - 3in")
+            .take(5)
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Imag(2.5),
+                Token::Add,
+                Token::Imag(1.0),
+                Token::Subtract,
+                // the i of a longer name is not imaginary, here it starts the unit inches
+                Token::Quantity(3.0, units::read_unit("in").unwrap().0)
+            ]
+        )
+    }
+
+    #[test]
+    fn test_units() {
+        let tokens: Vec<String> = Tokenizer::new("9.81 m/s^2 * 2 min(1, 2) / 3 km/x to   mph")
+            .take(11)
+            .map(|spanned| match spanned.token {
+                Token::Quantity(i, unit) => format!("{} {}", i, unit),
+                Token::To(Some(unit)) => format!("to {}", unit),
+                token => token.to_string(),
+            })
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                "9.81 m/s^2",
+                "'*'",
+                "number 2",
+                "name 'min'",
+                "'('",
+                "number 1",
+                "','",
+                "number 2",
+                "')'",
+                "'/'",
+                "3 km"
+            ]
+        );
+        let spans: Vec<Span> = Tokenizer::new("3 km/x to   mph")
+            .take(4)
+            .map(|spanned| spanned.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 4),
+                Span::new(4, 5),
+                Span::new(5, 6),
+                Span::new(7, 15)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let spans: Vec<Span> = Tokenizer::new("12 + sqrt(x)")
+            .take(7)
+            .map(|spanned| spanned.span)
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                Span::new(0, 2),
+                Span::new(3, 4),
+                Span::new(5, 9),
+                Span::new(9, 10),
+                Span::new(10, 11),
+                Span::new(11, 12),
+                Span::new(12, 12)
+            ]
+        );
+        // an unrecognised character is an Invalid token recording where it was found
+        let mut tokenizer = Tokenizer::new("1 # 2");
+        tokenizer.next();
+        let invalid = tokenizer.next().unwrap();
+        assert_eq!(invalid.token, Token::Invalid("is not a valid character"));
+        assert_eq!(invalid.span, Span::new(2, 3));
+    }
+
+    #[test]
+    fn test_integer_literals() {
+        let tokens: Vec<Token> =
+            Tokenizer::new("0xFF_FF // 0b1010 % 1_000 << 0o17 xor 9007199254740993")
+                .take(9)
+                .map(|spanned| spanned.token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Int(0xffff),
+                Token::FloorDivide,
+                Token::Int(0b1010),
+                Token::Modulo,
+                Token::Num(1000.0),
+                Token::ShiftLeft,
+                Token::Int(0o17),
+                Token::BitXor,
+                Token::Int(9007199254740993)
+            ]
+        );
+        // too large for 64 bits
+        assert_eq!(
+            Tokenizer::new("0x1_0000_0000_0000_0000").next().unwrap(),
+            Token::Invalid("is too large for 64 bits")
+        );
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let tokens: Vec<Token> = Tokenizer::new("a<=b<<1 != !c && d>e || true ? 1 : 2==x")
+            .take(19)
+            .map(|spanned| spanned.token)
+            .collect();
+        let name = |n: &str| Token::Ident(n.into());
+        assert_eq!(
+            tokens,
+            vec![
+                name("a"),
+                Token::LessEqual,
+                name("b"),
+                Token::ShiftLeft,
+                Token::Num(1.0),
+                Token::NotEqual,
+                Token::Not,
+                name("c"),
+                Token::And,
+                name("d"),
+                Token::Greater,
+                name("e"),
+                Token::Or,
+                Token::Bool(true),
+                Token::Question,
+                Token::Num(1.0),
+                Token::Colon,
+                Token::Num(2.0),
+                Token::Equal
+            ]
+        );
+    }
+
+    #[test]
     fn test_invalid_input() {
         let mut tokenizer = Tokenizer::new("#$#");
-        assert_eq!(tokenizer.next().unwrap(), Token::Num(34.5))
+        assert_eq!(
+            tokenizer.next().unwrap(),
+            Token::Invalid("is not a valid character")
+        );
+        // a malformed number is one token, rather than a panic or a number and a stray '.'
+        let tokens: Vec<SpannedToken> = Tokenizer::new("1.2.3 + 4").take(2).collect();
+        assert_eq!(
+            tokens[0].token,
+            Token::Invalid("has more than one decimal point")
+        );
+        assert_eq!(tokens[0].span, Span::new(0, 5));
+        assert_eq!(tokens[1].token, Token::Add);
+        // as is a misplaced digit separator or a radix prefix without digits of its base
+        for (expr, message, end) in [
+            ("1__0 + 4", "has a '_' that is not between two digits", 4),
+            ("1_ + 4", "has a '_' that is not between two digits", 2),
+            ("0x + 4", "is not a valid number", 2),
+            ("0xZZ + 4", "is not a valid number", 4),
+            ("0b12 + 4", "is not a valid number", 4),
+            ("0x_1 + 4", "is not a valid number", 4),
+            ("1._5 + 4", "has a '_' that is not between two digits", 4),
+            ("1_.5 + 4", "has a '_' that is not between two digits", 4),
+            ("2_e3 + 4", "has a '_' that is not between two digits", 4),
+            ("0o + 4", "is not a valid number", 2),
+            ("0b_ + 4", "is not a valid number", 3),
+        ] {
+            let tokens: Vec<SpannedToken> = Tokenizer::new(expr).take(2).collect();
+            assert_eq!(tokens[0].token, Token::Invalid(message), "{}", expr);
+            assert_eq!(tokens[0].span, Span::new(0, end), "{}", expr);
+            assert_eq!(tokens[1].token, Token::Add, "{}", expr);
+        }
+    }
+
+    #[test]
+    fn test_number_formats() {
+        let tokens: Vec<Token> =
+            Tokenizer::new("1e-9 + 6.02E23 * .5 - 2.5e+3i / 1_000.000_1 2e x 7(")
+                .take(13)
+                .map(|spanned| spanned.token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Num(1e-9),
+                Token::Add,
+                Token::Num(6.02e23),
+                Token::Multiply,
+                Token::Num(0.5),
+                Token::Subtract,
+                Token::Imag(2500.0),
+                Token::Divide,
+                Token::Num(1000.0001),
+                Token::Num(2.0),
+                Token::Ident("e".into()),
+                Token::Ident("x".into()),
+                Token::Num(7.0)
+            ]
+        );
+        // more digits than an f64 keeps are kept as text, as many as it keeps are a number
+        let tokens: Vec<Token> =
+            Tokenizer::new("0.123_456_789_012_345_678_9 0.1000000000000000000 1.5e-400")
+                .take(4)
+                .map(|spanned| spanned.token)
+                .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Decimal("0.1234567890123456789".into()),
+                Token::Num(0.1),
+                Token::Num(0.0),
+                Token::EoF
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unicode_operators() {
+        let tokens: Vec<Token> = Tokenizer::new("2 × π ÷ √x − 1")
+            .take(8)
+            .map(|spanned| spanned.token)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Num(2.0),
+                Token::Multiply,
+                Token::Ident("pi".into()),
+                Token::Divide,
+                Token::Sqrt,
+                Token::Ident("x".into()),
+                Token::Subtract,
+                Token::Num(1.0)
+            ]
+        );
     }
 }
@@ -0,0 +1,762 @@
+// in units.rs - providing code for units of measure and the quantities that carry them
+
+use crate::parsemaths::ast::EvalError;
+use crate::parsemaths::environment::{Environment, EvalPolicy};
+use crate::parsemaths::functions::Builtin;
+use crate::parsemaths::value::{check_real, real_text, Value};
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::fmt;
+
+// symbols of the SI base units, in the order of the exponents of a Dimension
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/*
+Dimension of a quantity as the exponents of the SI base units kg, m, s, A, K, mol and cd
+e.g. a force kg*m/s^2 is [1, 1, -2, 0, 0, 0, 0]
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Dimension([i8; 7]);
+
+impl Dimension {
+    pub const NONE: Dimension = Dimension([0; 7]);
+
+    pub fn is_none(self) -> bool {
+        self == Dimension::NONE
+    }
+
+    fn mul(self, other: Dimension) -> Dimension {
+        Dimension(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+
+    fn div(self, other: Dimension) -> Dimension {
+        Dimension(std::array::from_fn(|i| self.0[i] - other.0[i]))
+    }
+
+    /* The dimension to the power n - None unless every exponent stays whole e.g. m^0.5 */
+    fn pow(self, n: f64) -> Option<Dimension> {
+        let mut exponents = [0; 7];
+        for (exponent, base) in exponents.iter_mut().zip(self.0) {
+            let power = f64::from(base) * n;
+            if power.fract() != 0.0 || power.abs() > f64::from(i8::MAX) {
+                return None;
+            }
+            *exponent = power as i8;
+        }
+        Some(Dimension(exponents))
+    }
+}
+
+/*
+Writes the dimension as a named SI unit when there is one e.g. N, otherwise in base units
+e.g. m/s^2, or m^-1 when every exponent is negative
+*/
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(name) = SI_NAMES
+            .iter()
+            .find(|name| lookup(name).is_some_and(|(_, dim, _)| dim == *self))
+        {
+            return write!(f, "{}", name);
+        }
+        let power = |symbol: &str, exponent: i8| match exponent {
+            1 => symbol.to_string(),
+            _ => format!("{}^{}", symbol, exponent),
+        };
+        let numerator: Vec<String> = BASE_UNITS
+            .iter()
+            .zip(self.0)
+            .filter(|(_, exponent)| *exponent > 0)
+            .map(|(symbol, exponent)| power(symbol, exponent))
+            .collect();
+        let negative = BASE_UNITS.iter().zip(self.0).filter(|(_, e)| *e < 0);
+        if numerator.is_empty() {
+            let terms: Vec<String> = negative.map(|(symbol, e)| power(symbol, e)).collect();
+            return write!(f, "{}", terms.join("*"));
+        }
+        write!(f, "{}", numerator.join("*"))?;
+        for (symbol, exponent) in negative {
+            write!(f, "/{}", power(symbol, -exponent))?;
+        }
+        Ok(())
+    }
+}
+
+/* The SI derived units a result is shown in when it has their dimension */
+const SI_NAMES: [&str; 12] = [
+    "N", "J", "W", "Pa", "Hz", "C", "V", "Ω", "F", "T", "Wb", "H",
+];
+
+// dimensions of the table below
+const NONE: [i8; 7] = [0; 7];
+const MASS: [i8; 7] = [1, 0, 0, 0, 0, 0, 0];
+const LENGTH: [i8; 7] = [0, 1, 0, 0, 0, 0, 0];
+const TIME: [i8; 7] = [0, 0, 1, 0, 0, 0, 0];
+const CURRENT: [i8; 7] = [0, 0, 0, 1, 0, 0, 0];
+const TEMPERATURE: [i8; 7] = [0, 0, 0, 0, 1, 0, 0];
+const AMOUNT: [i8; 7] = [0, 0, 0, 0, 0, 1, 0];
+const LUMINOSITY: [i8; 7] = [0, 0, 0, 0, 0, 0, 1];
+const VOLUME: [i8; 7] = [0, 3, 0, 0, 0, 0, 0];
+const SPEED: [i8; 7] = [0, 1, -1, 0, 0, 0, 0];
+const FREQUENCY: [i8; 7] = [0, 0, -1, 0, 0, 0, 0];
+const FORCE: [i8; 7] = [1, 1, -2, 0, 0, 0, 0];
+const PRESSURE: [i8; 7] = [1, -1, -2, 0, 0, 0, 0];
+const ENERGY: [i8; 7] = [1, 2, -2, 0, 0, 0, 0];
+const POWER: [i8; 7] = [1, 2, -3, 0, 0, 0, 0];
+const CHARGE: [i8; 7] = [0, 0, 1, 1, 0, 0, 0];
+const VOLTAGE: [i8; 7] = [1, 2, -3, -1, 0, 0, 0];
+const RESISTANCE: [i8; 7] = [1, 2, -3, -2, 0, 0, 0];
+const CONDUCTANCE: [i8; 7] = [-1, -2, 3, 2, 0, 0, 0];
+const CAPACITANCE: [i8; 7] = [-1, -2, 4, 2, 0, 0, 0];
+const MAGNETIC_FLUX: [i8; 7] = [1, 2, -2, -1, 0, 0, 0];
+const FLUX_DENSITY: [i8; 7] = [1, 0, -2, -1, 0, 0, 0];
+const INDUCTANCE: [i8; 7] = [1, 2, -2, -2, 0, 0, 0];
+
+/*
+A unit that can be used on its own, with its size in SI base units
+Units that take a prefix can be written with one e.g. km, mW, µs
+*/
+struct Definition {
+    symbol: &'static str,
+    scale: f64,
+    dim: [i8; 7],
+    prefixes: bool,
+}
+
+const fn unit(symbol: &'static str, scale: f64, dim: [i8; 7], prefixes: bool) -> Definition {
+    Definition {
+        symbol,
+        scale,
+        dim,
+        prefixes,
+    }
+}
+
+static UNITS: &[Definition] = &[
+    // SI base units, with the gram taking the prefixes rather than the kilogram
+    unit("m", 1.0, LENGTH, true),
+    unit("g", 1e-3, MASS, true),
+    unit("s", 1.0, TIME, true),
+    unit("A", 1.0, CURRENT, true),
+    unit("K", 1.0, TEMPERATURE, true),
+    unit("mol", 1.0, AMOUNT, true),
+    unit("cd", 1.0, LUMINOSITY, true),
+    // SI derived units
+    unit("Hz", 1.0, FREQUENCY, true),
+    unit("N", 1.0, FORCE, true),
+    unit("Pa", 1.0, PRESSURE, true),
+    unit("J", 1.0, ENERGY, true),
+    unit("W", 1.0, POWER, true),
+    unit("C", 1.0, CHARGE, true),
+    unit("V", 1.0, VOLTAGE, true),
+    unit("Ω", 1.0, RESISTANCE, true),
+    unit("ohm", 1.0, RESISTANCE, true),
+    unit("S", 1.0, CONDUCTANCE, true),
+    unit("F", 1.0, CAPACITANCE, true),
+    unit("Wb", 1.0, MAGNETIC_FLUX, true),
+    unit("T", 1.0, FLUX_DENSITY, true),
+    unit("H", 1.0, INDUCTANCE, true),
+    // units accepted for use with the SI
+    unit("L", 1e-3, VOLUME, true),
+    unit("eV", 1.602_176_634e-19, ENERGY, true),
+    unit("Wh", 3600.0, ENERGY, true),
+    unit("bar", 1e5, PRESSURE, true),
+    unit("cal", 4.184, ENERGY, true),
+    unit("min", 60.0, TIME, false),
+    unit("h", 3600.0, TIME, false),
+    unit("d", 86400.0, TIME, false),
+    unit("t", 1000.0, MASS, false),
+    unit("rad", 1.0, NONE, false),
+    unit("deg", PI / 180.0, NONE, false),
+    // imperial and US customary units
+    unit("in", 0.0254, LENGTH, false),
+    unit("ft", 0.3048, LENGTH, false),
+    unit("yd", 0.9144, LENGTH, false),
+    unit("mi", 1609.344, LENGTH, false),
+    unit("mph", 0.44704, SPEED, false),
+    unit("kn", 1852.0 / 3600.0, SPEED, false),
+    unit("lb", 0.453_592_37, MASS, false),
+    unit("oz", 0.028_349_523_125, MASS, false),
+    unit("psi", 6_894.757_293_168_361, PRESSURE, false),
+    unit("atm", 101_325.0, PRESSURE, false),
+];
+
+static PREFIXES: &[(&str, f64)] = &[
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("µ", 1e-6),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+    ("y", 1e-24),
+];
+
+/* Power levels in decibels against a reference power in watts e.g. 0 dBm = 1 mW */
+static DECIBELS: &[(&str, f64)] = &[("dBm", 1e-3), ("dBW", 1.0)];
+
+/*
+Scale and dimension of a unit symbol, looking for a prefixed unit when the symbol is not a unit
+itself, and the reference power of a decibel unit
+*/
+fn lookup(symbol: &str) -> Option<(f64, Dimension, Option<f64>)> {
+    if let Some((_, reference)) = DECIBELS.iter().find(|(name, _)| *name == symbol) {
+        return Some((1.0, Dimension(POWER), Some(*reference)));
+    }
+    let find = |symbol: &str| UNITS.iter().find(|unit| unit.symbol == symbol);
+    if let Some(unit) = find(symbol) {
+        return Some((unit.scale, Dimension(unit.dim), None));
+    }
+    PREFIXES.iter().find_map(|(prefix, factor)| {
+        let unit = find(symbol.strip_prefix(prefix)?).filter(|unit| unit.prefixes)?;
+        Some((unit.scale * factor, Dimension(unit.dim), None))
+    })
+}
+
+/*
+A unit as written in an expression e.g. km/h, with its size in SI base units
+A decibel unit measures a power on a log scale against its reference power
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unit {
+    name: String,
+    dim: Dimension,
+    scale: f64,
+    reference: Option<f64>,
+}
+
+impl Unit {
+    /* Value in SI base units of x of this unit e.g. 2 km => 2000 */
+    fn si_value(&self, x: f64) -> f64 {
+        match self.reference {
+            Some(reference) => reference * 10f64.powf(x / 10.0),
+            None => x * self.scale,
+        }
+    }
+
+    /* The unit symbols the unit is written with e.g. km and h for km/h */
+    fn symbols(&self) -> impl Iterator<Item = &str> {
+        self.name
+            .split(['*', '/'])
+            .map(|part| part.split('^').next().unwrap_or(part))
+    }
+
+    /* Value in this unit of x SI base units */
+    fn value_in(&self, x: f64) -> f64 {
+        match self.reference {
+            Some(reference) => 10.0 * (x / reference).log10(),
+            None => x / self.scale,
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/*
+Reads the unit at the start of text, returning it with its length in bytes
+A unit is one or more unit symbols joined by '*', '/' or a space, each with an optional whole
+exponent e.g. km/h, kg*m^2/s^3, m*s^-2 or kg m/s^2, and a space is written back as '*'
+The unit ends before anything else, so in 3 m/x the '/' divides by x and in 3 m x the x is a name
+A decibel unit e.g. dBm must be on its own
+None if text does not start with a unit
+*/
+pub fn read_unit(text: &str) -> Option<(Unit, usize)> {
+    let mut unit = Unit {
+        name: String::new(),
+        dim: Dimension::NONE,
+        scale: 1.0,
+        reference: None,
+    };
+    let mut name = String::new();
+    let mut len = 0;
+    let mut divide = false;
+    loop {
+        let rest = &text[len..];
+        let symbol_len = name_len(rest);
+        let (scale, dim, reference) = lookup(&rest[..symbol_len])?;
+        let (exponent, exponent_len) = read_exponent(&rest[symbol_len..]);
+        if reference.is_some() && (len > 0 || exponent != 1) {
+            return None;
+        }
+        let (scale, dim) = (scale.powi(exponent.into()), dim.pow(exponent.into())?);
+        if divide {
+            (unit.scale, unit.dim) = (unit.scale / scale, unit.dim.div(dim));
+        } else {
+            (unit.scale, unit.dim) = (unit.scale * scale, unit.dim.mul(dim));
+        }
+        unit.reference = reference;
+        name.push_str(&rest[..symbol_len + exponent_len]);
+        len += symbol_len + exponent_len;
+        if unit.reference.is_some() {
+            break;
+        }
+        // another symbol must follow the operator for it to be part of the unit, and a symbol
+        // after a space must not be a decibel unit or a function call e.g. 2 kg min(x, 1)
+        let rest = &text[len..];
+        let spaces = rest.len() - rest.trim_start().len();
+        let (next_divide, separator_len) = match rest.chars().next() {
+            Some('*') => (false, 1),
+            Some('/') => (true, 1),
+            _ if spaces > 0 => (false, spaces),
+            _ => break,
+        };
+        let start = len + separator_len;
+        match lookup_symbol_at(text, start) {
+            Some(_) if spaces == 0 => {}
+            Some((_, _, None)) if !text[start + name_len(&text[start..])..].starts_with('(') => {}
+            _ => break,
+        }
+        name.push(if next_divide { '/' } else { '*' });
+        divide = next_divide;
+        len += separator_len;
+    }
+    unit.name = name;
+    Some((unit, len))
+}
+
+/* Checks for a unit symbol starting at byte offset start of text */
+fn lookup_symbol_at(text: &str, start: usize) -> Option<(f64, Dimension, Option<f64>)> {
+    let rest = &text[start..];
+    lookup(&rest[..name_len(rest)])
+}
+
+/* Length in bytes of the name at the start of text */
+fn name_len(text: &str) -> usize {
+    text.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(text.len())
+}
+
+/* Reads an exponent ^n or ^-n, returning 1 with no length if there is none */
+fn read_exponent(text: &str) -> (i8, usize) {
+    let Some(rest) = text.strip_prefix('^') else {
+        return (1, 0);
+    };
+    let sign = usize::from(rest.starts_with('-'));
+    let digits = rest[sign..]
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len() - sign);
+    match rest[..sign + digits].parse::<i8>() {
+        Ok(exponent) if digits > 0 => (exponent, 1 + sign + digits),
+        _ => (1, 0),
+    }
+}
+
+/*
+A number with a unit, held in SI base units e.g. 2 km is held as 2000 with the dimension of m
+unit is the unit it is shown in, None to show it in the SI units of its dimension
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    value: f64,
+    dim: Dimension,
+    unit: Option<Unit>,
+}
+
+impl Quantity {
+    /* Value in SI base units */
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.unit {
+            Some(unit) => write!(f, "{} {}", real_text(unit.value_in(self.value)), unit),
+            None => write!(f, "{} {}", real_text(self.value), self.dim),
+        }
+    }
+}
+
+/*
+Builds the value of a result - a plain Real when it has no dimension and no unit to be shown in
+e.g. 6 km / 2 m = 3000
+*/
+fn quantity(value: f64, dim: Dimension, unit: Option<Unit>) -> Value {
+    let unit = unit.filter(|unit| unit.dim == dim);
+    if dim.is_none() && unit.is_none() {
+        Value::Real(value)
+    } else {
+        Value::Quantity(Quantity { value, dim, unit })
+    }
+}
+
+/*
+Value of a literal such as 3 km
+A unit symbol that is also the name of a variable makes the literal ambiguous e.g. 3 s after
+let s = 4 could be 3 seconds or 3 * s, so it is a UnitError rather than a silent choice
+*/
+pub fn literal(x: f64, unit: &Unit, env: &Environment) -> Result<Value, EvalError> {
+    if env.int_width().is_some() {
+        return Err(EvalError::TypeError(format!(
+            "{} {} is not a whole number",
+            x, unit
+        )));
+    }
+    if let Some(symbol) = unit.symbols().find(|symbol| env.get(symbol).is_some()) {
+        return Err(EvalError::UnitError(format!(
+            "'{}' in {} {} is both a unit and a variable, write {} * {} for the variable",
+            symbol, x, unit, x, symbol
+        )));
+    }
+    let value = check_real(env.policy(), &[x], unit.si_value(x), || {
+        format!("{} {}", real_text(x), unit)
+    })?;
+    Ok(quantity(value, unit.dim, Some(unit.clone())))
+}
+
+/*
+Converts a value to a unit of the same dimension e.g. 60 mph to m/s
+The value in the new unit is checked against the policy, so under the strict policy a decibel
+unit cannot show a power of zero or less e.g. 0 W to dBm, as its log scale has no value there
+*/
+pub fn convert(val: Value, unit: &Unit, env: &Environment) -> Result<Value, EvalError> {
+    let (value, dim, _) = parts(val.clone())?;
+    if dim != unit.dim {
+        return Err(EvalError::UnitError(format!(
+            "cannot convert {} to {}",
+            describe(dim),
+            unit
+        )));
+    }
+    let describe = || format!("{} to {}", val, unit);
+    let shown = unit.value_in(value);
+    // the log of zero is a pole of the scale rather than an overflow, as for ln(0)
+    if shown.is_infinite() && value == 0.0 && env.policy() == EvalPolicy::Strict {
+        return Err(EvalError::DomainError(describe()));
+    }
+    check_real(env.policy(), &[value], shown, describe)?;
+    Ok(Value::Quantity(Quantity {
+        value,
+        dim,
+        unit: Some(unit.clone()),
+    }))
+}
+
+/*
+SI value, dimension and unit of a value, where a plain number has no dimension
+Quantities are always floating point, so they cannot be complex
+*/
+fn parts(val: Value) -> Result<(f64, Dimension, Option<Unit>), EvalError> {
+    match val {
+        Value::Quantity(q) => Ok((q.value, q.dim, q.unit)),
+        Value::Complex(_) | Value::Bool(_) => {
+            Err(EvalError::TypeError(format!("{} cannot have a unit", val)))
+        }
+        _ => Ok((val.to_f64(), Dimension::NONE, None)),
+    }
+}
+
+fn describe(dim: Dimension) -> String {
+    if dim.is_none() {
+        "a plain number".into()
+    } else {
+        dim.to_string()
+    }
+}
+
+/*
+Applies an operator to two values when either has a unit
+Adding, subtracting and the remainder need the same dimension on both sides, and the result is
+shown in the unit of the left side e.g. 1 km + 500 m = 1.5 km
+A product or quotient multiplies or divides the dimensions, keeping the unit when the other side
+is a plain number e.g. 2 * 3 km = 6 km
+*/
+pub fn operate(
+    left: Value,
+    right: Value,
+    symbol: &str,
+    env: &Environment,
+) -> Result<Value, EvalError> {
+    let plain_left = !matches!(left, Value::Quantity(_));
+    let plain_right = !matches!(right, Value::Quantity(_));
+    let ((a, dim_a, unit_a), (b, dim_b, unit_b)) = (parts(left)?, parts(right)?);
+    let same_dimension = |verb: &str| {
+        if dim_a == dim_b {
+            Ok(dim_a)
+        } else {
+            Err(EvalError::UnitError(format!(
+                "cannot {} {} and {}",
+                verb,
+                describe(dim_a),
+                describe(dim_b)
+            )))
+        }
+    };
+    let (result, dim, unit) = match symbol {
+        "+" => (a + b, same_dimension("add")?, unit_a.or(unit_b)),
+        "-" => (a - b, same_dimension("subtract")?, unit_a.or(unit_b)),
+        "%" => {
+            let remainder = a % b;
+            let result = if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+                remainder + b
+            } else {
+                remainder
+            };
+            (result, same_dimension("divide")?, unit_a)
+        }
+        "*" | "/" | "//" => {
+            let (result, dim) = match symbol {
+                "*" => (a * b, dim_a.mul(dim_b)),
+                "/" => (a / b, dim_a.div(dim_b)),
+                _ => ((a / b).floor(), dim_a.div(dim_b)),
+            };
+            let unit = match (plain_left, plain_right) {
+                (false, true) => unit_a,
+                (true, false) if symbol == "*" => unit_b,
+                _ => None,
+            };
+            (result, dim, unit)
+        }
+        _ => {
+            if !dim_b.is_none() {
+                return Err(EvalError::UnitError(format!(
+                    "the power of {} must be a plain number, found {}",
+                    describe(dim_a),
+                    dim_b
+                )));
+            }
+            let dim = dim_a.pow(b).ok_or_else(|| {
+                EvalError::UnitError(format!("{} to the power {} has no unit", dim_a, b))
+            })?;
+            (a.powf(b), dim, None)
+        }
+    };
+    let result = check_real(env.policy(), &[a, b], result, || {
+        format!(
+            "{} {} {} {} {}",
+            real_text(a),
+            describe(dim_a),
+            symbol,
+            real_text(b),
+            describe(dim_b)
+        )
+    })?;
+    Ok(quantity(result, dim, unit))
+}
+
+/* -q, which keeps the unit of q */
+pub fn negate(q: Quantity) -> Value {
+    Value::Quantity(Quantity {
+        value: -q.value,
+        ..q
+    })
+}
+
+/* Orders two values when either has a unit - they must have the same dimension */
+pub fn compare(a: &Value, b: &Value) -> Result<Option<Ordering>, EvalError> {
+    let ((a, dim_a, _), (b, dim_b, _)) = (parts(a.clone())?, parts(b.clone())?);
+    if dim_a != dim_b {
+        return Err(EvalError::UnitError(format!(
+            "cannot compare {} and {}",
+            describe(dim_a),
+            describe(dim_b)
+        )));
+    }
+    Ok(a.partial_cmp(&b))
+}
+
+/*
+Calls a built-in function when an argument has a unit
+abs, min, max and hypot need arguments of the same dimension and keep it, sqrt and cbrt take the
+root of the dimension e.g. sqrt(4 m^2) = 2 m, and every other function needs plain numbers
+*/
+pub fn call(builtin: &Builtin, values: &[Value], env: &Environment) -> Result<Value, EvalError> {
+    let mut args = Vec::with_capacity(values.len());
+    let mut dims = Vec::with_capacity(values.len());
+    let mut units = Vec::with_capacity(values.len());
+    for value in values {
+        let (x, dim, unit) = parts(value.clone())?;
+        args.push(x);
+        dims.push(dim);
+        units.push(unit);
+    }
+    let dim = match builtin.name {
+        "abs" | "min" | "max" | "hypot" if dims.iter().all(|dim| *dim == dims[0]) => Some(dims[0]),
+        "sqrt" => dims[0].pow(0.5),
+        "cbrt" => dims[0].pow(1.0 / 3.0),
+        _ if dims.iter().all(|dim| dim.is_none()) => Some(Dimension::NONE),
+        _ => None,
+    };
+    let dim = dim.ok_or_else(|| {
+        let found: Vec<String> = dims.iter().map(|dim| describe(*dim)).collect();
+        EvalError::UnitError(format!(
+            "{}() cannot take {}",
+            builtin.name,
+            found.join(", ")
+        ))
+    })?;
+    let result = check_real(env.policy(), &args, (builtin.func)(&args), || {
+        let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+        format!("{}({})", builtin.name, values.join(", "))
+    })?;
+    let unit = match builtin.name {
+        "abs" | "min" | "max" | "hypot" => units.swap_remove(0),
+        _ => None,
+    };
+    Ok(quantity(result, dim, unit))
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str) -> Option<(String, usize)> {
+        read_unit(text).map(|(unit, len)| (unit.to_string(), len))
+    }
+
+    #[test]
+    fn test_read_unit() {
+        assert_eq!(read("km/h + 1"), Some(("km/h".into(), 4)));
+        assert_eq!(read("m/s^2"), Some(("m/s^2".into(), 5)));
+        assert_eq!(read("kg*m^2/s^-3"), Some(("kg*m^2/s^-3".into(), 11)));
+        // the '/' is only part of the unit when a unit follows it
+        assert_eq!(read("m/x"), Some(("m".into(), 1)));
+        assert_eq!(read("m/2"), Some(("m".into(), 1)));
+        assert_eq!(read("dBm"), Some(("dBm".into(), 3)));
+        assert_eq!(read("dBm/s"), Some(("dBm".into(), 3)));
+        // symbols separated by spaces are multiplied, up to the first name that is not a unit
+        assert_eq!(read("kg m/s^2"), Some(("kg*m/s^2".into(), 8)));
+        assert_eq!(read("W  s + 1"), Some(("W*s".into(), 4)));
+        assert_eq!(read("m x"), Some(("m".into(), 1)));
+        assert_eq!(read("kg min(1, 2)"), Some(("kg".into(), 2)));
+        assert_eq!(read("W dBm"), Some(("W".into(), 1)));
+        assert_eq!(read("m /s"), Some(("m".into(), 1)));
+        assert_eq!(read("x"), None);
+        assert_eq!(read("m2"), None);
+        assert_eq!(read("kh"), None);
+    }
+
+    #[test]
+    fn test_lookup() {
+        let scale = |symbol: &str| lookup(symbol).map(|(scale, _, _)| scale);
+        assert_eq!(scale("km"), Some(1000.0));
+        assert_eq!(scale("mW"), Some(1e-3));
+        assert_eq!(scale("µs"), Some(1e-6));
+        assert_eq!(scale("dam"), Some(10.0));
+        // a unit of its own is found before a prefixed unit
+        assert_eq!(scale("min"), Some(60.0));
+        assert_eq!(scale("cd"), Some(1.0));
+        assert_eq!(lookup("kg").map(|(_, dim, _)| dim), Some(Dimension(MASS)));
+    }
+
+    fn eval_str(expr: &str) -> Result<String, EvalError> {
+        use crate::parsemaths::ast::eval;
+        use crate::parsemaths::parser::Parser;
+
+        let ast = Parser::new(expr).unwrap().parse().unwrap();
+        eval(ast, &mut Environment::new()).map(|val| val.to_string())
+    }
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(eval_str("1 km + 500 m").unwrap(), "1.5 km");
+        assert_eq!(eval_str("2 * 3 km").unwrap(), "6 km");
+        assert_eq!(eval_str("70 kg * 9.81 m/s^2").unwrap(), "686.7 N");
+        assert_eq!(eval_str("6 km / 2 m").unwrap(), "3000");
+        assert_eq!(eval_str("sqrt(16 m^2)").unwrap(), "4 m");
+        assert_eq!(eval_str("max(1 km, 500 m)").unwrap(), "1 km");
+        assert_eq!(eval_str("72 km/h to m/s").unwrap(), "20 m/s");
+        // a unit directly after its number is read the same as one after a space
+        assert_eq!(eval_str("2m + 3km").unwrap(), "3002 m");
+        assert_eq!(eval_str("2m^2 to cm^2").unwrap(), "20000 cm^2");
+        // units of the same dimension convert however they are written
+        assert_eq!(eval_str("1 J to W s").unwrap(), "1 W*s");
+        assert_eq!(eval_str("1 N to kg m/s^2").unwrap(), "1 kg*m/s^2");
+        assert_eq!(eval_str("3 kWh to MJ").unwrap(), "10.8 MJ");
+        assert_eq!(eval_str("10 dBm to mW").unwrap(), "10 mW");
+        assert_eq!(eval_str("1 W to dBm").unwrap(), "30 dBm");
+        // a decibel unit has no value for a power of zero or less
+        assert_eq!(
+            eval_str("0 W to dBm"),
+            Err(EvalError::DomainError("0 W to dBm".into()))
+        );
+        assert_eq!(
+            eval_str("-1 mW to dBm"),
+            Err(EvalError::DomainError("-1 mW to dBm".into()))
+        );
+        assert_eq!(eval_str("1 km > 500 m").unwrap(), "true");
+        assert_eq!(eval_str("sin(90 deg)").unwrap(), "1");
+        // a number on its own takes no unit
+        assert_eq!(
+            eval_str("5 to m"),
+            Err(EvalError::UnitError(
+                "cannot convert a plain number to m".into()
+            ))
+        );
+        assert_eq!(
+            eval_str("3 m + 2 s"),
+            Err(EvalError::UnitError("cannot add m and s".into()))
+        );
+        for expr in [
+            "60 mph to kg",
+            "1 m < 1 s",
+            "sin(2 m)",
+            "2 ^ (1 m)",
+            "sqrt(2 m)",
+        ] {
+            assert!(
+                matches!(eval_str(expr), Err(EvalError::UnitError(_))),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn test_unit_or_variable() {
+        use crate::parsemaths::ast::eval;
+        use crate::parsemaths::parser::Parser;
+
+        let mut env = Environment::new();
+        let mut eval_str = |expr: &str| {
+            let ast = Parser::new(expr).unwrap().parse().unwrap();
+            eval(ast, &mut env).map(|val| val.to_string())
+        };
+        assert_eq!(eval_str("3 s").unwrap(), "3 s");
+        eval_str("let s = 4").unwrap();
+        // with a variable s, 3 s and 3s could be either, while 3 * s is the variable
+        for expr in ["3 s", "3s"] {
+            assert_eq!(
+                eval_str(expr),
+                Err(EvalError::UnitError(
+                    "'s' in 3 s is both a unit and a variable, write 3 * s for the variable".into()
+                ))
+            );
+        }
+        assert!(matches!(eval_str("3 m/s"), Err(EvalError::UnitError(_))));
+        assert!(matches!(eval_str("3 m s"), Err(EvalError::UnitError(_))));
+        assert_eq!(eval_str("3 * s").unwrap(), "12");
+        assert_eq!(eval_str("3 m").unwrap(), "3 m");
+    }
+
+    #[test]
+    fn test_dimension_names() {
+        assert_eq!(Dimension(FORCE).to_string(), "N");
+        assert_eq!(Dimension(SPEED).to_string(), "m/s");
+        assert_eq!(
+            Dimension([1, 2, -2, 0, -1, 0, 0]).to_string(),
+            "kg*m^2/s^2/K"
+        );
+        assert_eq!(Dimension([0, -1, 0, 0, 0, 0, 0]).to_string(), "m^-1");
+        assert_eq!(Dimension(LENGTH).pow(0.5), None);
+    }
+}
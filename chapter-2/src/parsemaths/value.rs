@@ -0,0 +1,731 @@
+// in value.rs - providing code for the values produced by evaluating an AST
+
+use crate::parsemaths::ast::EvalError;
+use crate::parsemaths::bigint::BigInt;
+use crate::parsemaths::complex::Complex;
+use crate::parsemaths::environment::{Environment, EvalPolicy, IntWidth, NumberMode};
+use crate::parsemaths::rational::Rational;
+use crate::parsemaths::units::{self, Quantity};
+use std::cmp::Ordering;
+use std::fmt;
+
+/*
+Result of evaluating a node
+Real: floating point, used by the default number mode and by every real function call
+Rational: exact fraction, produced by literals in the exact number mode
+Complex: produced by imaginary literals such as 2i, or in the complex number mode by operations
+that have no real result e.g. sqrt(-1)
+Integer: whole number produced in the integer number mode, within the range of the mode's width
+(i128 holds both the i64 and the u64 range)
+Bool: result of a comparison or logical operator, which ast::eval never lets into arithmetic
+Quantity: floating point number with a unit of measure e.g. 3 km, see units.rs
+Combining a Rational with a Real gives a Real, so exactness is only kept while it can be, and
+combining anything with a Complex gives a Complex
+A Complex with a zero imaginary part is turned back into a Real, see Value::from_complex
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Real(f64),
+    Rational(Rational),
+    Complex(Complex),
+    Integer(i128),
+    Bool(bool),
+    Quantity(Quantity),
+}
+
+// exponents larger than this are computed in floating point rather than exactly
+const MAX_EXACT_EXPONENT: i64 = 10_000;
+
+impl Value {
+    /* A complex result with no imaginary part is a Real */
+    pub fn from_complex(z: Complex) -> Value {
+        if z.im == 0.0 {
+            Value::Real(z.re)
+        } else {
+            Value::Complex(z)
+        }
+    }
+
+    /* Real part as an f64 - only meaningful on its own for values that are not Complex */
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Value::Real(x) => *x,
+            Value::Rational(r) => r.to_f64(),
+            Value::Complex(z) => z.re,
+            Value::Integer(n) => *n as f64,
+            // the value in SI base units e.g. 2 km => 2000
+            Value::Quantity(q) => q.value(),
+            // not reached in practice, booleans are rejected before any arithmetic
+            Value::Bool(b) => f64::from(u8::from(*b)),
+        }
+    }
+
+    pub fn to_complex(&self) -> Complex {
+        match self {
+            Value::Complex(z) => *z,
+            _ => Complex::new(self.to_f64(), 0.0),
+        }
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Value::Complex(_))
+    }
+
+    pub fn is_quantity(&self) -> bool {
+        matches!(self, Value::Quantity(_))
+    }
+
+    /* The value as a whole number for integer operations - a TypeError if it is not one */
+    pub fn to_whole(&self) -> Result<i128, EvalError> {
+        match self {
+            Value::Integer(n) => Ok(*n),
+            // infinity and NaN have a NaN fractional part so they are rejected here too
+            Value::Real(x) if x.fract() == 0.0 => Ok(*x as i128),
+            Value::Rational(r) if r.is_integer() => r
+                .numer()
+                .to_i64()
+                .map(i128::from)
+                .ok_or_else(|| EvalError::Overflow(r.to_string())),
+            _ => Err(EvalError::TypeError(format!(
+                "{} is not a whole number",
+                self
+            ))),
+        }
+    }
+
+    /*
+    Checks two values for equality by what they are worth, so 1/4 == 0.25 is true even though one
+    is exact and the other is not
+    Booleans only equal booleans, comparing one with a number is a TypeError
+    */
+    pub fn equals(&self, other: &Value) -> Result<bool, EvalError> {
+        match (self, other) {
+            (Value::Bool(a), Value::Bool(b)) => Ok(a == b),
+            (Value::Bool(_), _) | (_, Value::Bool(_)) => Err(EvalError::TypeError(format!(
+                "cannot compare {} with {}",
+                self, other
+            ))),
+            _ if self.is_quantity() || other.is_quantity() => {
+                Ok(units::compare(self, other)? == Some(Ordering::Equal))
+            }
+            _ if self.is_complex() || other.is_complex() => {
+                Ok(self.to_complex() == other.to_complex())
+            }
+            _ => Ok(self.compare(other) == Some(Ordering::Equal)),
+        }
+    }
+
+    /*
+    Orders two numbers, exactly when both are Rational or both are Integer
+    None if either is NaN or Complex, which have no order
+    */
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            _ if self.is_complex() || other.is_complex() => None,
+            (Value::Rational(a), Value::Rational(b)) => Some(a.cmp(b)),
+            (Value::Integer(a), Value::Integer(b)) => Some(a.cmp(b)),
+            _ => self.to_f64().partial_cmp(&other.to_f64()),
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Value::Complex(z) => z.re == 0.0 && z.im == 0.0,
+            _ => self.to_f64() == 0.0,
+        }
+    }
+
+    pub fn add(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        match (&self, &other) {
+            _ if env.int_width().is_some() => integer_op(self, other, env, "+", i128::checked_add),
+            _ if self.is_quantity() || other.is_quantity() => units::operate(self, other, "+", env),
+            (Value::Rational(a), Value::Rational(b)) => Ok(Value::Rational(a.add(b))),
+            _ if self.is_complex() || other.is_complex() => {
+                complex_op(self, other, env, "+", Complex::add)
+            }
+            _ => real_op(self, other, env, "+", |a, b| a + b),
+        }
+    }
+
+    pub fn sub(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        match (&self, &other) {
+            _ if env.int_width().is_some() => integer_op(self, other, env, "-", i128::checked_sub),
+            _ if self.is_quantity() || other.is_quantity() => units::operate(self, other, "-", env),
+            (Value::Rational(a), Value::Rational(b)) => Ok(Value::Rational(a.sub(b))),
+            _ if self.is_complex() || other.is_complex() => {
+                complex_op(self, other, env, "-", Complex::sub)
+            }
+            _ => real_op(self, other, env, "-", |a, b| a - b),
+        }
+    }
+
+    pub fn mul(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        match (&self, &other) {
+            _ if env.int_width().is_some() => integer_op(self, other, env, "*", i128::checked_mul),
+            _ if self.is_quantity() || other.is_quantity() => units::operate(self, other, "*", env),
+            (Value::Rational(a), Value::Rational(b)) => Ok(Value::Rational(a.mul(b))),
+            _ if self.is_complex() || other.is_complex() => {
+                complex_op(self, other, env, "*", Complex::mul)
+            }
+            _ => real_op(self, other, env, "*", |a, b| a * b),
+        }
+    }
+
+    pub fn div(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        if other.is_zero() && (env.policy() == EvalPolicy::Strict || env.int_width().is_some()) {
+            return Err(EvalError::DivisionByZero);
+        }
+        match (&self, &other) {
+            // integer division truncates towards zero e.g. -7 / 2 = -3
+            _ if env.int_width().is_some() => integer_op(self, other, env, "/", i128::checked_div),
+            _ if self.is_quantity() || other.is_quantity() => units::operate(self, other, "/", env),
+            // a zero divisor under the IEEE policy falls through to give inf or NaN
+            (Value::Rational(a), Value::Rational(b)) if !b.is_zero() => {
+                Ok(Value::Rational(a.div(b).expect("divisor is non-zero")))
+            }
+            _ if self.is_complex() || other.is_complex() => {
+                complex_op(self, other, env, "/", Complex::div)
+            }
+            _ => real_op(self, other, env, "/", |a, b| a / b),
+        }
+    }
+
+    /*
+    Floor division - the quotient rounded towards negative infinity e.g. -7 // 2 = -4
+    Exact values stay exact
+    */
+    pub fn floor_div(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        if other.is_zero() && (env.policy() == EvalPolicy::Strict || env.int_width().is_some()) {
+            return Err(EvalError::DivisionByZero);
+        }
+        match (&self, &other) {
+            _ if env.int_width().is_some() => integer_op(self, other, env, "//", floor_div_integer),
+            _ if self.is_quantity() || other.is_quantity() => {
+                units::operate(self, other, "//", env)
+            }
+            (Value::Rational(a), Value::Rational(b)) if !b.is_zero() => Ok(Value::Rational(
+                Rational::from_integer(a.div(b).expect("divisor is non-zero").floor()),
+            )),
+            _ if self.is_complex() || other.is_complex() => Err(EvalError::TypeError(
+                "'//' is not defined for complex numbers".into(),
+            )),
+            _ => real_op(self, other, env, "//", |a, b| (a / b).floor()),
+        }
+    }
+
+    /*
+    Remainder of floor division, which has the sign of the divisor e.g. -7 % 2 = 1
+    so that a = (a // b) * b + a % b
+    */
+    pub fn modulo(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        if other.is_zero() && (env.policy() == EvalPolicy::Strict || env.int_width().is_some()) {
+            return Err(EvalError::DivisionByZero);
+        }
+        match (&self, &other) {
+            _ if env.int_width().is_some() => integer_op(self, other, env, "%", modulo_integer),
+            _ if self.is_quantity() || other.is_quantity() => units::operate(self, other, "%", env),
+            (Value::Rational(a), Value::Rational(b)) if !b.is_zero() => {
+                let quotient = a.div(b).expect("divisor is non-zero").floor();
+                Ok(Value::Rational(
+                    a.sub(&b.mul(&Rational::from_integer(quotient))),
+                ))
+            }
+            _ if self.is_complex() || other.is_complex() => Err(EvalError::TypeError(
+                "'%' is not defined for complex numbers".into(),
+            )),
+            _ => real_op(self, other, env, "%", |a, b| {
+                let remainder = a % b;
+                if remainder != 0.0 && (remainder < 0.0) != (b < 0.0) {
+                    remainder + b
+                } else {
+                    remainder
+                }
+            }),
+        }
+    }
+
+    /*
+    Bitwise operators work on whole numbers in every number mode, e.g. 6.0 & 3 is 2
+    Outside the integer mode the operands and result must fit in an i64
+    */
+    pub fn bit_and(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        integer_op(self, other, env, "&", |a, b| Some(a & b))
+    }
+
+    pub fn bit_or(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        integer_op(self, other, env, "|", |a, b| Some(a | b))
+    }
+
+    pub fn bit_xor(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        integer_op(self, other, env, "xor", |a, b| Some(a ^ b))
+    }
+
+    /* Left shift - an Overflow error if any set bit is shifted out of the width */
+    pub fn shl(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        shift_op(self, other, env, "<<", |a, n| {
+            let shifted = a.checked_shl(n)?;
+            (shifted >> n == a).then_some(shifted)
+        })
+    }
+
+    /* Arithmetic right shift - the sign bit is copied in, so -8 >> 1 = -4 */
+    pub fn shr(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        shift_op(self, other, env, ">>", |a, n| Some(a >> n.min(127)))
+    }
+
+    pub fn bit_not(self, env: &Environment) -> Result<Value, EvalError> {
+        let n = self.to_whole()?;
+        // a u64 has every bit of the width flipped, otherwise ~n = -n - 1 as in two's complement
+        let result = match env.int_width() {
+            Some(IntWidth::U64) => IntWidth::U64.max() - n,
+            _ => !n,
+        };
+        whole_result(result, env, || format!("~{}", n))
+    }
+
+    /*
+    Integer powers of exact values stay exact, anything else is a floating point power
+    In the complex number mode a negative base with a fractional exponent e.g. (-8)^(1/3) gives
+    the principal complex root instead of a domain error
+    */
+    pub fn pow(self, other: Value, env: &Environment) -> Result<Value, EvalError> {
+        // 0^-n is 1/0^n
+        if self.is_zero() && other.to_f64() < 0.0 && env.policy() == EvalPolicy::Strict {
+            return Err(EvalError::DivisionByZero);
+        }
+        if env.int_width().is_some() {
+            if other.to_f64() < 0.0 {
+                return Err(EvalError::DomainError(format!("{} ^ {}", self, other)));
+            }
+            return integer_op(self, other, env, "^", |a, b| {
+                a.checked_pow(u32::try_from(b).ok()?)
+            });
+        }
+        if self.is_quantity() || other.is_quantity() {
+            return units::operate(self, other, "^", env);
+        }
+        if let (Value::Rational(base), Value::Rational(exponent)) = (&self, &other) {
+            let small_integer = exponent
+                .numer()
+                .to_i64()
+                .filter(|n| exponent.is_integer() && n.abs() <= MAX_EXACT_EXPONENT);
+            if let Some(power) = small_integer.and_then(|n| base.pow(n)) {
+                return Ok(Value::Rational(power));
+            }
+        }
+        let negative_root = self.to_f64() < 0.0 && other.to_f64().fract() != 0.0;
+        if self.is_complex()
+            || other.is_complex()
+            || (negative_root && env.mode() == NumberMode::Complex)
+        {
+            return complex_op(self, other, env, "^", Complex::pow);
+        }
+        real_op(self, other, env, "^", f64::powf)
+    }
+
+    pub fn neg(self, env: &Environment) -> Result<Value, EvalError> {
+        if env.int_width().is_some() {
+            let n = self.to_whole()?;
+            return whole_result(-n, env, || format!("-{}", n));
+        }
+        Ok(match self {
+            Value::Real(x) => Value::Real(-x),
+            Value::Rational(r) => Value::Rational(r.neg()),
+            Value::Complex(z) => Value::Complex(z.neg()),
+            Value::Integer(n) => Value::Real(-(n as f64)),
+            Value::Quantity(q) => units::negate(q),
+            Value::Bool(b) => {
+                return Err(EvalError::TypeError(format!(
+                    "expected a number, found {}",
+                    b
+                )))
+            }
+        })
+    }
+
+    /* A percentage, x% = x / 100 - a TypeError in the integer mode, which has no fractions */
+    pub fn percent(self, env: &Environment) -> Result<Value, EvalError> {
+        if env.int_width().is_some() {
+            return Err(EvalError::TypeError(
+                "percentages are not available for integers".into(),
+            ));
+        }
+        let hundred = whole_result(100, env, || "100".into())?;
+        self.div(hundred, env)
+    }
+
+    /* Formats the value for the user according to the REPL's display options */
+    pub fn format(&self, options: &DisplayOptions) -> String {
+        match (self, options.exact, options.complex) {
+            (Value::Integer(n), _, _) => format_radix(*n, options.radix),
+            (Value::Rational(r), ExactFormat::Decimal(digits), _) => r.to_decimal(digits),
+            (Value::Complex(z), _, ComplexFormat::Polar) => z.to_polar_string(),
+            _ => self.to_string(),
+        }
+    }
+}
+
+/*
+Writes an f64 so that reading it back gives exactly the same number e.g. 2, -0.5 or 6.02e23
+Rust writes the fewest digits that do this; very large and very small numbers use an exponent, so
+1e308 is not written with all 309 digits
+*/
+pub fn real_text(x: f64) -> String {
+    if x != 0.0 && x.is_finite() && (x.abs() >= 1e16 || x.abs() < 1e-5) {
+        format!("{:e}", x)
+    } else {
+        format!("{}", x)
+    }
+}
+
+/* Applies a floating point operation to two values, checking the result against the policy */
+fn real_op(
+    left: Value,
+    right: Value,
+    env: &Environment,
+    symbol: &str,
+    op: fn(f64, f64) -> f64,
+) -> Result<Value, EvalError> {
+    let (a, b) = (left.to_f64(), right.to_f64());
+    check_real(env.policy(), &[a, b], op(a, b), || {
+        format!("{} {} {}", real_text(a), symbol, real_text(b))
+    })
+    .map(Value::Real)
+}
+
+/* Applies a whole number operation to two values - None from op means the result overflowed */
+fn integer_op(
+    left: Value,
+    right: Value,
+    env: &Environment,
+    symbol: &str,
+    op: fn(i128, i128) -> Option<i128>,
+) -> Result<Value, EvalError> {
+    let (a, b) = (left.to_whole()?, right.to_whole()?);
+    let describe = || format!("{} {} {}", a, symbol, b);
+    match op(a, b) {
+        Some(n) => whole_result(n, env, describe),
+        None => Err(EvalError::Overflow(describe())),
+    }
+}
+
+/* integer_op for the shift operators, where a negative shift amount is a domain error */
+fn shift_op(
+    left: Value,
+    right: Value,
+    env: &Environment,
+    symbol: &str,
+    op: fn(i128, u32) -> Option<i128>,
+) -> Result<Value, EvalError> {
+    let (a, b) = (left.to_whole()?, right.to_whole()?);
+    let describe = || format!("{} {} {}", a, symbol, b);
+    let amount = u32::try_from(b).map_err(|_| EvalError::DomainError(describe()))?;
+    match op(a, amount) {
+        Some(n) => whole_result(n, env, describe),
+        None => Err(EvalError::Overflow(describe())),
+    }
+}
+
+/* Integer division rounded towards negative infinity - None when dividing by zero */
+fn floor_div_integer(a: i128, b: i128) -> Option<i128> {
+    let quotient = a.checked_div(b)?;
+    // truncating rounded a negative quotient up, unless the division was exact
+    if a % b != 0 && (a < 0) != (b < 0) {
+        Some(quotient - 1)
+    } else {
+        Some(quotient)
+    }
+}
+
+/* Remainder with the sign of the divisor - None when dividing by zero */
+fn modulo_integer(a: i128, b: i128) -> Option<i128> {
+    let remainder = a.checked_rem(b)?;
+    if remainder != 0 && (remainder < 0) != (b < 0) {
+        Some(remainder + b)
+    } else {
+        Some(remainder)
+    }
+}
+
+/*
+Turns the result of a whole number operation into a Value of the current number mode
+In the integer mode it has to fit in the mode's width, the other modes use the range of an i64
+describe is only called to build the message of an Overflow or Underflow error
+*/
+pub fn whole_result(
+    n: i128,
+    env: &Environment,
+    describe: impl FnOnce() -> String,
+) -> Result<Value, EvalError> {
+    let width = env.int_width().unwrap_or(IntWidth::I64);
+    if n < width.min() {
+        return Err(EvalError::Underflow(describe()));
+    }
+    if n > width.max() {
+        return Err(EvalError::Overflow(describe()));
+    }
+    Ok(match env.mode() {
+        NumberMode::Integer(_) => Value::Integer(n),
+        NumberMode::Exact => Value::Rational(Rational::from_integer(BigInt::from_i128(n))),
+        _ => Value::Real(n as f64),
+    })
+}
+
+/*
+Writes a whole number in a base from 2 to 36, with the prefix the tokenizer reads back for bases
+2, 8 and 16 e.g. 255 in base 16 => 0xff
+Negative numbers keep their minus sign e.g. -0x1, rather than being shown in two's complement
+Other bases have no literal syntax, so the base is written after the digits e.g. 73 (base 36)
+*/
+fn format_radix(n: i128, radix: u32) -> String {
+    let mut magnitude = n.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        let digit = (magnitude % radix as u128) as u32;
+        digits.push(std::char::from_digit(digit, radix).expect("digit is below the radix"));
+        magnitude /= radix as u128;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    let digits: String = digits.iter().rev().collect();
+    let sign = if n < 0 { "-" } else { "" };
+    match radix {
+        2 => format!("{}0b{}", sign, digits),
+        8 => format!("{}0o{}", sign, digits),
+        10 => format!("{}{}", sign, digits),
+        16 => format!("{}0x{}", sign, digits),
+        _ => format!("{}{} (base {})", sign, digits, radix),
+    }
+}
+
+/* Applies a complex operation to two values, checking the result against the policy */
+fn complex_op(
+    left: Value,
+    right: Value,
+    env: &Environment,
+    symbol: &str,
+    op: fn(Complex, Complex) -> Complex,
+) -> Result<Value, EvalError> {
+    let (a, b) = (left.to_complex(), right.to_complex());
+    check_complex(env.policy(), &[a, b], op(a, b), || {
+        format!("({}) {} ({})", a, symbol, b)
+    })
+    .map(Value::from_complex)
+}
+
+/* check_real for complex numbers - a result is finite when both of its parts are */
+pub fn check_complex(
+    policy: EvalPolicy,
+    operands: &[Complex],
+    result: Complex,
+    describe: impl FnOnce() -> String,
+) -> Result<Complex, EvalError> {
+    if policy == EvalPolicy::Ieee || result.is_finite() {
+        Ok(result)
+    } else if operands.iter().any(|operand| !operand.is_finite()) {
+        Err(EvalError::NonFinite)
+    } else if result.re.is_nan() || result.im.is_nan() {
+        Err(EvalError::DomainError(describe()))
+    } else {
+        Err(EvalError::Overflow(describe()))
+    }
+}
+
+/*
+Applies the EvalPolicy to the result of a floating point operation
+Under EvalPolicy::Ieee every result is returned as it is, including infinities and NaN
+Under EvalPolicy::Strict a non-finite result is turned into the matching EvalError:
+- an operand was already infinite/NaN => NonFinite
+- NaN from finite operands e.g. (-8)^0.5 => DomainError
+- infinity from finite operands e.g. 10^400 => Overflow
+describe is only called to build the message of an error
+*/
+pub fn check_real(
+    policy: EvalPolicy,
+    operands: &[f64],
+    result: f64,
+    describe: impl FnOnce() -> String,
+) -> Result<f64, EvalError> {
+    if policy == EvalPolicy::Ieee || result.is_finite() {
+        Ok(result)
+    } else if operands.iter().any(|operand| !operand.is_finite()) {
+        Err(EvalError::NonFinite)
+    } else if result.is_nan() {
+        Err(EvalError::DomainError(describe()))
+    } else {
+        Err(EvalError::Overflow(describe()))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Real(x) => write!(f, "{}", real_text(*x)),
+            Value::Rational(r) => write!(f, "{}", r),
+            Value::Complex(z) => write!(f, "{}", z),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Quantity(q) => write!(f, "{}", q),
+        }
+    }
+}
+
+/* Lets a value be compared with a plain number e.g. assert_eq!(value, 3.75) */
+impl PartialEq<f64> for Value {
+    fn eq(&self, other: &f64) -> bool {
+        self.to_f64() == *other
+    }
+}
+
+/* How exact (Rational) results are printed - as a fraction, or as a decimal to a number of digits */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExactFormat {
+    Fraction,
+    Decimal(usize),
+}
+
+/* How complex results are printed - as re + im*i, or as modulus∠argument */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComplexFormat {
+    Rectangular,
+    Polar,
+}
+
+/*
+Output settings chosen by the user with REPL commands
+radix is the base integers are printed in, from 2 to 36
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisplayOptions {
+    pub exact: ExactFormat,
+    pub complex: ComplexFormat,
+    pub radix: u32,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        DisplayOptions {
+            exact: ExactFormat::Fraction,
+            complex: ComplexFormat::Rectangular,
+            radix: 10,
+        }
+    }
+}
+
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exact(text: &str) -> Value {
+        Value::Rational(Rational::parse_decimal(text).unwrap())
+    }
+
+    #[test]
+    fn test_exact_arithmetic() {
+        let env = Environment::new();
+        let sum = exact("0.1").add(exact("0.2"), &env).unwrap();
+        assert_eq!(sum, exact("0.3"));
+        let power = exact("2").pow(exact("-3"), &env).unwrap();
+        assert_eq!(power.to_string(), "1/8");
+        // a non-integer power or a real operand leaves exact arithmetic
+        assert_eq!(
+            exact("4").pow(exact("0.5"), &env).unwrap(),
+            Value::Real(2.0)
+        );
+        assert_eq!(
+            exact("0.5").mul(Value::Real(3.0), &env).unwrap(),
+            Value::Real(1.5)
+        );
+        assert_eq!(
+            exact("1").div(exact("0"), &env),
+            Err(EvalError::DivisionByZero)
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let third = exact("1").div(exact("3"), &Environment::new()).unwrap();
+        assert_eq!(third.format(&DisplayOptions::default()), "1/3");
+        let options = DisplayOptions {
+            exact: ExactFormat::Decimal(5),
+            complex: ComplexFormat::Polar,
+            radix: 16,
+        };
+        assert_eq!(third.format(&options), "0.33333");
+        assert_eq!(Value::Real(0.25).format(&options), "0.25");
+        assert_eq!(Value::Integer(-255).format(&options), "-0xff");
+        let z = Value::Complex(Complex::new(0.0, 2.0));
+        assert_eq!(
+            z.format(&options),
+            format!("2∠{}", std::f64::consts::FRAC_PI_2)
+        );
+        let options = DisplayOptions {
+            radix: 36,
+            ..DisplayOptions::default()
+        };
+        assert_eq!(Value::Integer(71).format(&options), "1z (base 36)");
+    }
+
+    #[test]
+    fn test_complex_arithmetic() {
+        let mut env = Environment::new();
+        let i = Value::Complex(Complex::new(0.0, 1.0));
+        // i*i has no imaginary part left, so it becomes a Real
+        assert_eq!(i.clone().mul(i.clone(), &env).unwrap(), Value::Real(-1.0));
+        let sum = Value::Real(3.0).add(i.clone(), &env).unwrap();
+        assert_eq!(sum.to_string(), "3 + i");
+        assert_eq!(
+            i.div(Value::Real(0.0), &env),
+            Err(EvalError::DivisionByZero)
+        );
+        // a negative base with a fractional power needs the complex number mode
+        assert!(Value::Real(-4.0).pow(Value::Real(0.5), &env).is_err());
+        env.set_mode(NumberMode::Complex);
+        let root = Value::Real(-4.0).pow(Value::Real(0.5), &env).unwrap();
+        assert!((root.to_complex().sub(Complex::new(0.0, 2.0))).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let mut env = Environment::new();
+        env.set_mode(NumberMode::Integer(IntWidth::I64));
+        let int = Value::Integer;
+        assert_eq!(int(-7).div(int(2), &env).unwrap(), int(-3));
+        assert_eq!(int(-7).floor_div(int(2), &env).unwrap(), int(-4));
+        assert_eq!(int(-7).modulo(int(2), &env).unwrap(), int(1));
+        assert_eq!(int(-8).shr(int(1), &env).unwrap(), int(-4));
+        assert_eq!(int(0).bit_not(&env).unwrap(), int(-1));
+        assert!(matches!(
+            int(i64::MAX as i128).add(int(1), &env),
+            Err(EvalError::Overflow(_))
+        ));
+        assert!(matches!(
+            int(1).shl(int(63), &env),
+            Err(EvalError::Overflow(_))
+        ));
+        assert!(matches!(
+            int(1).add(Value::Real(0.5), &env),
+            Err(EvalError::TypeError(_))
+        ));
+        env.set_mode(NumberMode::Integer(IntWidth::U64));
+        assert_eq!(int(0).bit_not(&env).unwrap(), int(u64::MAX as i128));
+        assert_eq!(int(1).shl(int(63), &env).unwrap(), int(1 << 63));
+        assert!(matches!(int(1).neg(&env), Err(EvalError::Underflow(_))));
+        assert_eq!(
+            int(1).sub(int(2), &env).unwrap_err().to_string(),
+            "Underflow: 1 - 2 is too small to represent"
+        );
+        // floating point modes use the floor division and remainder of the real numbers
+        env.set_mode(NumberMode::Real);
+        assert_eq!(
+            Value::Real(7.5).modulo(Value::Real(-2.0), &env).unwrap(),
+            -0.5
+        );
+        assert_eq!(
+            Value::Real(6.0).bit_and(Value::Real(3.0), &env).unwrap(),
+            2.0
+        );
+    }
+}